@@ -0,0 +1,7 @@
+-- `{users_table}` is replaced with `mysql_table` from the config file.
+CREATE TABLE IF NOT EXISTS {users_table} (
+    name       VARCHAR(255)     NOT NULL,
+    discord_id BIGINT UNSIGNED  NOT NULL PRIMARY KEY,
+    steam_id   VARCHAR(255),
+    mode       TINYINT UNSIGNED
+);
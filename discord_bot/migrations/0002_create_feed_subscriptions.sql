@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS feed_subscriptions (
+    guild_id        BIGINT UNSIGNED  NOT NULL,
+    channel_id      BIGINT UNSIGNED  NOT NULL,
+    mode            TINYINT UNSIGNED,
+    tp              BOOLEAN,
+    min_tier        TINYINT UNSIGNED,
+    pbs             BOOLEAN          NOT NULL DEFAULT FALSE,
+    registered_only BOOLEAN          NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (guild_id, channel_id)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS motw_settings (
+    guild_id   BIGINT UNSIGNED   NOT NULL PRIMARY KEY,
+    channel_id BIGINT UNSIGNED   NOT NULL,
+    mode       TINYINT UNSIGNED  NOT NULL,
+    min_tier   TINYINT UNSIGNED,
+    max_tier   TINYINT UNSIGNED,
+    days       TINYINT UNSIGNED  NOT NULL DEFAULT 7
+);
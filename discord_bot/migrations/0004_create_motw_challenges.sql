@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS motw_challenges (
+    id         INT UNSIGNED       NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    guild_id   BIGINT UNSIGNED    NOT NULL,
+    map_id     SMALLINT UNSIGNED  NOT NULL,
+    map_name   VARCHAR(255)       NOT NULL,
+    map_tier   TINYINT UNSIGNED   NOT NULL,
+    mode       TINYINT UNSIGNED   NOT NULL,
+    started_at BIGINT             NOT NULL,
+    ends_at    BIGINT             NOT NULL,
+    finished   BOOLEAN            NOT NULL DEFAULT FALSE,
+    INDEX (guild_id)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS motw_entries (
+    challenge_id INT UNSIGNED     NOT NULL,
+    discord_id   BIGINT UNSIGNED  NOT NULL,
+    steam_id     VARCHAR(255)     NOT NULL,
+    name         VARCHAR(255)     NOT NULL,
+    tp_time      DOUBLE,
+    tp_teleports INT UNSIGNED,
+    pro_time     DOUBLE,
+    PRIMARY KEY (challenge_id, discord_id)
+);
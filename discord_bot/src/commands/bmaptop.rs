@@ -51,7 +51,8 @@ pub async fn bmaptop(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let map = ctx.get_map(map_choice)?;
@@ -2,12 +2,11 @@ use {
 	super::{autocompletion::autocomplete_map, choices::ModeChoice},
 	crate::{
 		error::{Error, Result},
-		gokz::format_replay_links,
+		gokz::{format_record, format_replay_links},
 		target::Target,
 		Context, State,
 	},
-	gokz_rs::{global_api, MapIdentifier},
-	schnosebot::formatting::fmt_time,
+	schnosebot::commands,
 };
 
 /// A player's personal best on a bonus course.
@@ -22,6 +21,8 @@ use {
 /// - `player`: this can be any string. The bot will try its best to interpret it as something \
 ///   useful. If you want to help it with that, specify one of the following:
 ///   - a `SteamID`, e.g. `STEAM_1:1:161178172`, `U:1:322356345` or `76561198282622073`
+///   - a Steam profile link, e.g. `https://steamcommunity.com/id/AlphaKeks` or \
+///     `https://steamcommunity.com/profiles/76561198282622073`
 ///   - a `Mention`, e.g. `@MyBestFriend`
 ///   - a player's name, e.g. `AlphaKeks`
 ///   - If you don't specify this, the bot will search the database for your UserID. If it can't \
@@ -53,86 +54,33 @@ pub async fn bpb(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let map = ctx.get_map(map_choice)?;
-	let map_identifier = MapIdentifier::Name(map.name);
 	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
 	let player_identifier = Target::parse_input(target, db_entry, &ctx).await?;
 	let course = course_choice.unwrap_or(1).max(1);
 
-	let tp = global_api::get_pb(
-		player_identifier.clone(),
-		map_identifier.clone(),
-		mode,
-		true,
-		course,
-		ctx.gokz_client(),
-	)
-	.await;
-	let pro = global_api::get_pb(
-		player_identifier.clone(),
-		map_identifier.clone(),
-		mode,
-		false,
-		course,
-		ctx.gokz_client(),
-	)
-	.await;
-
-	let mut player_name = String::from("unknown");
-
-	let (tp_time, tp_links) = if let Ok(tp) = &tp {
-		player_name = tp.player_name.to_owned();
-
-		let place = global_api::get_place(tp.id, ctx.gokz_client())
-			.await
-			.map(|place| format!("[#{place}]"))
-			.unwrap_or_default();
-
-		(
-			format!(
-				"{} {} ({} TP{})",
-				fmt_time(tp.time),
-				place,
-				tp.teleports,
-				if tp.teleports > 1 { "s" } else { "" }
-			),
-			Some((tp.replay_download_link(), tp.replay_view_link())),
-		)
-	} else {
-		(String::from("😔"), None)
-	};
-
-	let (pro_time, pro_links) = if let Ok(pro) = &pro {
-		player_name = pro.player_name.to_owned();
-
-		let place = global_api::get_place(pro.id, ctx.gokz_client())
-			.await
-			.map(|place| format!("[#{place}]"))
-			.unwrap_or_default();
-
-		(
-			format!("{} {}", fmt_time(pro.time), place),
-			Some((pro.replay_download_link(), pro.replay_view_link())),
-		)
-	} else {
-		(String::from("😔"), None)
-	};
+	let pbs = commands::pb::execute(map, player_identifier, mode, course, ctx.gokz_client()).await;
+	let player_name = pbs
+		.player_name()
+		.unwrap_or("unknown")
+		.to_owned();
 
 	ctx.send(|replay| {
 		replay.embed(|e| {
 			e.color(ctx.color())
 				.title(format!(
 					"[PB] {} on {} B{} (T{})",
-					player_name, map_identifier, course, map.tier as u8
+					player_name, &pbs.map.name, course, pbs.map.tier as u8
 				))
-				.url(format!("{}?{}=&bonus={}", &map.url, mode.short().to_lowercase(), course))
-				.thumbnail(&map.thumbnail)
-				.description(format_replay_links(tp_links, pro_links).unwrap_or_default())
-				.field("TP", tp_time, true)
-				.field("PRO", pro_time, true)
+				.url(pbs.map_url())
+				.thumbnail(&pbs.map.thumbnail)
+				.description(format_replay_links(&pbs).unwrap_or_default())
+				.field("TP", format_record(&pbs.tp, false), true)
+				.field("PRO", format_record(&pbs.pro, false), true)
 				.footer(|f| {
 					f.text(format!("Mode: {mode}"))
 						.icon_url(ctx.icon())
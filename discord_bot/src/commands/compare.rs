@@ -0,0 +1,206 @@
+use {
+	super::{
+		choices::{ModeChoice, RuntypeChoice},
+		pagination::paginate,
+	},
+	crate::{
+		error::{Error, Result},
+		target::Target,
+		Context, State,
+	},
+	num_format::{Locale, ToFormattedString},
+	poise::serenity_prelude::CreateEmbed,
+	schnosebot::{
+		commands::{self, compare::SharedMap},
+		formatting::{fmt_time, time::TimeFormat},
+		global_maps::GlobalMap,
+	},
+};
+
+/// Compare two players head-to-head.
+///
+/// This command will fetch the personal bests of two players and compare them. It shows on \
+/// which maps each player is faster and by how much, which maps only one of them has finished \
+/// and how many points they have per tier. Only global maps are taken into account. You may \
+/// specify the following parameters:
+///
+/// - `player1` / `player2`: this can be any string. The bot will try its best to interpret it as \
+///   something useful. If you want to help it with that, specify one of the following:
+///   - a `SteamID`, e.g. `STEAM_1:1:161178172`, `U:1:322356345` or `76561198282622073`
+///   - a Steam profile link, e.g. `https://steamcommunity.com/id/AlphaKeks` or \
+///     `https://steamcommunity.com/profiles/76561198282622073`
+///   - a `Mention`, e.g. `@MyBestFriend`
+///   - a player's name, e.g. `AlphaKeks`
+///   - If you don't specify `player2`, the bot will search the database for your UserID. If it \
+///     can't find one, or you don't have a SteamID set, the command will fail. To save a SteamID \
+///     in the database, see `/setsteam`.
+/// - `mode`: `KZTimer` / `SimpleKZ` / `Vanilla`
+///   - If you don't specify this, the bot will search the database for your UserID. If it can't \
+///   find one, or you don't have a mode preference set, the command will fail. To save a mode \
+///   preference in the database, see `/mode`.
+/// - `runtype`: `TP` / `PRO`
+///   - If you don't specify this, the bot will default to `PRO`.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, on_error = "Error::handle_command")]
+pub async fn compare(
+	ctx: Context<'_>,
+
+	#[description = "The player you want to compare."]
+	#[rename = "player1"]
+	first_target: String,
+
+	#[description = "The player you want to compare them to. (defaults to you)"]
+	#[rename = "player2"]
+	second_target: Option<String>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	ctx.defer().await?;
+
+	let db_entry = ctx
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
+		.await;
+
+	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
+	let runtype = matches!(runtype_choice, Some(RuntypeChoice::TP));
+	let first = Target::parse_input(Some(first_target), db_entry.clone(), &ctx).await?;
+	let second = Target::parse_input(second_target, db_entry, &ctx).await?;
+
+	let comparison = commands::compare::execute(
+		first,
+		second,
+		mode,
+		runtype,
+		&ctx.global_maps(),
+		ctx.gokz_client(),
+	)
+	.await?;
+
+	let (first_name, second_name) = &comparison.players;
+	let (first_wins, second_wins) = comparison.wins();
+	let ties = comparison.shared.len() - first_wins - second_wins;
+
+	let template = CreateEmbed::default()
+		.color(ctx.color())
+		.title(format!(
+			"{first_name} vs. {second_name} ({} {})",
+			mode.short(),
+			if runtype { "TP" } else { "PRO" }
+		))
+		.to_owned();
+
+	let mut pages = Vec::new();
+
+	let mut overview = template.clone();
+	overview
+		.field("Shared maps", comparison.shared.len(), true)
+		.field(
+			"Faster on",
+			format!("{first_name}: {first_wins}\n{second_name}: {second_wins}\nTies: {ties}"),
+			true,
+		)
+		.field(
+			"Only finished by",
+			format!(
+				"{first_name}: {}\n{second_name}: {}",
+				comparison.only_first.len(),
+				comparison.only_second.len()
+			),
+			true,
+		)
+		.field("Points", format_points(&comparison.points, first_name, second_name), false);
+	pages.push(overview);
+
+	for chunk in comparison.shared.chunks(10) {
+		let mut page = template.clone();
+		page.description(
+			chunk
+				.iter()
+				.map(|map| format_shared_map(map, first_name, second_name))
+				.collect::<Vec<_>>()
+				.join("\n"),
+		);
+		pages.push(page);
+	}
+
+	for (player_name, maps) in [
+		(first_name, &comparison.only_first),
+		(second_name, &comparison.only_second),
+	] {
+		for chunk in maps.chunks(20) {
+			let mut page = template.clone();
+			page.description(format!(
+				"Only finished by **{player_name}**:\n{}",
+				format_map_list(chunk)
+			));
+			pages.push(page);
+		}
+	}
+
+	let page_count = pages.len();
+	for (i, page) in pages.iter_mut().enumerate() {
+		page.footer(|f| {
+			f.text(format!("Page {} / {page_count}", i + 1))
+				.icon_url(ctx.icon())
+		});
+	}
+
+	paginate(&ctx, pages).await?;
+
+	Ok(())
+}
+
+/// `kz_lionharder (T7): 12:07.727 vs. 12:08.000 -> AlphaKeks (-0.273)`
+fn format_shared_map(map: &SharedMap, first_name: &str, second_name: &str) -> String {
+	let difference = map.difference();
+	let winner = if difference < 0.0 {
+		format!("**{first_name}**")
+	} else if difference > 0.0 {
+		format!("**{second_name}**")
+	} else {
+		String::from("tie")
+	};
+
+	format!(
+		"`{}` (T{}): {} vs. {} -> {winner} ({})",
+		map.name,
+		map.tier as u8,
+		fmt_time(map.times.0),
+		fmt_time(map.times.1),
+		TimeFormat::default().format_delta(difference)
+	)
+}
+
+fn format_map_list(maps: &[GlobalMap]) -> String {
+	maps.iter()
+		.map(|map| format!("`{}` (T{})", map.name, map.tier as u8))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+fn format_points(points: &[(u32, u32); 8], first_name: &str, second_name: &str) -> String {
+	let rows = points
+		.iter()
+		.enumerate()
+		.skip(1)
+		.chain(std::iter::once((0, &points[0])))
+		.map(|(tier, (first, second))| {
+			let tier = if tier == 0 { String::from("Total") } else { format!("T{tier}") };
+			format!(
+				"{tier:<6} {:>10} {:>10}",
+				first.to_formatted_string(&Locale::en),
+				second.to_formatted_string(&Locale::en)
+			)
+		})
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	format!("```\n{:<6} {first_name:>10.10} {second_name:>10.10}\n{rows}\n```", "")
+}
@@ -28,7 +28,8 @@ pub async fn db(
 	}
 
 	let User { name, discord_id, steam_id, mode } = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await?;
 
 	let steam_id = steam_id
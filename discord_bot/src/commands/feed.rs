@@ -0,0 +1,153 @@
+use {
+	super::choices::{ModeChoice, RuntypeChoice, TierChoice},
+	crate::{
+		error::{Error, Result},
+		feed::{self, Subscription},
+		Context, State,
+	},
+	gokz_rs::{Mode, Tier},
+	poise::serenity_prelude::GuildChannel,
+};
+
+/// Announce new records in a channel.
+///
+/// This command lets you subscribe a channel to new world records. Use one of the subcommands:
+///
+/// - `/feed subscribe`: subscribe a channel (or change an existing subscription)
+/// - `/feed unsubscribe`: stop announcing records in a channel
+/// - `/feed list`: list all subscriptions on this server
+#[tracing::instrument(skip(_ctx))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	subcommands("subscribe", "unsubscribe", "list"),
+	on_error = "Error::handle_command"
+)]
+pub async fn feed(_ctx: Context<'_>) -> Result<()> {
+	Ok(())
+}
+
+/// Subscribe a channel to new records.
+///
+/// The bot will post every new world record matching your filters in this channel. You may \
+/// specify the following parameters:
+///
+/// - `channel`: the channel to post in. If you don't specify this, the current channel is used.
+/// - `mode`: `KZTimer` / `SimpleKZ` / `Vanilla`
+///   - If you don't specify this, records in all modes are posted.
+/// - `runtype`: `TP` / `PRO`
+///   - If you don't specify this, both TP and PRO records are posted.
+/// - `min_tier`: only post records on maps with at least this tier.
+/// - `pbs`: also post personal bests of users who saved their SteamID with `/setsteam`.
+/// - `registered_only`: only post records of users who saved their SteamID with `/setsteam`.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	required_permissions = "MANAGE_CHANNELS",
+	on_error = "Error::handle_command"
+)]
+pub async fn subscribe(
+	ctx: Context<'_>,
+
+	#[description = "The channel to post records in."] channel: Option<GuildChannel>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+
+	#[description = "Only post records on maps with at least this tier."]
+	#[rename = "min_tier"]
+	tier_choice: Option<TierChoice>,
+
+	#[description = "Also post PBs of registered users."] pbs: Option<bool>,
+
+	#[description = "Only post records of registered users."] registered_only: Option<bool>,
+) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+	let subscription = Subscription {
+		guild_id: *guild_id.as_u64(),
+		channel_id: *channel_id.as_u64(),
+		mode: mode_choice.map(Mode::from),
+		tp: runtype_choice.map(bool::from),
+		min_tier: tier_choice.map(Tier::from),
+		pbs: pbs.unwrap_or(false),
+		registered_only: registered_only.unwrap_or(false),
+	};
+
+	feed::subscribe(ctx.database(), &subscription).await?;
+
+	ctx.say(format!("Subscribed {subscription}"))
+		.await?;
+
+	Ok(())
+}
+
+/// Stop announcing new records in a channel.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	required_permissions = "MANAGE_CHANNELS",
+	on_error = "Error::handle_command"
+)]
+pub async fn unsubscribe(
+	ctx: Context<'_>,
+	#[description = "The channel to stop posting records in."] channel: Option<GuildChannel>,
+) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+
+	let content =
+		if feed::unsubscribe(ctx.database(), *guild_id.as_u64(), *channel_id.as_u64()).await? {
+			format!("<#{channel_id}> will no longer receive new records.")
+		} else {
+			format!("<#{channel_id}> is not subscribed.")
+		};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
+
+/// List all channels on this server that receive new records.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn list(ctx: Context<'_>) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let subscriptions = feed::guild_subscriptions(ctx.database(), *guild_id.as_u64()).await?;
+
+	let content = if subscriptions.is_empty() {
+		String::from("No channels on this server are subscribed. See `/feed subscribe`.")
+	} else {
+		subscriptions
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join("\n")
+	};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
@@ -2,8 +2,11 @@ use {
 	super::autocompletion::autocomplete_map,
 	crate::{
 		error::{Error, Result},
+		gokz::format_record,
 		Context, State,
 	},
+	gokz_rs::Mode,
+	schnosebot::commands::{self, map::ModeStats},
 };
 
 /// Get detailed information on a map.
@@ -14,6 +17,10 @@ use {
 /// [n4vyn's](https://github.com/n4vyn) [KZ:GO API](https://kzgo.eu/) and my own \
 /// [SchnoseAPI](https://github.com/AlphaKeks/SchnoseAPI). If anything seems incorrect, feel free \
 /// to report it.
+///
+/// Besides the map's tier, mapper and bonuses you get the amount of completions and the current \
+/// world records in every mode. If you saved your SteamID with `/setsteam`, your personal bests \
+/// are shown as well, in the mode you set with `/mode`.
 #[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
 #[poise::command(slash_command, on_error = "Error::handle_command")]
 pub async fn map(
@@ -25,45 +32,141 @@ pub async fn map(
 ) -> Result<()> {
 	ctx.defer().await?;
 
-	let map = ctx.get_map(map_choice)?;
+	let global_map = ctx.get_map(map_choice)?;
+
+	// Links and PBs are shown for the user's favorite mode, or the first mode with a filter.
+	let user = ctx
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
+		.await
+		.ok();
+	let mode = user
+		.as_ref()
+		.and_then(|user| user.mode)
+		.or_else(|| {
+			[
+				(Mode::KZTimer, global_map.kzt),
+				(Mode::SimpleKZ, global_map.skz),
+				(Mode::Vanilla, global_map.vnl),
+			]
+			.into_iter()
+			.find(|(_, has_filter)| *has_filter)
+			.map(|(mode, _)| mode)
+		})
+		.unwrap_or(Mode::KZTimer);
+	let player = user
+		.and_then(|user| user.steam_id)
+		.map(|steam_id| (steam_id.into(), mode));
+
+	let details =
+		commands::map::details(global_map, player, ctx.completions_cache(), ctx.gokz_client())
+			.await;
+	let info = &details.info;
 
-	let mapper = if let Some(steam_id) = map.mapper_steam_id {
-		format!("[{}](https://steamcommunity.com/profiles/{})", map.mapper_name, steam_id.as_id64())
+	let mapper = if let Some(mapper_url) = &info.mapper_url {
+		format!("[{}]({})", info.mapper_name, mapper_url)
 	} else {
-		map.mapper_name
+		info.mapper_name.clone()
 	};
 
-	let kzt_filter = if map.kzt { "✅" } else { "❌" };
-	let skz_filter = if map.skz { "✅" } else { "❌" };
-	let vnl_filter = if map.vnl { "✅" } else { "❌" };
+	let bonuses = if details.bonuses.is_empty() {
+		String::from("0")
+	} else {
+		format!(
+			"{} ({})",
+			details.bonuses.len(),
+			details
+				.bonuses
+				.iter()
+				.map(|bonus| match bonus.tier {
+					Some(tier) => format!("B{}: T{}", bonus.course, tier as u8),
+					None => format!("B{}: ?", bonus.course),
+				})
+				.collect::<Vec<_>>()
+				.join(", ")
+		)
+	};
+
+	let links = std::iter::once(format!("[Main]({}?{}=)", info.url, mode.short().to_lowercase()))
+		.chain(
+			details
+				.bonuses
+				.iter()
+				.map(|bonus| format!("[B{}]({})", bonus.course, bonus.url(info, mode))),
+		)
+		.collect::<Vec<_>>()
+		.join(" | ");
+
+	let created_on = info.created_on.and_utc().timestamp();
+	let updated_on = info.updated_on.and_utc().timestamp();
 
 	ctx.send(|reply| {
 		reply.embed(|e| {
 			e.color(ctx.color())
-				.title(&map.name)
-				.url(&map.url)
-				.thumbnail(&map.thumbnail)
+				.title(&info.name)
+				.url(&info.url)
+				.thumbnail(&info.thumbnail)
 				.description(format!(
 					"
 🢂 Tier: {} ({})
 🢂 Mapper(s): {}
 🢂 Bonuses: {}
-🢂 Last Updated: {}
-
-🢂 Filters:
+🢂 Created: <t:{created_on}:D> (<t:{created_on}:R>)
+🢂 Last Updated: <t:{updated_on}:D> (<t:{updated_on}:R>)
+🢂 KZ:GO ({}): {}
 				",
-					map.tier as u8,
-					map.tier,
+					info.tier as u8,
+					info.tier,
 					mapper,
-					map.courses.len() - 1,
-					map.updated_on.format("%d/%m/%Y")
+					bonuses,
+					mode.short(),
+					links,
 				))
-				.field("KZT", kzt_filter, true)
-				.field("SKZ", skz_filter, true)
-				.field("VNL", vnl_filter, true)
+				.fields(info.filters.map(|(mode, has_filter)| {
+					let stats = details
+						.modes
+						.iter()
+						.find(|stats| stats.mode == mode);
+
+					let value = match (has_filter, stats) {
+						(true, Some(stats)) => format_mode_stats(stats),
+						(true, None) if details.unavailable.contains(&mode) => {
+							String::from("✅ (stats unavailable)")
+						}
+						(true, None) => String::from("✅"),
+						(false, _) => String::from("❌"),
+					};
+
+					(mode.short(), value, true)
+				}));
+
+			if let Some(pb) = &details.pb {
+				e.field(
+					format!("Your PBs ({})", pb.mode.short()),
+					format!(
+						"TP: {}\nPRO: {}",
+						format_record(&pb.tp, false),
+						format_record(&pb.pro, false)
+					),
+					false,
+				);
+			}
+
+			e
 		})
 	})
 	.await?;
 
 	Ok(())
 }
+
+/// Completions and WRs of a single mode for an embed field.
+fn format_mode_stats(stats: &ModeStats) -> String {
+	format!(
+		"✅ {} TP / {} PRO completions\n**TP WR**: {}\n**PRO WR**: {}",
+		ModeStats::fmt_completions(stats.tp_completions),
+		ModeStats::fmt_completions(stats.pro_completions),
+		format_record(&stats.tp_wr, true),
+		format_record(&stats.pro_wr, true),
+	)
+}
@@ -13,9 +13,15 @@ pub use btop::btop;
 mod bwr;
 pub use bwr::bwr;
 
+mod compare;
+pub use compare::compare;
+
 mod db;
 pub use db::db;
 
+mod feed;
+pub use feed::feed;
+
 mod help;
 pub use help::help;
 
@@ -31,6 +37,9 @@ pub use maptop::maptop;
 mod mode;
 pub use mode::mode;
 
+mod motw;
+pub use motw::motw;
+
 mod nocrouch;
 pub use nocrouch::nocrouch;
 
@@ -61,6 +70,9 @@ pub use report::report;
 mod restart;
 pub use restart::restart;
 
+mod serverlb;
+pub use serverlb::serverlb;
+
 mod setsteam;
 pub use setsteam::setsteam;
 
@@ -74,39 +86,26 @@ mod wr;
 pub use wr::wr;
 
 mod autocompletion {
-	use {
-		crate::{Context, State},
-		fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
-	};
+	use crate::{Context, State};
 
-	// Provides autocompletion for map names on certain commands using some fuzzy finding algorithm
-	// I found on the interent. :)
+	// Provides autocompletion for map names on certain commands. Exact and prefix matches are
+	// listed before fuzzy ones.
 	#[tracing::instrument(skip(ctx))]
 	pub async fn autocomplete_map<'a>(
 		ctx: Context<'a>,
 		input: &'a str,
 	) -> impl futures::Stream<Item = String> + 'a {
-		let fzf = SkimMatcherV2::default();
-		let input = input.to_lowercase();
-		let mut map_names = ctx
-			.global_map_names()
-			.iter()
-			.filter_map(|name| {
-				let score = fzf.fuzzy_match(name, &input)?;
-				if score > 0 || input.is_empty() {
-					return Some((score, name.clone()));
-				}
-				None
-			})
-			.collect::<Vec<_>>();
-
-		map_names.sort_unstable_by(|(a_score, _), (b_score, _)| b_score.cmp(a_score));
-
-		futures::stream::iter(
-			map_names
+		let map_names = if input.trim().is_empty() {
+			ctx.global_map_names()
+		} else {
+			ctx.map_search()
+				.search(input, &ctx.global_maps())
 				.into_iter()
-				.map(|(_, map_name)| map_name),
-		)
+				.map(|candidate| candidate.map.name.clone())
+				.collect()
+		};
+
+		futures::stream::iter(map_names)
 	}
 }
 
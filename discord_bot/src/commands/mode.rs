@@ -1,11 +1,11 @@
 use {
 	super::choices::DBModeChoice,
 	crate::{
+		db::UserRepository,
 		error::{Error, Result},
 		Context, State,
 	},
 	gokz_rs::Mode,
-	sqlx::QueryBuilder,
 };
 
 /// Set your mode preference.
@@ -26,89 +26,108 @@ pub async fn mode(
 	ctx.defer().await?;
 
 	let mode = Mode::try_from(mode_choice);
-	let mode_id = mode
-		.as_ref()
-		.map(|mode| (*mode as u8).to_string())
-		.ok();
 
 	let (name, id) = {
 		let author = ctx.author();
 		(&author.name, *author.id.as_u64())
 	};
 
-	let table = &ctx.config().mysql_table;
-
-	let updated = match ctx.find_user_by_id(id).await {
-		// User already has a database entry => modify current one
-		Ok(user) => {
-			// :tf:
-			if user.mode.as_ref() == mode.as_ref().ok() {
-				ctx.say("You already have this mode set.")
-					.await?;
-				return Ok(());
-			}
-
-			let mut query = QueryBuilder::new(format!(r#"UPDATE {table} SET mode = "#));
-
-			query
-				.push_bind(mode_id)
-				.push(" WHERE discord_id = ")
-				.push_bind(id);
-
-			query
-				.build()
-				.execute(ctx.database())
-				.await?;
-
-			true
-		}
-		// We failed to get the user's database entry. Why?
-		Err(why) => match why {
-			// This is not supposed to happen! Return with an error.
-			why @ (Error::DatabaseAccess | Error::DatabaseUpdate) => return Err(why),
-			// The user simply has no entry yet => create a new one
-			_ => {
-				if mode.is_err() {
-					ctx.say("<:tf:999383331647012935>")
-						.await?;
-					return Ok(());
-				}
-
-				let mut query = QueryBuilder::new(format!(
-					r#"
-					INSERT INTO {table}
-					    (name, discord_id, mode)
-					"#
-				));
-
-				query.push_values([(name, id, mode_id)], |mut query, (name, id, mode_id)| {
-					query
-						.push_bind(name)
-						.push_bind(id)
-						.push_bind(mode_id);
-				});
-
-				query
-					.build()
-					.execute(ctx.database())
-					.await?;
-
-				false
-			}
-		},
-	};
-
-	let reply = if let Ok(mode) = mode {
-		if updated {
+	let reply = match (save_mode(ctx.users(), id, name, mode.as_ref().ok().copied()).await?, mode) {
+		// :tf:
+		(ModeUpdate::Unchanged, _) => String::from("You already have this mode set."),
+		(ModeUpdate::NothingToClear, _) => String::from("<:tf:999383331647012935>"),
+		(ModeUpdate::Updated, Ok(mode)) => {
 			format!("Successfully updated Mode for <@{id}>! New Mode: `{mode}`")
-		} else {
-			format!("Successfully set Mode `{mode}` for <@{id}>!")
 		}
-	} else {
-		format!("Successfully cleared Mode for <@{id}>!")
+		(ModeUpdate::Created, Ok(mode)) => format!("Successfully set Mode `{mode}` for <@{id}>!"),
+		(_, Err(_)) => format!("Successfully cleared Mode for <@{id}>!"),
 	};
 
 	ctx.say(reply).await?;
 
 	Ok(())
 }
+
+/// What [`save_mode`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModeUpdate {
+	/// The user already had this mode set.
+	Unchanged,
+
+	/// The user wanted to clear their mode but has no database entry.
+	NothingToClear,
+
+	/// The user already had a database entry.
+	Updated,
+
+	/// The user didn't have a database entry yet.
+	Created,
+}
+
+/// `None` clears the user's mode preference.
+async fn save_mode(
+	users: &dyn UserRepository,
+	discord_id: u64,
+	name: &str,
+	mode: Option<Mode>,
+) -> Result<ModeUpdate> {
+	let update = match users
+		.find_by_discord_id(discord_id)
+		.await
+	{
+		Ok(user) if user.mode == mode => return Ok(ModeUpdate::Unchanged),
+		// User already has a database entry => modify current one
+		Ok(_) => ModeUpdate::Updated,
+		// The user simply has no entry yet => create a new one
+		Err(Error::NoDatabaseEntries) if mode.is_none() => return Ok(ModeUpdate::NothingToClear),
+		Err(Error::NoDatabaseEntries) => ModeUpdate::Created,
+		// This is not supposed to happen! Return with an error.
+		Err(why) => return Err(why),
+	};
+
+	users
+		.set_mode(discord_id, name, mode)
+		.await?;
+
+	Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, crate::db::InMemoryUsers};
+
+	#[tokio::test]
+	async fn save_modes() {
+		let users = InMemoryUsers::default();
+		let save = |mode| save_mode(&users, 1, "AlphaKeks", mode);
+
+		assert_eq!(save(None).await.unwrap(), ModeUpdate::NothingToClear);
+		assert!(users
+			.find_by_discord_id(1)
+			.await
+			.is_err());
+
+		assert_eq!(
+			save(Some(Mode::SimpleKZ))
+				.await
+				.unwrap(),
+			ModeUpdate::Created
+		);
+		assert_eq!(
+			save(Some(Mode::SimpleKZ))
+				.await
+				.unwrap(),
+			ModeUpdate::Unchanged
+		);
+		assert_eq!(save(Some(Mode::KZTimer)).await.unwrap(), ModeUpdate::Updated);
+		assert_eq!(save(None).await.unwrap(), ModeUpdate::Updated);
+		assert_eq!(
+			users
+				.find_by_discord_id(1)
+				.await
+				.unwrap()
+				.mode,
+			None
+		);
+	}
+}
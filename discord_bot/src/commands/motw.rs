@@ -0,0 +1,255 @@
+use {
+	super::choices::{ModeChoice, TierChoice},
+	crate::{
+		error::{Error, Result},
+		motw::{self, Settings},
+		Context, State,
+	},
+	gokz_rs::{Mode, Tier},
+	poise::serenity_prelude::GuildChannel,
+};
+
+/// Play a new map together every week.
+///
+/// The bot picks a map that matches this server's filters and hasn't been played before, \
+/// announces it and keeps track of the participants' PBs on it. If you finished the map before \
+/// the challenge started, only times that beat your old PB count. When the challenge is over the \
+/// final standings are posted and the next map is picked. Use one of the subcommands:
+///
+/// - `/motw setup`: set up challenges on this server (or change the settings)
+/// - `/motw disable`: stop running challenges on this server
+/// - `/motw join`: take part in the current and future challenges
+/// - `/motw leave`: stop taking part
+/// - `/motw current`: show the current map and standings
+#[tracing::instrument(skip(_ctx))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	subcommands("setup", "disable", "join", "leave", "current"),
+	on_error = "Error::handle_command"
+)]
+pub async fn motw(_ctx: Context<'_>) -> Result<()> {
+	Ok(())
+}
+
+/// Set up map challenges on this server.
+///
+/// If there is no challenge running yet, the first one starts right away. Otherwise the new \
+/// settings apply to the next challenge. You may specify the following parameters:
+///
+/// - `mode`: `KZTimer` / `SimpleKZ` / `Vanilla`
+/// - `channel`: the channel to post in. If you don't specify this, the current channel is used.
+/// - `min_tier` / `max_tier`: only pick maps within this tier range.
+/// - `days`: how long every challenge lasts. This defaults to 7 days.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	required_permissions = "MANAGE_CHANNELS",
+	on_error = "Error::handle_command"
+)]
+pub async fn setup(
+	ctx: Context<'_>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: ModeChoice,
+
+	#[description = "The channel to post challenges in."] channel: Option<GuildChannel>,
+
+	#[description = "Only pick maps with at least this tier."]
+	#[rename = "min_tier"]
+	min_tier_choice: Option<TierChoice>,
+
+	#[description = "Only pick maps with at most this tier."]
+	#[rename = "max_tier"]
+	max_tier_choice: Option<TierChoice>,
+
+	#[description = "How many days every challenge lasts."]
+	#[min = 1]
+	#[max = 30]
+	days: Option<u8>,
+) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let channel_id = channel.map_or(ctx.channel_id(), |channel| channel.id);
+	let settings = Settings {
+		guild_id: *guild_id.as_u64(),
+		channel_id: *channel_id.as_u64(),
+		mode: Mode::from(mode_choice),
+		min_tier: min_tier_choice.map(Tier::from),
+		max_tier: max_tier_choice.map(Tier::from),
+		days: days.unwrap_or(7),
+	};
+
+	let global_maps = ctx.global_maps();
+	if !global_maps
+		.iter()
+		.any(|map| settings.allows(map))
+	{
+		return Err(Error::Custom(String::from("No global maps match these filters.")));
+	}
+
+	motw::save_settings(ctx.database(), &settings).await?;
+
+	let content = if motw::active_challenge(ctx.database(), settings.guild_id)
+		.await?
+		.is_some()
+	{
+		format!("Updated settings: {settings}\nThey will apply to the next challenge.")
+	} else {
+		motw::start(
+			&ctx.serenity_context().http,
+			ctx.database(),
+			&global_maps,
+			&settings,
+			ctx.color(),
+		)
+		.await?;
+
+		format!("Started the first challenge! {settings}")
+	};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
+
+/// Stop running map challenges on this server.
+///
+/// The current challenge ends without posting results.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	required_permissions = "MANAGE_CHANNELS",
+	on_error = "Error::handle_command"
+)]
+pub async fn disable(ctx: Context<'_>) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let content = if motw::delete_settings(ctx.database(), *guild_id.as_u64()).await? {
+		"Map challenges are disabled now."
+	} else {
+		"Map challenges are not set up on this server."
+	};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
+
+/// Take part in the current map challenge.
+///
+/// Your PBs on the challenge's map are tracked using the SteamID you saved with `/setsteam`. Only \
+/// new PBs count, so if you already finished the map you have to beat your old time. You stay in \
+/// for future challenges until you use `/motw leave`.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn join(ctx: Context<'_>) -> Result<()> {
+	ctx.defer().await?;
+
+	let challenge = current_challenge(&ctx).await?;
+
+	let user = match ctx
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
+		.await
+	{
+		Ok(user) => Some(user),
+		Err(Error::NoDatabaseEntries) => None,
+		Err(why) => return Err(why),
+	};
+
+	let steam_id = user
+		.and_then(|user| user.steam_id)
+		.ok_or(Error::MissingSteamID { blame_user: true })?;
+
+	let content = if motw::join(
+		ctx.database(),
+		challenge.id,
+		*ctx.author().id.as_u64(),
+		&ctx.author().name,
+		&steam_id,
+	)
+	.await?
+	{
+		format!("You joined the challenge on {}. Good luck!", challenge.map_name)
+	} else {
+		String::from("You are already taking part in this challenge.")
+	};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
+
+/// Stop taking part in map challenges.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn leave(ctx: Context<'_>) -> Result<()> {
+	ctx.defer().await?;
+
+	let challenge = current_challenge(&ctx).await?;
+
+	let content = if motw::leave(ctx.database(), challenge.id, *ctx.author().id.as_u64()).await? {
+		"You left the challenge."
+	} else {
+		"You are not taking part in this challenge."
+	};
+
+	ctx.say(content).await?;
+
+	Ok(())
+}
+
+/// Show the current map challenge and its standings.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn current(ctx: Context<'_>) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let Some(settings) = motw::guild_settings(ctx.database(), *guild_id.as_u64()).await? else {
+		return Err(not_set_up());
+	};
+
+	let challenge = current_challenge(&ctx).await?;
+	let entries = motw::entries(ctx.database(), challenge.id).await?;
+	let embed = motw::challenge_embed(&challenge, settings.title(), &entries, ctx.color());
+
+	ctx.send(|reply| {
+		reply.embed(|e| {
+			*e = embed;
+			e
+		})
+	})
+	.await?;
+
+	Ok(())
+}
+
+async fn current_challenge(ctx: &Context<'_>) -> Result<motw::Challenge> {
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	motw::active_challenge(ctx.database(), *guild_id.as_u64())
+		.await?
+		.ok_or_else(not_set_up)
+}
+
+fn not_set_up() -> Error {
+	Error::Custom(String::from("There is no challenge running on this server. See `/motw setup`."))
+}
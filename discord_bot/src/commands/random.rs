@@ -23,8 +23,8 @@ pub async fn random(
 ) -> Result<()> {
 	ctx.defer().await?;
 
-	let global_maps = ctx
-		.global_maps()
+	let global_maps = ctx.global_maps();
+	let global_maps = global_maps
 		.iter()
 		.filter(|map| tier_choice.map_or(true, |tier| map.tier as u8 == tier as u8))
 		.collect::<Vec<_>>();
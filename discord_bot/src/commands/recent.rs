@@ -5,9 +5,8 @@ use {
 		target::Target,
 		Context, State,
 	},
-	gokz_rs::{global_api, schnose_api},
 	poise::serenity_prelude::CreateEmbed,
-	schnosebot::formatting::fmt_time,
+	schnosebot::{commands, formatting::fmt_time},
 };
 
 /// Get a player's 10 most recent runs.
@@ -19,6 +18,8 @@ use {
 /// will try its best to interpret it as something useful. If you want to help it with that, \
 /// specify one of the following:
 ///   - a `SteamID`, e.g. `STEAM_1:1:161178172`, `U:1:322356345` or `76561198282622073`
+///   - a Steam profile link, e.g. `https://steamcommunity.com/id/AlphaKeks` or \
+///     `https://steamcommunity.com/profiles/76561198282622073`
 ///   - a `Mention`, e.g. `@MyBestFriend`
 ///   - a player's name, e.g. `AlphaKeks`
 ///   - If you don't specify this, the bot will search the database for your UserID. If it can't \
@@ -36,20 +37,23 @@ pub async fn recent(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let player_identifier = Target::parse_input(target, db_entry, &ctx).await?;
 
-	let recent_records = schnose_api::get_recent(player_identifier, 10, ctx.gokz_client()).await?;
+	let recent_records =
+		commands::recent::execute(player_identifier, 10, ctx.gokz_client()).await?;
 
 	let mut embeds = Vec::new();
 	let max_records = recent_records.len();
 
 	for (i, record) in recent_records.into_iter().enumerate() {
-		let place = global_api::get_place(record.id, ctx.gokz_client())
-			.await
-			.map(|place| format!("[#{place}]"))?;
+		let place = record
+			.place
+			.map(|place| format!("[#{place}]"))
+			.unwrap_or_default();
 
 		let (map_name, map_tier, map_url, map_thumbnail) = ctx
 			.get_map(record.map_name.clone())
@@ -70,41 +74,29 @@ pub async fn recent(
 				)
 			});
 
-		let n_teleports = if record.teleports > 0 {
+		let n_teleports = if record.is_tp() {
 			format!(" ({} TP{})", record.teleports, if record.teleports > 1 { "s" } else { "" })
 		} else {
 			String::new()
 		};
 
 		let discord_timestamp = format!("<t:{}:R>", record.created_on.timestamp());
-		let player_profile = format!(
-			"[Profile](https://kzgo.eu/players/{}?{}=)",
-			record.player.steam_id,
-			record.mode.short().to_lowercase()
-		);
+		let player_profile = format!("[Profile]({})", record.player_url());
 
 		let mut embed = CreateEmbed::default();
 		embed
 			.color(ctx.color())
 			.title(format!(
 				"{} on {}{} (T{})",
-				record.player.name,
+				record.player_name,
 				&map_name,
-				if record.course.stage > 0 {
-					format!(" B{}", record.course.stage)
-				} else {
-					String::new()
-				},
+				if record.course > 0 { format!(" B{}", record.course) } else { String::new() },
 				map_tier
 			))
 			.url(map_url)
 			.thumbnail(&map_thumbnail)
 			.field(
-				format!(
-					"{} {}",
-					record.mode.short(),
-					if record.teleports > 0 { "TP" } else { "PRO" }
-				),
+				format!("{} {}", record.mode.short(), record.runtype()),
 				format!(
 					"> {} {}{}\n> {}\n> {}",
 					fmt_time(record.time),
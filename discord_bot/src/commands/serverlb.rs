@@ -0,0 +1,243 @@
+use {
+	super::{
+		autocompletion::autocomplete_map,
+		choices::{ModeChoice, RuntypeChoice, TierChoice},
+		pagination::paginate,
+	},
+	crate::{
+		error::{Error, Result},
+		stats::{self, Category},
+		Context, State,
+	},
+	futures::TryStreamExt,
+	gokz_rs::Tier,
+	num_format::{Locale, ToFormattedString},
+	poise::serenity_prelude::{CreateEmbed, UserId},
+	schnosebot::formatting::fmt_time,
+	std::collections::HashSet,
+};
+
+/// Leaderboards of the registered players on this server.
+///
+/// These leaderboards only include members of this server who saved their SteamID with \
+/// `/setsteam`. Stats are refreshed in the background every hour, so new records might take a \
+/// while to show up. Use one of the subcommands:
+///
+/// - `/serverlb points`: most points
+/// - `/serverlb wrs`: most world records
+/// - `/serverlb completion`: most completed maps, optionally for a single tier
+/// - `/serverlb map`: fastest times on a map
+///
+/// Every subcommand takes a `mode` and a `runtype`. If you don't specify a `mode`, your mode \
+/// preference from `/mode` is used. If you don't specify a `runtype`, TP and PRO are combined \
+/// (`/serverlb map` defaults to PRO instead).
+#[tracing::instrument(skip(_ctx))]
+#[poise::command(
+	slash_command,
+	guild_only,
+	subcommands("points", "wrs", "completion", "map"),
+	on_error = "Error::handle_command"
+)]
+pub async fn serverlb(_ctx: Context<'_>) -> Result<()> {
+	Ok(())
+}
+
+/// Registered players on this server with the most points.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn points(
+	ctx: Context<'_>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	send_leaderboard(ctx, Category::Points, mode_choice, runtype_choice).await
+}
+
+/// Registered players on this server with the most world records.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn wrs(
+	ctx: Context<'_>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	send_leaderboard(ctx, Category::Wrs, mode_choice, runtype_choice).await
+}
+
+/// Registered players on this server with the most completed maps.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn completion(
+	ctx: Context<'_>,
+
+	#[description = "Only count maps with this tier."]
+	#[rename = "tier"]
+	tier_choice: Option<TierChoice>,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	let tier = tier_choice.map(Tier::from);
+	send_leaderboard(ctx, Category::Completion(tier), mode_choice, runtype_choice).await
+}
+
+/// Fastest times of registered players on this server on a map.
+#[tracing::instrument(skip(ctx), fields(user = ctx.author().tag()))]
+#[poise::command(slash_command, guild_only, on_error = "Error::handle_command")]
+pub async fn map(
+	ctx: Context<'_>,
+
+	#[autocomplete = "autocomplete_map"]
+	#[rename = "map"]
+	map_choice: String,
+
+	#[description = "KZT/SKZ/VNL"]
+	#[rename = "mode"]
+	mode_choice: Option<ModeChoice>,
+
+	#[description = "TP/PRO"]
+	#[rename = "runtype"]
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	let map = ctx.get_map(map_choice)?;
+	send_leaderboard(ctx, Category::Map(map.id), mode_choice, runtype_choice).await
+}
+
+async fn send_leaderboard(
+	ctx: Context<'_>,
+	category: Category,
+	mode_choice: Option<ModeChoice>,
+	runtype_choice: Option<RuntypeChoice>,
+) -> Result<()> {
+	ctx.defer().await?;
+
+	let Some(guild_id) = ctx.guild_id() else {
+		return Err(Error::NoGuild { reason: String::new() });
+	};
+
+	let db_entry = ctx
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
+		.await;
+
+	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
+	let tp = runtype_choice.map(bool::from);
+
+	if ctx.stats_cache().updated_at().is_none() {
+		return Err(Error::Custom(String::from(
+			"Player stats are still being collected. Please try again in a few minutes.",
+		)));
+	}
+
+	let members = guild_id
+		.members_iter(ctx.serenity_context())
+		.map_ok(|member| member.user.id)
+		.try_collect::<HashSet<UserId>>()
+		.await?;
+
+	let steam_ids = ctx
+		.users()
+		.with_steam_id()
+		.await?
+		.into_iter()
+		.filter(|user| members.contains(&UserId(user.discord_id)))
+		.filter_map(|user| user.steam_id)
+		.collect();
+
+	let players = ctx
+		.stats_cache()
+		.players(mode, &steam_ids);
+	let leaderboard = stats::leaderboard(&players, category, tp);
+
+	if leaderboard.is_empty() {
+		return Err(Error::NoRecords);
+	}
+
+	let runtype = match (category, tp) {
+		(_, Some(true)) => "TP",
+		(Category::Map(_), _) | (_, Some(false)) => "PRO",
+		(_, None) => "TP & PRO",
+	};
+
+	let (title, url) = match category {
+		Category::Points => (String::from("Points"), None),
+		Category::Wrs => (String::from("World Records"), None),
+		Category::Completion(None) => (String::from("Completion"), None),
+		Category::Completion(Some(tier)) => (format!("Completion (T{})", tier as u8), None),
+		Category::Map(map_id) => {
+			let map = ctx.get_map(map_id)?;
+			let url = format!("{}?{}=", map.url, mode.short().to_lowercase());
+			(format!("{} (T{})", map.name, map.tier as u8), Some(url))
+		}
+	};
+
+	let completion_stats = ctx.stats_cache().completion_stats(mode);
+	let fmt_value = |value: f64| match category {
+		Category::Points | Category::Wrs => (value as u32).to_formatted_string(&Locale::en),
+		Category::Completion(tier) => {
+			let max = completion_stats.as_ref().map(|stats| {
+				let tier = tier.map_or(0, |tier| tier as usize);
+				match tp {
+					None => stats.tp[tier] + stats.pro[tier],
+					Some(true) => stats.tp[tier],
+					Some(false) => stats.pro[tier],
+				}
+			});
+
+			match max {
+				Some(max) if max > 0 => {
+					format!("{value}/{max} ({:.2}%)", value / f64::from(max) * 100.0)
+				}
+				_ => value.to_string(),
+			}
+		}
+		Category::Map(_) => fmt_time(value),
+	};
+
+	let chunk_size = 12;
+	let max_pages = (leaderboard.len() as f64 / chunk_size as f64).ceil() as u8;
+	let mut embeds = Vec::new();
+
+	for (page_idx, players) in leaderboard
+		.chunks(chunk_size)
+		.enumerate()
+	{
+		let mut embed = CreateEmbed::default();
+		embed
+			.color(ctx.color())
+			.title(format!("[Server {runtype}] {title}"))
+			.footer(|f| f.text(format!("Mode: {} | Page {} / {}", mode, page_idx + 1, max_pages)));
+
+		if let Some(url) = &url {
+			embed.url(url);
+		}
+
+		for (i, (player, value)) in players.iter().enumerate() {
+			let place = page_idx * chunk_size + i + 1;
+			embed.field(format!("{} [#{place}]", player.name), fmt_value(*value), true);
+		}
+
+		embeds.push(embed);
+	}
+
+	paginate(&ctx, embeds).await?;
+
+	Ok(())
+}
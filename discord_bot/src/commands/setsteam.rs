@@ -1,10 +1,10 @@
 use {
 	crate::{
+		db::UserRepository,
 		error::{Error, Result},
 		Context, State,
 	},
 	gokz_rs::SteamID,
-	sqlx::QueryBuilder,
 };
 
 /// Save your SteamID in the bot's database.
@@ -27,60 +27,68 @@ pub async fn setsteam(
 		(&author.name, *author.id.as_u64())
 	};
 
-	let table = &ctx.config().mysql_table;
+	if !save_steam_id(ctx.users(), id, name, &steam_id).await? {
+		// :tf:
+		ctx.say("You already have this SteamID set.")
+			.await?;
+		return Ok(());
+	}
 
-	match ctx.find_user_by_id(id).await {
-		// User already has a database entry => modify current one
-		Ok(user) => {
-			// :tf:
-			if user.steam_id.as_ref() == Some(&steam_id) {
-				ctx.say("You already have this SteamID set.")
-					.await?;
-				return Ok(());
-			}
+	ctx.say(format!("Successfully set SteamID `{steam_id}` for <@{id}>!"))
+		.await?;
 
-			let mut query = QueryBuilder::new(format!(r#"UPDATE {table} SET steam_id = "#));
+	Ok(())
+}
 
-			query
-				.push_bind(steam_id.to_string())
-				.push(" WHERE discord_id = ")
-				.push_bind(id);
+/// Returns `false` if the user already had `steam_id` set.
+async fn save_steam_id(
+	users: &dyn UserRepository,
+	discord_id: u64,
+	name: &str,
+	steam_id: &SteamID,
+) -> Result<bool> {
+	match users
+		.find_by_discord_id(discord_id)
+		.await
+	{
+		Ok(user) if user.steam_id.as_ref() == Some(steam_id) => return Ok(false),
+		// The user either has an entry already or simply doesn't have one yet.
+		Ok(_) | Err(Error::NoDatabaseEntries) => {}
+		// This is not supposed to happen! Return with an error.
+		Err(why) => return Err(why),
+	}
 
-			query
-				.build()
-				.execute(ctx.database())
-				.await?;
-		}
-		// We failed to get the user's database entry. Why?
-		Err(why) => match why {
-			// This is not supposed to happen! Return with an error.
-			why @ (Error::DatabaseAccess | Error::DatabaseUpdate) => return Err(why),
-			// The user simply has no entry yet => create a new one
-			_ => {
-				let mut query = QueryBuilder::new(format!(
-					r#"
-					INSERT INTO {table}
-					    (name, discord_id, steam_id)
-					"#
-				));
+	users
+		.upsert_steam_id(discord_id, name, steam_id)
+		.await?;
 
-				query.push_values([(name, id, steam_id)], |mut query, (name, id, steam_id)| {
-					query
-						.push_bind(name)
-						.push_bind(id)
-						.push_bind(steam_id.to_string());
-				});
+	Ok(true)
+}
 
-				query
-					.build()
-					.execute(ctx.database())
-					.await?;
-			}
-		},
-	};
+#[cfg(test)]
+mod tests {
+	use {super::*, crate::db::InMemoryUsers};
 
-	ctx.say(format!("Successfully set SteamID `{steam_id}` for <@{id}>!"))
-		.await?;
+	#[tokio::test]
+	async fn save_steam_ids() {
+		let users = InMemoryUsers::default();
+		let alphakeks = SteamID::new("STEAM_1:1:161178172").unwrap();
+		let other = SteamID::new("STEAM_1:0:102468802").unwrap();
 
-	Ok(())
+		assert!(save_steam_id(&users, 1, "AlphaKeks", &alphakeks)
+			.await
+			.unwrap());
+		assert!(!save_steam_id(&users, 1, "AlphaKeks", &alphakeks)
+			.await
+			.unwrap());
+		assert!(save_steam_id(&users, 1, "AlphaKeks", &other)
+			.await
+			.unwrap());
+
+		let user = users
+			.find_by_discord_id(1)
+			.await
+			.unwrap();
+		assert_eq!((user.name.as_str(), user.steam_id), ("AlphaKeks", Some(other)));
+	}
 }
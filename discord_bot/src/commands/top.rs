@@ -38,7 +38,8 @@ pub async fn top(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
@@ -5,7 +5,6 @@ use {
 	},
 	crate::{
 		error::{Error, Result},
-		steam,
 		target::Target,
 		Context, State,
 	},
@@ -28,6 +27,8 @@ use {
 /// - `player`: this can be any string. The bot will try its best to interpret it as something \
 ///   useful. If you want to help it with that, specify one of the following:
 ///   - a `SteamID`, e.g. `STEAM_1:1:161178172`, `U:1:322356345` or `76561198282622073`
+///   - a Steam profile link, e.g. `https://steamcommunity.com/id/AlphaKeks` or \
+///     `https://steamcommunity.com/profiles/76561198282622073`
 ///   - a `Mention`, e.g. `@MyBestFriend`
 ///   - a player's name, e.g. `AlphaKeks`
 ///   - If you don't specify this, the bot will search the database for your UserID. If it can't \
@@ -57,7 +58,8 @@ pub async fn unfinished(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
@@ -88,12 +90,9 @@ pub async fn unfinished(
 	let avatar = if let Ok(user) = kzgo_api::get_avatar(player.steam_id, ctx.gokz_client()).await {
 		user.avatar_url
 	} else {
-		steam::get_steam_avatar(
-			&ctx.config().steam_token,
-			player.steam_id.as_id64(),
-			ctx.gokz_client(),
-		)
-		.await?
+		ctx.steam()
+			.get_avatar(player.steam_id)
+			.await?
 	};
 
 	let mut template = CreateEmbed::default()
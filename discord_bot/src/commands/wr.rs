@@ -2,11 +2,10 @@ use {
 	super::{autocompletion::autocomplete_map, choices::ModeChoice},
 	crate::{
 		error::{Error, Result},
-		gokz::format_replay_links,
+		gokz::{format_record, format_replay_links},
 		Context, State,
 	},
-	gokz_rs::{global_api, MapIdentifier},
-	schnosebot::formatting::fmt_time,
+	schnosebot::commands,
 };
 
 /// World record on a given map.
@@ -34,63 +33,24 @@ pub async fn wr(
 	ctx.defer().await?;
 
 	let db_entry = ctx
-		.find_user_by_id(*ctx.author().id.as_u64())
+		.users()
+		.find_by_discord_id(*ctx.author().id.as_u64())
 		.await;
 
 	let map = ctx.get_map(map_choice)?;
-	let map_identifier = MapIdentifier::Name(map.name);
 	let mode = ModeChoice::parse_input(mode_choice, &db_entry)?;
 
-	let tp = global_api::get_wr(map_identifier.clone(), mode, true, 0, ctx.gokz_client()).await;
-	let pro = global_api::get_wr(map_identifier.clone(), mode, false, 0, ctx.gokz_client()).await;
-
-	let (tp_time, tp_links) = if let Ok(tp) = tp {
-		let player_name = format!(
-			"[{}](https://kzgo.eu/players/{}?{}=)",
-			tp.player_name,
-			tp.steam_id,
-			mode.short().to_lowercase()
-		);
-
-		(
-			format!(
-				"{} ({} TP{})\n> {}",
-				fmt_time(tp.time),
-				tp.teleports,
-				if tp.teleports > 1 { "s" } else { "" },
-				player_name
-			),
-			Some((tp.replay_view_link(), tp.replay_download_link())),
-		)
-	} else {
-		(String::from("😔"), None)
-	};
-
-	let (pro_time, pro_links) = if let Ok(pro) = pro {
-		let player_name = format!(
-			"[{}](https://kzgo.eu/players/{}?{}=)",
-			pro.player_name,
-			pro.steam_id,
-			mode.short().to_lowercase()
-		);
-
-		(
-			format!("{}\n> {}", fmt_time(pro.time), player_name),
-			Some((pro.replay_view_link(), pro.replay_download_link())),
-		)
-	} else {
-		(String::from("😔"), None)
-	};
+	let wrs = commands::wr::execute(map, mode, 0, ctx.gokz_client()).await;
 
 	ctx.send(|replay| {
 		replay.embed(|e| {
 			e.color(ctx.color())
-				.title(format!("[WR] {} (T{})", &map_identifier, map.tier as u8))
-				.url(format!("{}?{}=", &map.url, mode.short().to_lowercase()))
-				.thumbnail(&map.thumbnail)
-				.description(format_replay_links(tp_links, pro_links).unwrap_or_default())
-				.field("TP", tp_time, true)
-				.field("PRO", pro_time, true)
+				.title(format!("[WR] {} (T{})", &wrs.map.name, wrs.map.tier as u8))
+				.url(wrs.map_url())
+				.thumbnail(&wrs.map.thumbnail)
+				.description(format_replay_links(&wrs).unwrap_or_default())
+				.field("TP", format_record(&wrs.tp, true), true)
+				.field("PRO", format_record(&wrs.pro, true), true)
 				.footer(|f| {
 					f.text(format!("Mode: {mode}"))
 						.icon_url(ctx.icon())
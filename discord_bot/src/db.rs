@@ -1,5 +1,13 @@
 //! `MySQL` module for the bot's database.
 
+pub mod migrations;
+mod users;
+
+pub use users::{MySqlUsers, UserRepository};
+
+#[cfg(test)]
+pub use users::InMemoryUsers;
+
 use {
 	gokz_rs::{Mode, SteamID},
 	sqlx::FromRow,
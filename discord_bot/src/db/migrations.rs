@@ -0,0 +1,138 @@
+//! Schema migrations for the bot's database.
+//!
+//! Every file in `discord_bot/migrations` is applied exactly once, in order. Applied versions are
+//! tracked in the [`TABLE`] table, so adding a new migration only means adding a new file and an
+//! entry in [`MIGRATIONS`].
+
+use {
+	crate::error::Result,
+	sqlx::{MySql, Pool, QueryBuilder},
+	std::collections::HashSet,
+	tracing::info,
+};
+
+/// Table for keeping track of which migrations have been applied already.
+pub const TABLE: &str = "schema_migrations";
+
+/// Placeholder for the (configurable) name of the users table.
+const USERS_TABLE_PLACEHOLDER: &str = "{users_table}";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+	pub version: u32,
+	pub name: &'static str,
+	sql: &'static str,
+}
+
+impl Migration {
+	/// The migration's SQL with all placeholders filled in.
+	pub fn sql(&self, users_table: &str) -> String {
+		self.sql
+			.replace(USERS_TABLE_PLACEHOLDER, users_table)
+	}
+}
+
+/// All migrations, sorted by version.
+pub const MIGRATIONS: &[Migration] = &[
+	Migration {
+		version: 1,
+		name: "create_users",
+		sql: include_str!("../../migrations/0001_create_users.sql"),
+	},
+	Migration {
+		version: 2,
+		name: "create_feed_subscriptions",
+		sql: include_str!("../../migrations/0002_create_feed_subscriptions.sql"),
+	},
+	Migration {
+		version: 3,
+		name: "create_motw_settings",
+		sql: include_str!("../../migrations/0003_create_motw_settings.sql"),
+	},
+	Migration {
+		version: 4,
+		name: "create_motw_challenges",
+		sql: include_str!("../../migrations/0004_create_motw_challenges.sql"),
+	},
+	Migration {
+		version: 5,
+		name: "create_motw_entries",
+		sql: include_str!("../../migrations/0005_create_motw_entries.sql"),
+	},
+];
+
+/// Applies all migrations that haven't been applied yet.
+#[tracing::instrument(skip(database))]
+pub async fn run(database: &Pool<MySql>, users_table: &str) -> Result<()> {
+	sqlx::query(&format!(
+		r#"
+		CREATE TABLE IF NOT EXISTS {TABLE} (
+		    version    INT UNSIGNED  NOT NULL PRIMARY KEY,
+		    name       VARCHAR(255)  NOT NULL,
+		    applied_on TIMESTAMP     NOT NULL DEFAULT CURRENT_TIMESTAMP
+		)
+		"#
+	))
+	.execute(database)
+	.await?;
+
+	let applied = sqlx::query_as::<_, (u32,)>(&format!("SELECT version FROM {TABLE}"))
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(|(version,)| version)
+		.collect::<HashSet<_>>();
+
+	for migration in MIGRATIONS {
+		if applied.contains(&migration.version) {
+			continue;
+		}
+
+		info!("Applying migration #{} `{}`.", migration.version, migration.name);
+
+		let mut transaction = database.begin().await?;
+
+		sqlx::query(&migration.sql(users_table))
+			.execute(&mut transaction)
+			.await?;
+
+		let mut query = QueryBuilder::new(format!("INSERT INTO {TABLE} (version, name)"));
+		query.push_values([migration], |mut query, migration| {
+			query
+				.push_bind(migration.version)
+				.push_bind(migration.name);
+		});
+
+		query
+			.build()
+			.execute(&mut transaction)
+			.await?;
+
+		transaction.commit().await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn migrations_are_ordered() {
+		for (i, migration) in MIGRATIONS.iter().enumerate() {
+			assert_eq!(migration.version as usize, i + 1, "{}", migration.name);
+		}
+	}
+
+	#[test]
+	fn substitute_users_table() {
+		let sql = MIGRATIONS[0].sql("discord_users");
+		assert!(sql.contains("CREATE TABLE IF NOT EXISTS discord_users"));
+		assert!(!MIGRATIONS
+			.iter()
+			.any(|migration| migration
+				.sql("discord_users")
+				.contains('{')));
+	}
+}
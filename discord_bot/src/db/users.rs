@@ -0,0 +1,356 @@
+//! Access to the users table.
+
+use {
+	super::{User, UserSchema},
+	crate::error::{Error, Result},
+	gokz_rs::{Mode, SteamID},
+	poise::async_trait,
+	sqlx::{MySql, Pool, QueryBuilder},
+	std::collections::HashSet,
+};
+
+/// Everything the bot knows about its users. Lookups return [`Error::NoDatabaseEntries`] if there
+/// is no matching user.
+#[async_trait]
+pub trait UserRepository: std::fmt::Debug + Send + Sync {
+	async fn find_by_discord_id(&self, discord_id: u64) -> Result<User>;
+	async fn find_by_steam_id(&self, steam_id: &SteamID) -> Result<User>;
+
+	/// Matches partial names as well.
+	async fn find_by_name(&self, name: &str) -> Result<User>;
+
+	/// Which of `steam_ids` belong to a user.
+	async fn registered(&self, steam_ids: &HashSet<SteamID>) -> Result<HashSet<SteamID>>;
+
+	/// Every user who saved a SteamID.
+	async fn with_steam_id(&self) -> Result<Vec<User>>;
+
+	/// Creates the user if they don't exist yet.
+	async fn upsert_steam_id(&self, discord_id: u64, name: &str, steam_id: &SteamID) -> Result<()>;
+
+	/// Creates the user if they don't exist yet. `None` clears the preference.
+	async fn set_mode(&self, discord_id: u64, name: &str, mode: Option<Mode>) -> Result<()>;
+
+	/// Returns `false` if there was no such user.
+	async fn delete(&self, discord_id: u64) -> Result<bool>;
+}
+
+/// [`UserRepository`] backed by the bot's `MySQL` database.
+#[derive(Debug, Clone)]
+pub struct MySqlUsers {
+	database: Pool<MySql>,
+	table: String,
+}
+
+impl MySqlUsers {
+	pub fn new(database: Pool<MySql>, table: impl Into<String>) -> Self {
+		Self { database, table: table.into() }
+	}
+
+	async fn find_one(&self, column: &str, value: impl ToString + Send) -> Result<User> {
+		let mut query = QueryBuilder::new(format!("SELECT * FROM {} WHERE {column} ", self.table));
+
+		if column == "name" {
+			query
+				.push("LIKE ")
+				.push_bind(format!("%{}%", value.to_string()));
+		} else {
+			query
+				.push("= ")
+				.push_bind(value.to_string());
+		}
+
+		Ok(query
+			.build_query_as::<UserSchema>()
+			.fetch_one(&self.database)
+			.await?
+			.into())
+	}
+
+	/// Updates `column` if the user exists, otherwise inserts a new row.
+	async fn upsert(
+		&self,
+		discord_id: u64,
+		name: &str,
+		column: &str,
+		value: Option<String>,
+	) -> Result<()> {
+		let mut query = match self
+			.find_by_discord_id(discord_id)
+			.await
+		{
+			Ok(_) => {
+				let mut query = QueryBuilder::new(format!("UPDATE {} SET {column} = ", self.table));
+				query
+					.push_bind(value)
+					.push(" WHERE discord_id = ")
+					.push_bind(discord_id);
+				query
+			}
+			Err(Error::NoDatabaseEntries) => {
+				let mut query = QueryBuilder::new(format!(
+					"INSERT INTO {} (name, discord_id, {column})",
+					self.table
+				));
+				query.push_values([(name, discord_id, value)], |mut query, (name, id, value)| {
+					query
+						.push_bind(name)
+						.push_bind(id)
+						.push_bind(value);
+				});
+				query
+			}
+			Err(why) => return Err(why),
+		};
+
+		query
+			.build()
+			.execute(&self.database)
+			.await?;
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl UserRepository for MySqlUsers {
+	async fn find_by_discord_id(&self, discord_id: u64) -> Result<User> {
+		self.find_one("discord_id", discord_id)
+			.await
+	}
+
+	async fn find_by_steam_id(&self, steam_id: &SteamID) -> Result<User> {
+		self.find_one("steam_id", steam_id)
+			.await
+	}
+
+	async fn find_by_name(&self, name: &str) -> Result<User> {
+		self.find_one("name", name).await
+	}
+
+	async fn registered(&self, steam_ids: &HashSet<SteamID>) -> Result<HashSet<SteamID>> {
+		if steam_ids.is_empty() {
+			return Ok(HashSet::new());
+		}
+
+		let mut query =
+			QueryBuilder::new(format!("SELECT steam_id FROM {} WHERE steam_id IN (", self.table));
+		let mut separated = query.separated(", ");
+		for steam_id in steam_ids {
+			separated.push_bind(steam_id.to_string());
+		}
+		separated.push_unseparated(")");
+
+		Ok(query
+			.build_query_as::<(String,)>()
+			.fetch_all(&self.database)
+			.await?
+			.into_iter()
+			.filter_map(|(steam_id,)| SteamID::new(&steam_id).ok())
+			.collect())
+	}
+
+	async fn with_steam_id(&self) -> Result<Vec<User>> {
+		Ok(sqlx::query_as::<_, UserSchema>(&format!(
+			"SELECT * FROM {} WHERE steam_id IS NOT NULL",
+			self.table
+		))
+		.fetch_all(&self.database)
+		.await?
+		.into_iter()
+		.map(User::from)
+		.filter(|user| user.steam_id.is_some())
+		.collect())
+	}
+
+	async fn upsert_steam_id(&self, discord_id: u64, name: &str, steam_id: &SteamID) -> Result<()> {
+		self.upsert(discord_id, name, "steam_id", Some(steam_id.to_string()))
+			.await
+	}
+
+	async fn set_mode(&self, discord_id: u64, name: &str, mode: Option<Mode>) -> Result<()> {
+		self.upsert(discord_id, name, "mode", mode.map(|mode| (mode as u8).to_string()))
+			.await
+	}
+
+	async fn delete(&self, discord_id: u64) -> Result<bool> {
+		let mut query =
+			QueryBuilder::new(format!("DELETE FROM {} WHERE discord_id = ", self.table));
+		query.push_bind(discord_id);
+
+		let result = query
+			.build()
+			.execute(&self.database)
+			.await?;
+
+		Ok(result.rows_affected() > 0)
+	}
+}
+
+/// [`UserRepository`] that only lives in memory. Used for testing.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct InMemoryUsers {
+	users: std::sync::Mutex<Vec<User>>,
+}
+
+#[cfg(test)]
+impl InMemoryUsers {
+	fn find(&self, predicate: impl Fn(&User) -> bool) -> Result<User> {
+		self.users
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|user| predicate(user))
+			.cloned()
+			.ok_or(Error::NoDatabaseEntries)
+	}
+
+	fn upsert(&self, discord_id: u64, name: &str, update: impl FnOnce(&mut User)) {
+		let mut users = self.users.lock().unwrap();
+		match users
+			.iter_mut()
+			.find(|user| user.discord_id == discord_id)
+		{
+			Some(user) => update(user),
+			None => {
+				let mut user = User {
+					name: name.to_owned(),
+					discord_id,
+					steam_id: None,
+					mode: None,
+				};
+				update(&mut user);
+				users.push(user);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+#[async_trait]
+impl UserRepository for InMemoryUsers {
+	async fn find_by_discord_id(&self, discord_id: u64) -> Result<User> {
+		self.find(|user| user.discord_id == discord_id)
+	}
+
+	async fn find_by_steam_id(&self, steam_id: &SteamID) -> Result<User> {
+		self.find(|user| user.steam_id.as_ref() == Some(steam_id))
+	}
+
+	async fn find_by_name(&self, name: &str) -> Result<User> {
+		let name = name.to_lowercase();
+		self.find(|user| user.name.to_lowercase().contains(&name))
+	}
+
+	async fn registered(&self, steam_ids: &HashSet<SteamID>) -> Result<HashSet<SteamID>> {
+		Ok(self
+			.users
+			.lock()
+			.unwrap()
+			.iter()
+			.filter_map(|user| user.steam_id)
+			.filter(|steam_id| steam_ids.contains(steam_id))
+			.collect())
+	}
+
+	async fn with_steam_id(&self) -> Result<Vec<User>> {
+		Ok(self
+			.users
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|user| user.steam_id.is_some())
+			.cloned()
+			.collect())
+	}
+
+	async fn upsert_steam_id(&self, discord_id: u64, name: &str, steam_id: &SteamID) -> Result<()> {
+		self.upsert(discord_id, name, |user| user.steam_id = Some(*steam_id));
+		Ok(())
+	}
+
+	async fn set_mode(&self, discord_id: u64, name: &str, mode: Option<Mode>) -> Result<()> {
+		self.upsert(discord_id, name, |user| user.mode = mode);
+		Ok(())
+	}
+
+	async fn delete(&self, discord_id: u64) -> Result<bool> {
+		let mut users = self.users.lock().unwrap();
+		let len = users.len();
+		users.retain(|user| user.discord_id != discord_id);
+		Ok(users.len() < len)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn in_memory_users() {
+		let users = InMemoryUsers::default();
+		let steam_id = SteamID::new("STEAM_1:1:161178172").unwrap();
+
+		assert!(matches!(users.find_by_discord_id(1).await, Err(Error::NoDatabaseEntries)));
+
+		users
+			.upsert_steam_id(1, "AlphaKeks", &steam_id)
+			.await
+			.unwrap();
+		users
+			.set_mode(1, "AlphaKeks", Some(Mode::SimpleKZ))
+			.await
+			.unwrap();
+		users
+			.set_mode(2, "Szwagi", Some(Mode::KZTimer))
+			.await
+			.unwrap();
+
+		let user = users
+			.find_by_steam_id(&steam_id)
+			.await
+			.unwrap();
+		assert_eq!((user.discord_id, user.mode), (1, Some(Mode::SimpleKZ)));
+		assert_eq!(
+			users
+				.find_by_name("szw")
+				.await
+				.unwrap()
+				.discord_id,
+			2
+		);
+		assert_eq!(
+			users
+				.registered(&HashSet::from([steam_id]))
+				.await
+				.unwrap(),
+			HashSet::from([steam_id])
+		);
+		assert_eq!(
+			users
+				.with_steam_id()
+				.await
+				.unwrap()
+				.len(),
+			1
+		);
+
+		users
+			.set_mode(1, "AlphaKeks", None)
+			.await
+			.unwrap();
+		let user = users
+			.find_by_discord_id(1)
+			.await
+			.unwrap();
+		assert_eq!((user.steam_id, user.mode), (Some(steam_id), None));
+
+		assert!(users.delete(1).await.unwrap());
+		assert!(!users.delete(1).await.unwrap());
+		assert!(users
+			.find_by_steam_id(&steam_id)
+			.await
+			.is_err());
+	}
+}
@@ -17,6 +17,9 @@ pub enum Error {
 	/// Used for the static map cache / invalid user input.
 	MapNotGlobal,
 
+	/// The user's input matched multiple maps equally well.
+	AmbiguousMap { candidates: Vec<String> },
+
 	/// Failed to access the database.
 	DatabaseAccess,
 
@@ -75,6 +78,16 @@ impl std::fmt::Display for Error {
 				Error::Unknown => "Some unknown error occurred.",
 				Error::Custom(msg) => msg,
 				Error::MapNotGlobal => "Map is not global.",
+				Error::AmbiguousMap { candidates } => {
+					let shown = candidates
+						.iter()
+						.take(10)
+						.map(|name| format!("`{name}`"))
+						.collect::<Vec<_>>()
+						.join(", ");
+					let more = if candidates.len() > 10 { ", ..." } else { "" };
+					return f.write_fmt(format_args!("Multiple maps match your input. Did you mean one of these?\n{shown}{more}"));
+				}
 				Error::DatabaseAccess => "Failed to access the database.",
 				Error::DatabaseUpdate => "Failed to update an entry in the database.",
 				Error::NoDatabaseEntries => "No database entries found.",
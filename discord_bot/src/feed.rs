@@ -0,0 +1,408 @@
+//! Announcements for new records in subscribed channels. See [`schnosebot::feed`] for how new
+//! records are detected.
+
+use {
+	crate::{db::UserRepository, error::Result},
+	gokz_rs::{Mode, Tier},
+	poise::serenity_prelude::{ChannelId, CreateEmbed, Http},
+	schnosebot::{
+		feed::{self, ApiSource, FeedRecord, RecordFeed},
+		formatting::fmt_time,
+		global_maps::MapCache,
+	},
+	sqlx::{FromRow, MySql, Pool, QueryBuilder},
+	std::{collections::HashSet, sync::Arc, time::Duration},
+	tokio::task::JoinHandle,
+	tracing::{error, info, warn},
+};
+
+/// Table for storing subscriptions.
+pub const TABLE: &str = "feed_subscriptions";
+
+/// `MySQL` schema for a subscription row.
+#[derive(Debug, Clone, FromRow)]
+pub struct SubscriptionSchema {
+	pub guild_id: u64,
+	pub channel_id: u64,
+	pub mode: Option<u8>,
+	pub tp: Option<bool>,
+	pub min_tier: Option<u8>,
+	pub pbs: bool,
+	pub registered_only: bool,
+}
+
+/// Parsed version of [`SubscriptionSchema`]. `None` means "no filter".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+	pub guild_id: u64,
+	pub channel_id: u64,
+	pub mode: Option<Mode>,
+	pub tp: Option<bool>,
+	pub min_tier: Option<Tier>,
+
+	/// Also announce PBs of registered users.
+	pub pbs: bool,
+
+	/// Only announce records of users in the users table.
+	pub registered_only: bool,
+}
+
+impl From<SubscriptionSchema> for Subscription {
+	fn from(value: SubscriptionSchema) -> Self {
+		Self {
+			guild_id: value.guild_id,
+			channel_id: value.channel_id,
+			mode: value
+				.mode
+				.and_then(|mode| Mode::try_from(mode).ok()),
+			tp: value.tp,
+			min_tier: value
+				.min_tier
+				.and_then(|tier| Tier::try_from(tier).ok()),
+			pbs: value.pbs,
+			registered_only: value.registered_only,
+		}
+	}
+}
+
+impl std::fmt::Display for Subscription {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"<#{}>: {} {} WRs",
+			self.channel_id,
+			self.mode
+				.map_or_else(|| String::from("all"), |mode| mode.short()),
+			match self.tp {
+				None => "TP & PRO",
+				Some(true) => "TP",
+				Some(false) => "PRO",
+			},
+		)?;
+
+		if self.pbs {
+			f.write_str(" + PBs")?;
+		}
+
+		if let Some(tier) = self.min_tier {
+			write!(f, " | T{}+", tier as u8)?;
+		}
+
+		if self.registered_only {
+			f.write_str(" | registered users only")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Subscription {
+	/// `registered` is whether the record's player is in the users table, `is_pb` whether the
+	/// record beat their previous PB (see [`feed::is_pb`]).
+	pub fn matches(&self, record: &FeedRecord, registered: bool, is_pb: bool) -> bool {
+		if self
+			.mode
+			.is_some_and(|mode| mode != record.record.mode)
+		{
+			return false;
+		}
+
+		if self
+			.tp
+			.is_some_and(|tp| tp != record.record.is_tp())
+		{
+			return false;
+		}
+
+		if self
+			.min_tier
+			.is_some_and(|min_tier| record.tier < min_tier)
+		{
+			return false;
+		}
+
+		if self.registered_only && !registered {
+			return false;
+		}
+
+		record.is_wr() || (self.pbs && registered && is_pb)
+	}
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn subscriptions(database: &Pool<MySql>) -> Result<Vec<Subscription>> {
+	Ok(sqlx::query_as::<_, SubscriptionSchema>(&format!("SELECT * FROM {TABLE}"))
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(Subscription::from)
+		.collect())
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn guild_subscriptions(
+	database: &Pool<MySql>,
+	guild_id: u64,
+) -> Result<Vec<Subscription>> {
+	let mut query = QueryBuilder::new(format!("SELECT * FROM {TABLE} WHERE guild_id = "));
+	query.push_bind(guild_id);
+
+	Ok(query
+		.build_query_as::<SubscriptionSchema>()
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(Subscription::from)
+		.collect())
+}
+
+/// Creates a new subscription or replaces the existing one for the same channel.
+#[tracing::instrument(skip(database))]
+pub async fn subscribe(database: &Pool<MySql>, subscription: &Subscription) -> Result<()> {
+	let mut query = QueryBuilder::new(format!(
+		"INSERT INTO {TABLE} (guild_id, channel_id, mode, tp, min_tier, pbs, registered_only) "
+	));
+
+	query
+		.push_values([subscription], |mut query, subscription| {
+			query
+				.push_bind(subscription.guild_id)
+				.push_bind(subscription.channel_id)
+				.push_bind(subscription.mode.map(|mode| mode as u8))
+				.push_bind(subscription.tp)
+				.push_bind(
+					subscription
+						.min_tier
+						.map(|tier| tier as u8),
+				)
+				.push_bind(subscription.pbs)
+				.push_bind(subscription.registered_only);
+		})
+		.push(
+			r#"
+			ON DUPLICATE KEY UPDATE
+			    mode = VALUES(mode),
+			    tp = VALUES(tp),
+			    min_tier = VALUES(min_tier),
+			    pbs = VALUES(pbs),
+			    registered_only = VALUES(registered_only)
+			"#,
+		);
+
+	query.build().execute(database).await?;
+
+	Ok(())
+}
+
+/// Returns `false` if there was no subscription to delete.
+#[tracing::instrument(skip(database))]
+pub async fn unsubscribe(database: &Pool<MySql>, guild_id: u64, channel_id: u64) -> Result<bool> {
+	let mut query = QueryBuilder::new(format!("DELETE FROM {TABLE} WHERE guild_id = "));
+	query
+		.push_bind(guild_id)
+		.push(" AND channel_id = ")
+		.push_bind(channel_id);
+
+	let result = query.build().execute(database).await?;
+
+	Ok(result.rows_affected() > 0)
+}
+
+fn record_embed(record: &FeedRecord, color: (u8, u8, u8)) -> CreateEmbed {
+	let FeedRecord { record, tier } = record;
+	let course = match record.course {
+		0 => String::new(),
+		course => format!(" B{course}"),
+	};
+	let title =
+		if matches!(record.place, Some(1)) { "New World Record" } else { "New Personal Best" };
+	let place = record
+		.place
+		.map_or_else(String::new, |place| format!(" [#{place}]"));
+	let teleports = match record.teleports {
+		0 => String::new(),
+		1 => String::from(" (1 TP)"),
+		n => format!(" ({n} TPs)"),
+	};
+
+	let mut embed = CreateEmbed::default();
+	embed
+		.color(color)
+		.title(format!("{title}! {}{course} (T{})", record.map_name, *tier as u8))
+		.url(format!(
+			"https://kzgo.eu/maps/{}?{}=",
+			record.map_name,
+			record.mode.short().to_lowercase()
+		))
+		.thumbnail(format!(
+			"https://raw.githubusercontent.com/KZGlobalTeam/map-images/master/images/{}.jpg",
+			record.map_name
+		))
+		.description(format!(
+			"[{}]({}) finished in {} {} with {}{place}{teleports}",
+			record.player_name,
+			record.player_url(),
+			record.mode.short(),
+			record.runtype(),
+			fmt_time(record.time)
+		));
+
+	embed
+}
+
+/// Polls for new records every `interval` and sends them to every matching subscription.
+pub fn spawn_poller(
+	http: Arc<Http>,
+	database: Pool<MySql>,
+	gokz_client: gokz_rs::Client,
+	global_maps: MapCache,
+	users: Arc<dyn UserRepository>,
+	color: (u8, u8, u8),
+	interval: Duration,
+) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		let source = ApiSource::new(gokz_client);
+		let mut feed = RecordFeed::new();
+		let mut interval = tokio::time::interval(interval);
+
+		loop {
+			interval.tick().await;
+
+			let records = match feed
+				.poll(&source, &global_maps.maps())
+				.await
+			{
+				Ok(records) if records.is_empty() => continue,
+				Ok(records) => records,
+				Err(why) => {
+					warn!("Failed to poll for new records: {why:?}");
+					continue;
+				}
+			};
+
+			let subscriptions = match subscriptions(&database).await {
+				Ok(subscriptions) if subscriptions.is_empty() => continue,
+				Ok(subscriptions) => subscriptions,
+				Err(why) => {
+					error!("Failed to fetch feed subscriptions: {why:?}");
+					continue;
+				}
+			};
+
+			let steam_ids = records
+				.iter()
+				.map(|record| record.record.steam_id)
+				.collect();
+			let registered = users
+				.registered(&steam_ids)
+				.await
+				.unwrap_or_else(|why| {
+					error!("Failed to look up registered players: {why:?}");
+					HashSet::new()
+				});
+
+			let wants_pbs = subscriptions
+				.iter()
+				.any(|subscription| subscription.pbs);
+
+			for record in &records {
+				let is_registered = registered.contains(&record.record.steam_id);
+				// only look up PBs if someone could be told about this one
+				let is_pb = record.is_wr()
+					|| (wants_pbs && is_registered && feed::is_pb(&source, record).await);
+
+				for subscription in &subscriptions {
+					if !subscription.matches(record, is_registered, is_pb) {
+						continue;
+					}
+
+					let embed = record_embed(record, color);
+					if let Err(why) = ChannelId(subscription.channel_id)
+						.send_message(&http, |msg| msg.set_embed(embed))
+						.await
+					{
+						warn!(
+							"Failed to announce record in <#{}>: {why:?}",
+							subscription.channel_id
+						);
+					}
+				}
+			}
+
+			info!("Processed {} new records.", records.len());
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, chrono::NaiveDateTime, gokz_rs::SteamID, schnosebot::commands::Record};
+
+	fn feed_record(place: u32, teleports: u32) -> FeedRecord {
+		FeedRecord {
+			record: Record {
+				id: 1,
+				player_name: String::from("AlphaKeks"),
+				steam_id: SteamID::new("STEAM_1:1:161178172").unwrap(),
+				map_name: String::from("kz_lionharder"),
+				course: 0,
+				mode: Mode::SimpleKZ,
+				time: 727.727,
+				teleports,
+				place: Some(place),
+				replay: None,
+				created_on: NaiveDateTime::default(),
+			},
+			tier: Tier::Extreme,
+		}
+	}
+
+	#[test]
+	fn match_subscriptions() {
+		let everything = Subscription {
+			guild_id: 0,
+			channel_id: 0,
+			mode: None,
+			tp: None,
+			min_tier: None,
+			pbs: false,
+			registered_only: false,
+		};
+
+		let wr = feed_record(1, 0);
+		let pb = feed_record(12, 0);
+
+		assert!(everything.matches(&wr, false, true));
+		assert!(!everything.matches(&pb, true, true));
+
+		let pbs = Subscription { pbs: true, ..everything.clone() };
+		assert!(pbs.matches(&pb, true, true));
+		assert!(!pbs.matches(&pb, false, true));
+
+		// slower than their existing PB
+		assert!(!pbs.matches(&pb, true, false));
+
+		let registered_only = Subscription {
+			registered_only: true,
+			..everything.clone()
+		};
+		assert!(!registered_only.matches(&wr, false, true));
+		assert!(registered_only.matches(&wr, true, true));
+
+		let kzt = Subscription {
+			mode: Some(Mode::KZTimer),
+			..everything.clone()
+		};
+		assert!(!kzt.matches(&wr, false, true));
+
+		let tp = Subscription { tp: Some(true), ..everything.clone() };
+		assert!(!tp.matches(&wr, false, true));
+		assert!(tp.matches(&feed_record(1, 3), false, true));
+
+		let death = Subscription {
+			min_tier: Some(Tier::Death),
+			..everything
+		};
+		assert!(!death.matches(&wr, false, true));
+	}
+}
@@ -1,43 +1,57 @@
 //! Some extra utilities in addition to [`gokz_rs`] to make working with the `GlobalAPI` easier.
 
-pub fn format_replay_links(
-	tp_links: Option<(Option<String>, Option<String>)>,
-	pro_links: Option<(Option<String>, Option<String>)>,
-) -> Option<String> {
-	let tp_links = match tp_links {
-		None => None,
-		Some(links) => {
-			if let (Some(view_link), Some(download_link)) = links {
-				Some((view_link, download_link))
-			} else {
-				None
-			}
-		}
+use schnosebot::{
+	commands::{Record, RecordLookup},
+	formatting::fmt_time,
+};
+
+/// Replay links for both records of a lookup as markdown.
+pub fn format_replay_links(lookup: &RecordLookup) -> Option<String> {
+	let link = |runtype: &str, record: &Option<Record>| {
+		record
+			.as_ref()
+			.and_then(|record| record.replay.as_ref())
+			.map(|replay| {
+				format!(
+					"{runtype} Replay: [View Online]({}) | [Download]({})",
+					replay.view, replay.download
+				)
+			})
 	};
 
-	let pro_links = match pro_links {
-		None => None,
-		Some(links) => {
-			if let (Some(view_link), Some(download_link)) = links {
-				Some((view_link, download_link))
-			} else {
-				None
-			}
-		}
+	match (link("TP", &lookup.tp), link("PRO", &lookup.pro)) {
+		(Some(tp), Some(pro)) => Some(format!("{tp}\n{pro}")),
+		(Some(links), None) | (None, Some(links)) => Some(links),
+		(None, None) => None,
+	}
+}
+
+/// Formats a record for an embed field. If `show_player` is true, a link to the player's profile
+/// is added below the time, otherwise the record's place is shown next to it.
+pub fn format_record(record: &Option<Record>, show_player: bool) -> String {
+	let Some(record) = record else {
+		return String::from("😔");
 	};
 
-	match (tp_links, pro_links) {
-		(Some((tp_view, tp_download)), Some((pro_view, pro_download))) => {
-			Some(format!("TP Replay: [View Online]({tp_view}) | [Download]({tp_download})\nPRO Replay: [View Online]({pro_view}) | [Download]({pro_download})"))
-		}
-		(Some((tp_view, tp_download)), None) => {
-			Some(format!("TP Replay: [View Online]({tp_view}) | [Download]({tp_download})"))
-		}
-		(None, Some((pro_view, pro_download))) => {
-			Some(format!("PRO Replay: [View Online]({pro_view}) | [Download]({pro_download})"))
-		}
-		(None, None) => {
-			None
+	let mut text = fmt_time(record.time);
+
+	if !show_player {
+		if let Some(place) = record.place {
+			text.push_str(&format!(" [#{place}]"));
 		}
 	}
+
+	if record.is_tp() {
+		text.push_str(&format!(
+			" ({} TP{})",
+			record.teleports,
+			if record.teleports > 1 { "s" } else { "" }
+		));
+	}
+
+	if show_player {
+		text.push_str(&format!("\n> [{}]({})", record.player_name, record.player_url()));
+	}
+
+	text
 }
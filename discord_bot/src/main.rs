@@ -13,27 +13,46 @@ mod based_maps;
 mod commands;
 mod db;
 mod error;
+mod feed;
 mod gokz;
+mod motw;
 mod process;
+mod stats;
 mod steam;
 mod target;
 
 use {
-	crate::error::{Error, Result},
+	crate::{
+		db::{MySqlUsers, UserRepository},
+		error::{Error, Result},
+		stats::StatsCache,
+		steam::SteamClient,
+	},
 	clap::{Parser, ValueEnum},
 	color_eyre::Result as Eyre,
-	gokz_rs::{MapIdentifier, Mode, SteamID},
+	gokz_rs::MapIdentifier,
 	poise::{
 		async_trait,
 		serenity_prelude::{Activity, GatewayIntents, GuildId, UserId},
 		Command, Event, Framework, FrameworkOptions, PrefixFrameworkOptions,
 	},
-	schnosebot::global_maps::{self, GlobalMap},
+	schnosebot::{
+		commands::map::CompletionsCache,
+		feed::DEFAULT_POLL_INTERVAL,
+		global_maps::{self, GlobalMap, MapCache},
+		map_search::{MapSearch, SearchResult},
+	},
 	serde::Deserialize,
-	sqlx::{mysql::MySqlPoolOptions, MySql, Pool, QueryBuilder},
-	std::{collections::HashSet, path::PathBuf},
+	sqlx::{mysql::MySqlPoolOptions, MySql, Pool},
+	std::{
+		collections::{HashMap, HashSet},
+		path::PathBuf,
+		sync::Arc,
+		time::Duration,
+	},
 	time::macros::format_description,
-	tracing::{debug, info},
+	tokio::sync::broadcast::error::RecvError,
+	tracing::{debug, info, warn},
 	tracing_subscriber::{
 		fmt::{format::FmtSpan, time::UtcTime},
 		EnvFilter,
@@ -98,12 +117,15 @@ async fn main() -> Eyre<()> {
 				commands::bpb(),
 				commands::btop(),
 				commands::bwr(),
+				commands::compare(),
 				commands::db(),
+				commands::feed(),
 				commands::help(),
 				commands::invite(),
 				commands::map(),
 				commands::maptop(),
 				commands::mode(),
+				commands::motw(),
 				commands::nocrouch(),
 				commands::pb(),
 				commands::ping(),
@@ -114,6 +136,7 @@ async fn main() -> Eyre<()> {
 				commands::recompile(),
 				commands::report(),
 				commands::restart(),
+				commands::serverlb(),
 				commands::setsteam(),
 				commands::top(),
 				commands::unfinished(),
@@ -166,6 +189,31 @@ async fn main() -> Eyre<()> {
 					info!("[{mode}] Successfully registered command `/{name}`.");
 				}
 
+				feed::spawn_poller(
+					ctx.http.clone(),
+					global_state.database.clone(),
+					global_state.gokz_client.clone(),
+					global_state.global_maps.clone(),
+					Arc::clone(&global_state.users),
+					global_state.color,
+					global_state
+						.config
+						.feed_interval
+						.map_or(DEFAULT_POLL_INTERVAL, Duration::from_secs),
+				);
+
+				motw::spawn_scheduler(
+					ctx.http.clone(),
+					global_state.database.clone(),
+					global_state.gokz_client.clone(),
+					global_state.global_maps.clone(),
+					global_state.color,
+					global_state
+						.config
+						.motw_interval
+						.map_or(motw::CHECK_INTERVAL, Duration::from_secs),
+				);
+
 				Ok(global_state)
 			})
 		});
@@ -268,6 +316,28 @@ pub struct Config {
 
 	/// How many CPU threads to use for compilation.
 	pub jobs: u8,
+
+	/// How often (in seconds) to re-fetch the global map pool. This defaults to 30 minutes.
+	pub map_refresh_interval: Option<u64>,
+
+	/// File to store a copy of the global map pool in. If this is set, the bot will fall back to
+	/// this file when the APIs are down on startup.
+	pub map_snapshot: Option<PathBuf>,
+
+	/// Short names for maps that users can type instead of the full name.
+	/// (e.g. `lh = "kz_lionharder"`)
+	pub map_aliases: Option<HashMap<String, String>>,
+
+	/// How often (in seconds) to check for new records to announce. This defaults to 1 minute.
+	pub feed_interval: Option<u64>,
+
+	/// How often (in seconds) to check map challenges for new PBs and challenges that are over.
+	/// This defaults to 15 minutes.
+	pub motw_interval: Option<u64>,
+
+	/// How often (in seconds) to re-fetch the stats of registered players for `/serverlb`. This
+	/// defaults to 1 hour.
+	pub stats_interval: Option<u64>,
 }
 
 /// Which level to register commands on.
@@ -306,14 +376,28 @@ pub struct GlobalState {
 	/// MySQL connection pool for storing user data.
 	pub database: Pool<MySql>,
 
+	/// Access to the users table.
+	pub users: Arc<dyn UserRepository>,
+
 	/// [`gokz_rs::Client`] for making requests with the `gokz_rs` crate.
 	pub gokz_client: gokz_rs::Client,
 
-	/// Cache of all global maps.
-	pub global_maps: Vec<GlobalMap>,
+	/// Cache of all global maps. This gets refreshed periodically in the background.
+	pub global_maps: MapCache,
+
+	/// Used for looking up maps by (partial) name.
+	pub map_search: MapSearch,
+
+	/// Stats of registered players. This gets refreshed periodically in the background.
+	pub stats_cache: StatsCache,
+
+	/// Completion counts for `/map`. Entries expire after [`COMPLETIONS_TTL`].
+	///
+	/// [`COMPLETIONS_TTL`]: schnosebot::commands::map::COMPLETIONS_TTL
+	pub completions_cache: CompletionsCache,
 
-	/// Cache of all global map names.
-	pub global_map_names: Vec<String>,
+	/// Steam WebAPI client using [`Config::steam_token`].
+	pub steam: SteamClient,
 
 	/// #7480c2
 	pub color: (u8, u8, u8),
@@ -334,21 +418,79 @@ impl GlobalState {
 			.await
 			.expect("Failed to establish database connection.");
 
+		db::migrations::run(&database, &config.mysql_table)
+			.await
+			.expect("Failed to run database migrations.");
+
+		let users = Arc::new(MySqlUsers::new(database.clone(), &config.mysql_table));
+
 		let gokz_client = gokz_rs::Client::new();
-		let global_maps = global_maps::init(&gokz_client, true)
+		let global_maps = MapCache::new(&gokz_client, true, config.map_snapshot.clone())
 			.await
 			.expect("Failed to fetch global maps.");
-		let global_map_names = global_maps
-			.iter()
-			.map(|map| map.name.to_string())
-			.collect();
+
+		if global_maps.is_degraded() {
+			warn!("Starting with a map pool loaded from disk.");
+		}
+
+		let refresh_interval = config
+			.map_refresh_interval
+			.map_or(global_maps::DEFAULT_REFRESH_INTERVAL, Duration::from_secs);
+		global_maps.spawn_refresh_task(gokz_client.clone(), refresh_interval);
+
+		let mut map_updates = global_maps.subscribe();
+		tokio::spawn(async move {
+			loop {
+				let diff = match map_updates.recv().await {
+					Ok(diff) => diff,
+					Err(RecvError::Lagged(skipped)) => {
+						warn!("Missed {skipped} map pool updates.");
+						continue;
+					}
+					Err(RecvError::Closed) => break,
+				};
+
+				for map in &diff.added {
+					info!("New global map: `{}`", map.name);
+				}
+				for map in &diff.removed {
+					warn!("Map is not global anymore: `{}`", map.name);
+				}
+				for (_, map) in &diff.changed {
+					info!("Global map changed: `{}`", map.name);
+				}
+			}
+		});
+
+		let stats_cache = StatsCache::default();
+		stats_cache.spawn_refresh_task(
+			Arc::clone(&users) as Arc<dyn UserRepository>,
+			gokz_client.clone(),
+			global_maps.clone(),
+			config
+				.stats_interval
+				.map_or(stats::DEFAULT_REFRESH_INTERVAL, Duration::from_secs),
+		);
+
+		let steam = SteamClient::new(config.steam_token.clone(), gokz_client.clone());
+
+		let map_search = MapSearch::new(
+			config
+				.map_aliases
+				.clone()
+				.unwrap_or_default(),
+		);
 
 		Self {
 			config,
 			database,
+			users,
 			gokz_client,
 			global_maps,
-			global_map_names,
+			map_search,
+			stats_cache,
+			completions_cache: CompletionsCache::default(),
+			steam,
 			color: (116, 128, 194),
 			icon: String::from(
 				"https://media.discordapp.net/attachments/981130651094900756/1068608508645347408/schnose.png"
@@ -368,9 +510,14 @@ pub type Context<'ctx> = poise::Context<'ctx, GlobalState, Error>;
 pub trait State {
 	fn config(&self) -> &Config;
 	fn database(&self) -> &Pool<MySql>;
+	fn users(&self) -> &dyn UserRepository;
 	fn gokz_client(&self) -> &gokz_rs::Client;
-	fn global_maps(&self) -> &Vec<GlobalMap>;
-	fn global_map_names(&self) -> &Vec<String>;
+	fn global_maps(&self) -> Arc<Vec<GlobalMap>>;
+	fn global_map_names(&self) -> Vec<String>;
+	fn map_search(&self) -> &MapSearch;
+	fn stats_cache(&self) -> &StatsCache;
+	fn completions_cache(&self) -> &CompletionsCache;
+	fn steam(&self) -> &SteamClient;
 	fn get_map(&self, map_identifier: impl Into<MapIdentifier>) -> Result<GlobalMap>;
 	fn get_map_name(&self, map_identifier: impl Into<MapIdentifier>) -> Result<String> {
 		self.get_map(map_identifier)
@@ -380,10 +527,6 @@ pub trait State {
 	fn color(&self) -> (u8, u8, u8);
 	fn icon(&self) -> &str;
 	fn schnose(&self) -> &str;
-	async fn find_user_by_id(&self, user_id: u64) -> Result<db::User>;
-	async fn find_user_by_name(&self, user_name: &str) -> Result<db::User>;
-	async fn find_user_by_steam_id(&self, steam_id: &SteamID) -> Result<db::User>;
-	async fn find_user_by_mode(&self, mode: Mode) -> Result<db::User>;
 }
 
 #[async_trait]
@@ -396,91 +539,62 @@ impl State for Context<'_> {
 		&self.data().database
 	}
 
+	fn users(&self) -> &dyn UserRepository {
+		self.data().users.as_ref()
+	}
+
 	fn gokz_client(&self) -> &gokz_rs::Client {
 		&self.data().gokz_client
 	}
 
-	fn global_maps(&self) -> &Vec<GlobalMap> {
-		&self.data().global_maps
+	fn global_maps(&self) -> Arc<Vec<GlobalMap>> {
+		self.data().global_maps.maps()
 	}
 
-	fn global_map_names(&self) -> &Vec<String> {
-		&self.data().global_map_names
+	fn global_map_names(&self) -> Vec<String> {
+		self.data().global_maps.map_names()
 	}
 
-	fn get_map(&self, map_identifier: impl Into<MapIdentifier>) -> Result<GlobalMap> {
-		schnosebot::global_maps::fuzzy_find_map(map_identifier, self.global_maps())
-			.ok_or(Error::MapNotGlobal)
+	fn map_search(&self) -> &MapSearch {
+		&self.data().map_search
 	}
 
-	fn color(&self) -> (u8, u8, u8) {
-		self.data().color
-	}
-	fn icon(&self) -> &str {
-		&self.data().icon
+	fn stats_cache(&self) -> &StatsCache {
+		&self.data().stats_cache
 	}
 
-	fn schnose(&self) -> &str {
-		&self.data().schnose
+	fn completions_cache(&self) -> &CompletionsCache {
+		&self.data().completions_cache
 	}
 
-	async fn find_user_by_id(&self, user_id: u64) -> Result<db::User> {
-		let mut query = QueryBuilder::new(format!(
-			r#"SELECT * FROM {} WHERE discord_id = "#,
-			self.config().mysql_table
-		));
-
-		query.push_bind(user_id);
-
-		Ok(query
-			.build_query_as::<db::UserSchema>()
-			.fetch_one(self.database())
-			.await?
-			.into())
+	fn steam(&self) -> &SteamClient {
+		&self.data().steam
 	}
 
-	async fn find_user_by_name(&self, user_name: &str) -> Result<db::User> {
-		let mut query = QueryBuilder::new(format!(
-			r#"SELECT * FROM {} WHERE name LIKE "#,
-			self.config().mysql_table
-		));
-
-		query.push_bind(format!(r#"%{user_name}%"#));
-
-		Ok(query
-			.build_query_as::<db::UserSchema>()
-			.fetch_one(self.database())
-			.await?
-			.into())
+	fn get_map(&self, map_identifier: impl Into<MapIdentifier>) -> Result<GlobalMap> {
+		match self
+			.map_search()
+			.find(map_identifier, &self.global_maps())
+		{
+			SearchResult::Found(map) => Ok(map.to_owned()),
+			SearchResult::Ambiguous(candidates) => Err(Error::AmbiguousMap {
+				candidates: candidates
+					.into_iter()
+					.map(|map| map.name.clone())
+					.collect(),
+			}),
+			SearchResult::NotFound => Err(Error::MapNotGlobal),
+		}
 	}
 
-	async fn find_user_by_steam_id(&self, steam_id: &SteamID) -> Result<db::User> {
-		let mut query = QueryBuilder::new(format!(
-			r#"SELECT * FROM {} WHERE steam_id = "#,
-			self.config().mysql_table
-		));
-
-		query.push_bind(steam_id.to_string());
-
-		Ok(query
-			.build_query_as::<db::UserSchema>()
-			.fetch_one(self.database())
-			.await?
-			.into())
+	fn color(&self) -> (u8, u8, u8) {
+		self.data().color
+	}
+	fn icon(&self) -> &str {
+		&self.data().icon
 	}
 
-	async fn find_user_by_mode(&self, mode: Mode) -> Result<db::User> {
-		let mut query = QueryBuilder::new(format!(
-			r#"SELECT * FROM {} WHERE mode = "#,
-			self.config().mysql_table
-		));
-
-		query.push_bind(mode as u8);
-
-		Ok(query
-			.build_query_as::<db::UserSchema>()
-			.fetch_one(self.database())
-			.await?
-			.into())
+	fn schnose(&self) -> &str {
+		&self.data().schnose
 	}
 }
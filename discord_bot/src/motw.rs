@@ -0,0 +1,998 @@
+//! Scheduled map challenges ("map of the week") per guild. The bot picks a map that matches the
+//! guild's filters and hasn't been played before, announces it, keeps track of the participants'
+//! PBs on it while the challenge is running and posts the final standings once it's over.
+//!
+//! The GlobalAPI only keeps every player's best time, so only runs that beat a participant's
+//! existing PB count. A slower run during the challenge is invisible to us if they had already
+//! finished the map before it started.
+//!
+//! Everything lives in the database, so challenges survive restarts. The scheduler just looks at
+//! the current state every [`CHECK_INTERVAL`] and does whatever is due.
+
+use {
+	crate::error::{Error, Result},
+	chrono::Utc,
+	gokz_rs::{global_api, Mode, SteamID, Tier},
+	poise::serenity_prelude::{ChannelId, CreateEmbed, Http},
+	rand::{seq::SliceRandom, Rng},
+	schnosebot::{
+		commands::Record,
+		formatting::fmt_time,
+		global_maps::{GlobalMap, MapCache},
+	},
+	sqlx::{FromRow, MySql, Pool, QueryBuilder},
+	std::{collections::HashSet, sync::Arc, time::Duration},
+	tokio::task::JoinHandle,
+	tracing::{error, info, warn},
+};
+
+/// Table for storing each guild's settings.
+pub const SETTINGS_TABLE: &str = "motw_settings";
+
+/// Table for storing current and past challenges.
+pub const CHALLENGES_TABLE: &str = "motw_challenges";
+
+/// Table for storing participants and their best times.
+pub const ENTRIES_TABLE: &str = "motw_entries";
+
+/// How often to check for new PBs and challenges that are over.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How many places to show in the standings.
+const LEADERBOARD_SIZE: usize = 10;
+
+/// `MySQL` schema for a settings row.
+#[derive(Debug, Clone, FromRow)]
+pub struct SettingsSchema {
+	pub guild_id: u64,
+	pub channel_id: u64,
+	pub mode: u8,
+	pub min_tier: Option<u8>,
+	pub max_tier: Option<u8>,
+	pub days: u8,
+}
+
+/// Parsed version of [`SettingsSchema`]. `None` means "no filter".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+	pub guild_id: u64,
+	pub channel_id: u64,
+	pub mode: Mode,
+	pub min_tier: Option<Tier>,
+	pub max_tier: Option<Tier>,
+
+	/// How long every challenge lasts.
+	pub days: u8,
+}
+
+impl TryFrom<SettingsSchema> for Settings {
+	type Error = crate::error::Error;
+
+	fn try_from(value: SettingsSchema) -> Result<Self> {
+		Ok(Self {
+			guild_id: value.guild_id,
+			channel_id: value.channel_id,
+			mode: Mode::try_from(value.mode)?,
+			min_tier: value
+				.min_tier
+				.and_then(|tier| Tier::try_from(tier).ok()),
+			max_tier: value
+				.max_tier
+				.and_then(|tier| Tier::try_from(tier).ok()),
+			days: value.days,
+		})
+	}
+}
+
+impl std::fmt::Display for Settings {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"<#{}>: {} ({}, {} days)",
+			self.channel_id,
+			self.title(),
+			self.mode.short(),
+			self.days
+		)?;
+
+		match (self.min_tier, self.max_tier) {
+			(None, None) => {}
+			(min, max) => write!(
+				f,
+				" | T{}-T{}",
+				min.map_or(1, |tier| tier as u8),
+				max.map_or(7, |tier| tier as u8)
+			)?,
+		}
+
+		Ok(())
+	}
+}
+
+impl Settings {
+	pub const fn title(&self) -> &'static str {
+		match self.days {
+			1 => "Map of the Day",
+			7 => "Map of the Week",
+			_ => "Map Challenge",
+		}
+	}
+
+	/// Whether `map` can be picked for a challenge.
+	pub fn allows(&self, map: &GlobalMap) -> bool {
+		let has_mode = match self.mode {
+			Mode::KZTimer => map.kzt,
+			Mode::SimpleKZ => map.skz,
+			Mode::Vanilla => map.vnl,
+		};
+
+		has_mode
+			&& self
+				.min_tier
+				.is_none_or(|min_tier| map.tier >= min_tier)
+			&& self
+				.max_tier
+				.is_none_or(|max_tier| map.tier <= max_tier)
+	}
+}
+
+/// Picks a random map that matches `settings` and is not in `history`. Once every matching map has
+/// been played, all of them are allowed again.
+pub fn pick_map<'maps>(
+	maps: &'maps [GlobalMap],
+	settings: &Settings,
+	history: &HashSet<u16>,
+	rng: &mut impl Rng,
+) -> Option<&'maps GlobalMap> {
+	let candidates = maps
+		.iter()
+		.filter(|map| settings.allows(map))
+		.collect::<Vec<_>>();
+
+	let fresh = candidates
+		.iter()
+		.copied()
+		.filter(|map| !history.contains(&map.id))
+		.collect::<Vec<_>>();
+
+	if fresh.is_empty() {
+		candidates.choose(rng).copied()
+	} else {
+		fresh.choose(rng).copied()
+	}
+}
+
+/// `MySQL` schema for a challenge row.
+#[derive(Debug, Clone, FromRow)]
+pub struct ChallengeSchema {
+	pub id: u32,
+	pub guild_id: u64,
+	pub map_id: u16,
+	pub map_name: String,
+	pub map_tier: u8,
+	pub mode: u8,
+	pub started_at: i64,
+	pub ends_at: i64,
+	pub finished: bool,
+}
+
+/// Parsed version of [`ChallengeSchema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+	pub id: u32,
+	pub guild_id: u64,
+	pub map_id: u16,
+	pub map_name: String,
+	pub map_tier: Tier,
+	pub mode: Mode,
+
+	/// Unix timestamp.
+	pub started_at: i64,
+
+	/// Unix timestamp.
+	pub ends_at: i64,
+	pub finished: bool,
+}
+
+impl TryFrom<ChallengeSchema> for Challenge {
+	type Error = crate::error::Error;
+
+	fn try_from(value: ChallengeSchema) -> Result<Self> {
+		Ok(Self {
+			id: value.id,
+			guild_id: value.guild_id,
+			map_id: value.map_id,
+			map_name: value.map_name,
+			map_tier: Tier::try_from(value.map_tier)?,
+			mode: Mode::try_from(value.mode)?,
+			started_at: value.started_at,
+			ends_at: value.ends_at,
+			finished: value.finished,
+		})
+	}
+}
+
+impl Challenge {
+	/// Whether `record` was set on the right map, in the right mode and during the challenge.
+	pub fn counts(&self, record: &Record) -> bool {
+		let created_on = record.created_on.and_utc().timestamp();
+
+		record.map_name == self.map_name
+			&& record.course == 0
+			&& record.mode == self.mode
+			&& (self.started_at..=self.ends_at).contains(&created_on)
+	}
+
+	pub fn map_url(&self) -> String {
+		format!("https://kzgo.eu/maps/{}?{}=", self.map_name, self.mode.short().to_lowercase())
+	}
+}
+
+/// `MySQL` schema for a participant row.
+#[derive(Debug, Clone, FromRow)]
+pub struct EntrySchema {
+	pub challenge_id: u32,
+	pub discord_id: u64,
+	pub steam_id: String,
+	pub name: String,
+	pub tp_time: Option<f64>,
+	pub tp_teleports: Option<u32>,
+	pub pro_time: Option<f64>,
+}
+
+/// Parsed version of [`EntrySchema`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+	pub challenge_id: u32,
+	pub discord_id: u64,
+
+	/// `None` if the stored SteamID is invalid; those entries are never updated.
+	pub steam_id: Option<SteamID>,
+	pub name: String,
+
+	/// Best TP time and how many teleports it took.
+	pub tp: Option<(f64, u32)>,
+
+	/// Best PRO time.
+	pub pro: Option<f64>,
+}
+
+impl From<EntrySchema> for Entry {
+	fn from(value: EntrySchema) -> Self {
+		Self {
+			challenge_id: value.challenge_id,
+			discord_id: value.discord_id,
+			steam_id: SteamID::new(&value.steam_id).ok(),
+			name: value.name,
+			tp: value
+				.tp_time
+				.map(|time| (time, value.tp_teleports.unwrap_or_default())),
+			pro: value.pro_time,
+		}
+	}
+}
+
+impl Entry {
+	/// Keeps `record` if it's faster than the current best time for its runtype. Returns `true` if
+	/// anything changed.
+	pub fn record(&mut self, record: &Record) -> bool {
+		if record.is_tp() {
+			if self
+				.tp
+				.is_some_and(|(time, _)| time <= record.time)
+			{
+				return false;
+			}
+
+			self.tp = Some((record.time, record.teleports));
+		} else {
+			if self
+				.pro
+				.is_some_and(|time| time <= record.time)
+			{
+				return false;
+			}
+
+			self.pro = Some(record.time);
+		}
+
+		true
+	}
+}
+
+/// Participants with a time for the given runtype, fastest first.
+pub fn leaderboard(entries: &[Entry], tp: bool) -> Vec<(&Entry, f64)> {
+	let mut leaderboard = entries
+		.iter()
+		.filter_map(|entry| {
+			let time = if tp { entry.tp.map(|(time, _)| time) } else { entry.pro };
+			Some((entry, time?))
+		})
+		.collect::<Vec<_>>();
+
+	leaderboard.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+	leaderboard
+}
+
+fn fmt_leaderboard(entries: &[Entry], tp: bool) -> String {
+	let leaderboard = leaderboard(entries, tp);
+
+	if leaderboard.is_empty() {
+		return String::from("No times yet.");
+	}
+
+	leaderboard
+		.into_iter()
+		.take(LEADERBOARD_SIZE)
+		.enumerate()
+		.map(|(i, (entry, time))| {
+			let place = match i {
+				0 => String::from("🥇"),
+				1 => String::from("🥈"),
+				2 => String::from("🥉"),
+				i => format!("#{}", i + 1),
+			};
+			let teleports = match entry.tp {
+				Some((_, 1)) if tp => String::from(" (1 TP)"),
+				Some((_, n)) if tp => format!(" ({n} TPs)"),
+				_ => String::new(),
+			};
+
+			format!("{place} {} - {}{teleports}", entry.name, fmt_time(time))
+		})
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// Embed for announcing a challenge or showing the current standings.
+pub fn challenge_embed(
+	challenge: &Challenge,
+	title: &str,
+	entries: &[Entry],
+	color: (u8, u8, u8),
+) -> CreateEmbed {
+	let mut embed = CreateEmbed::default();
+	embed
+		.color(color)
+		.title(format!(
+			"{title}: {} (T{}, {})",
+			challenge.map_name,
+			challenge.map_tier as u8,
+			challenge.mode.short()
+		))
+		.url(challenge.map_url())
+		.thumbnail(format!(
+			"https://raw.githubusercontent.com/KZGlobalTeam/map-images/master/images/{}.jpg",
+			challenge.map_name
+		));
+
+	if challenge.finished {
+		embed.description("The challenge is over! Here are the final standings:");
+	} else {
+		embed.description(format!(
+			"Ends <t:{}:R>. Use `/motw join` to take part; new PBs you set on the main course until \
+			 then will show up here. If you already finished this map before, only runs faster \
+			 than your old PB count.",
+			challenge.ends_at
+		));
+	}
+
+	embed
+		.field("TP", fmt_leaderboard(entries, true), true)
+		.field("PRO", fmt_leaderboard(entries, false), true);
+
+	embed
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn settings(database: &Pool<MySql>) -> Result<Vec<Settings>> {
+	sqlx::query_as::<_, SettingsSchema>(&format!("SELECT * FROM {SETTINGS_TABLE}"))
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(Settings::try_from)
+		.collect()
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn guild_settings(database: &Pool<MySql>, guild_id: u64) -> Result<Option<Settings>> {
+	let mut query = QueryBuilder::new(format!("SELECT * FROM {SETTINGS_TABLE} WHERE guild_id = "));
+	query.push_bind(guild_id);
+
+	query
+		.build_query_as::<SettingsSchema>()
+		.fetch_optional(database)
+		.await?
+		.map(Settings::try_from)
+		.transpose()
+}
+
+/// Creates the guild's settings or replaces the existing ones. Changes apply to the next challenge.
+#[tracing::instrument(skip(database))]
+pub async fn save_settings(database: &Pool<MySql>, settings: &Settings) -> Result<()> {
+	let mut query = QueryBuilder::new(format!(
+		"INSERT INTO {SETTINGS_TABLE} (guild_id, channel_id, mode, min_tier, max_tier, days) "
+	));
+
+	query
+		.push_values([settings], |mut query, settings| {
+			query
+				.push_bind(settings.guild_id)
+				.push_bind(settings.channel_id)
+				.push_bind(settings.mode as u8)
+				.push_bind(settings.min_tier.map(|tier| tier as u8))
+				.push_bind(settings.max_tier.map(|tier| tier as u8))
+				.push_bind(settings.days);
+		})
+		.push(
+			r#"
+			ON DUPLICATE KEY UPDATE
+			    channel_id = VALUES(channel_id),
+			    mode = VALUES(mode),
+			    min_tier = VALUES(min_tier),
+			    max_tier = VALUES(max_tier),
+			    days = VALUES(days)
+			"#,
+		);
+
+	query.build().execute(database).await?;
+
+	Ok(())
+}
+
+/// Deletes the guild's settings and ends its current challenge without posting results. Returns
+/// `false` if there were no settings to delete.
+#[tracing::instrument(skip(database))]
+pub async fn delete_settings(database: &Pool<MySql>, guild_id: u64) -> Result<bool> {
+	let mut query = QueryBuilder::new(format!("DELETE FROM {SETTINGS_TABLE} WHERE guild_id = "));
+	query.push_bind(guild_id);
+
+	let result = query.build().execute(database).await?;
+
+	if let Some(challenge) = active_challenge(database, guild_id).await? {
+		finish_challenge(database, challenge.id).await?;
+	}
+
+	Ok(result.rows_affected() > 0)
+}
+
+/// The guild's challenge that hasn't finished yet, if any.
+#[tracing::instrument(skip(database))]
+pub async fn active_challenge(database: &Pool<MySql>, guild_id: u64) -> Result<Option<Challenge>> {
+	let mut query =
+		QueryBuilder::new(format!("SELECT * FROM {CHALLENGES_TABLE} WHERE guild_id = "));
+	query
+		.push_bind(guild_id)
+		.push(" AND finished = FALSE ORDER BY id DESC LIMIT 1");
+
+	query
+		.build_query_as::<ChallengeSchema>()
+		.fetch_optional(database)
+		.await?
+		.map(Challenge::try_from)
+		.transpose()
+}
+
+/// IDs of every map the guild has had a challenge on.
+#[tracing::instrument(skip(database))]
+pub async fn history(database: &Pool<MySql>, guild_id: u64) -> Result<HashSet<u16>> {
+	let mut query =
+		QueryBuilder::new(format!("SELECT map_id FROM {CHALLENGES_TABLE} WHERE guild_id = "));
+	query.push_bind(guild_id);
+
+	Ok(query
+		.build_query_as::<(u16,)>()
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(|(map_id,)| map_id)
+		.collect())
+}
+
+/// Creates a new challenge on `map`. Participants of the guild's previous challenge are carried
+/// over, so nobody has to join again every week. Fails if the guild already has a challenge that
+/// hasn't finished yet.
+#[tracing::instrument(skip(database, map), fields(map = %map.name))]
+pub async fn start_challenge(
+	database: &Pool<MySql>,
+	settings: &Settings,
+	map: &GlobalMap,
+	started_at: i64,
+) -> Result<Challenge> {
+	let previous = {
+		let mut query =
+			QueryBuilder::new(format!("SELECT MAX(id) FROM {CHALLENGES_TABLE} WHERE guild_id = "));
+		query.push_bind(settings.guild_id);
+
+		query
+			.build_query_as::<(Option<u32>,)>()
+			.fetch_one(database)
+			.await?
+			.0
+	};
+
+	let challenge = Challenge {
+		id: 0,
+		guild_id: settings.guild_id,
+		map_id: map.id,
+		map_name: map.name.clone(),
+		map_tier: map.tier,
+		mode: settings.mode,
+		started_at,
+		ends_at: started_at + i64::from(settings.days) * 24 * 60 * 60,
+		finished: false,
+	};
+
+	// `/motw setup` and the scheduler can race each other, so the check for a running challenge
+	// has to be part of the insert.
+	let mut query = QueryBuilder::new(format!(
+		"INSERT INTO {CHALLENGES_TABLE} \
+		 (guild_id, map_id, map_name, map_tier, mode, started_at, ends_at) SELECT "
+	));
+
+	let mut values = query.separated(", ");
+	values
+		.push_bind(challenge.guild_id)
+		.push_bind(challenge.map_id)
+		.push_bind(&challenge.map_name)
+		.push_bind(challenge.map_tier as u8)
+		.push_bind(challenge.mode as u8)
+		.push_bind(challenge.started_at)
+		.push_bind(challenge.ends_at);
+
+	query
+		.push(format!(
+			" FROM DUAL WHERE NOT EXISTS (SELECT 1 FROM (SELECT id FROM {CHALLENGES_TABLE} \
+			 WHERE finished = FALSE AND guild_id = "
+		))
+		.push_bind(challenge.guild_id)
+		.push(") AS active)");
+
+	let result = query.build().execute(database).await?;
+
+	if result.rows_affected() == 0 {
+		return Err(Error::Custom(String::from("This server already has a challenge running.")));
+	}
+
+	let id = result.last_insert_id() as u32;
+
+	if let Some(previous) = previous {
+		let mut query = QueryBuilder::new(format!(
+			"INSERT INTO {ENTRIES_TABLE} (challenge_id, discord_id, steam_id, name) SELECT "
+		));
+		query
+			.push_bind(id)
+			.push(format!(
+				", discord_id, steam_id, name FROM {ENTRIES_TABLE} WHERE challenge_id = "
+			))
+			.push_bind(previous);
+
+		query.build().execute(database).await?;
+	}
+
+	Ok(Challenge { id, ..challenge })
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn finish_challenge(database: &Pool<MySql>, challenge_id: u32) -> Result<()> {
+	let mut query =
+		QueryBuilder::new(format!("UPDATE {CHALLENGES_TABLE} SET finished = TRUE WHERE id = "));
+	query.push_bind(challenge_id);
+
+	query.build().execute(database).await?;
+
+	Ok(())
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn entries(database: &Pool<MySql>, challenge_id: u32) -> Result<Vec<Entry>> {
+	let mut query =
+		QueryBuilder::new(format!("SELECT * FROM {ENTRIES_TABLE} WHERE challenge_id = "));
+	query.push_bind(challenge_id);
+
+	Ok(query
+		.build_query_as::<EntrySchema>()
+		.fetch_all(database)
+		.await?
+		.into_iter()
+		.map(Entry::from)
+		.collect())
+}
+
+/// Returns `false` if the user already takes part.
+#[tracing::instrument(skip(database))]
+pub async fn join(
+	database: &Pool<MySql>,
+	challenge_id: u32,
+	discord_id: u64,
+	name: &str,
+	steam_id: &SteamID,
+) -> Result<bool> {
+	let mut query = QueryBuilder::new(format!(
+		"INSERT IGNORE INTO {ENTRIES_TABLE} (challenge_id, discord_id, steam_id, name) "
+	));
+
+	query.push_values([(challenge_id, discord_id, name, steam_id)], |mut query, entry| {
+		query
+			.push_bind(entry.0)
+			.push_bind(entry.1)
+			.push_bind(entry.2)
+			.push_bind(entry.3.to_string());
+	});
+
+	let result = query.build().execute(database).await?;
+
+	Ok(result.rows_affected() > 0)
+}
+
+/// Returns `false` if the user didn't take part.
+#[tracing::instrument(skip(database))]
+pub async fn leave(database: &Pool<MySql>, challenge_id: u32, discord_id: u64) -> Result<bool> {
+	let mut query = QueryBuilder::new(format!("DELETE FROM {ENTRIES_TABLE} WHERE challenge_id = "));
+	query
+		.push_bind(challenge_id)
+		.push(" AND discord_id = ")
+		.push_bind(discord_id);
+
+	let result = query.build().execute(database).await?;
+
+	Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(skip(database))]
+pub async fn save_entry(database: &Pool<MySql>, entry: &Entry) -> Result<()> {
+	let mut query = QueryBuilder::new(format!("UPDATE {ENTRIES_TABLE} SET tp_time = "));
+	query
+		.push_bind(entry.tp.map(|(time, _)| time))
+		.push(", tp_teleports = ")
+		.push_bind(entry.tp.map(|(_, teleports)| teleports))
+		.push(", pro_time = ")
+		.push_bind(entry.pro)
+		.push(" WHERE challenge_id = ")
+		.push_bind(entry.challenge_id)
+		.push(" AND discord_id = ")
+		.push_bind(entry.discord_id);
+
+	query.build().execute(database).await?;
+
+	Ok(())
+}
+
+/// Picks a map for the guild's next challenge and announces it. Returns `None` if no map matches
+/// the guild's filters.
+#[tracing::instrument(skip(http, database, maps))]
+pub async fn start(
+	http: &Http,
+	database: &Pool<MySql>,
+	maps: &[GlobalMap],
+	settings: &Settings,
+	color: (u8, u8, u8),
+) -> Result<Option<Challenge>> {
+	let history = history(database, settings.guild_id).await?;
+	let Some(map) = pick_map(maps, settings, &history, &mut rand::thread_rng()).cloned() else {
+		return Ok(None);
+	};
+
+	let challenge = start_challenge(database, settings, &map, Utc::now().timestamp()).await?;
+	let entries = entries(database, challenge.id).await?;
+
+	info!("Started challenge #{} on `{}` in {}.", challenge.id, map.name, settings.guild_id);
+
+	let embed = challenge_embed(&challenge, settings.title(), &entries, color);
+	if let Err(why) = ChannelId(settings.channel_id)
+		.send_message(http, |msg| msg.set_embed(embed))
+		.await
+	{
+		warn!("Failed to announce challenge in <#{}>: {why:?}", settings.channel_id);
+	}
+
+	Ok(Some(challenge))
+}
+
+/// Fetches every participant's PBs and stores the ones that count for `challenge`. PBs from before
+/// the challenge don't count, and neither do slower runs that never became a PB.
+#[tracing::instrument(skip(database, gokz_client))]
+async fn update_entries(
+	database: &Pool<MySql>,
+	gokz_client: &gokz_rs::Client,
+	challenge: &Challenge,
+) -> Result<Vec<Entry>> {
+	let mut entries = entries(database, challenge.id).await?;
+
+	for entry in &mut entries {
+		let Some(steam_id) = entry.steam_id else {
+			continue;
+		};
+
+		let (tp, pro) = tokio::join!(
+			global_api::get_pb(
+				steam_id.into(),
+				challenge.map_id.into(),
+				challenge.mode,
+				true,
+				0,
+				gokz_client
+			),
+			global_api::get_pb(
+				steam_id.into(),
+				challenge.map_id.into(),
+				challenge.mode,
+				false,
+				0,
+				gokz_client
+			),
+		);
+
+		let mut changed = false;
+		for record in [tp, pro].into_iter().flatten() {
+			let record = Record::from(record);
+			if challenge.counts(&record) {
+				changed |= entry.record(&record);
+			}
+		}
+
+		if changed {
+			save_entry(database, entry).await?;
+		}
+	}
+
+	Ok(entries)
+}
+
+/// Does whatever is due for a single guild: start the first challenge, track PBs, or post the
+/// results and move on to the next map.
+#[tracing::instrument(skip(http, database, gokz_client, maps))]
+async fn check_guild(
+	http: &Http,
+	database: &Pool<MySql>,
+	gokz_client: &gokz_rs::Client,
+	maps: &[GlobalMap],
+	settings: &Settings,
+	color: (u8, u8, u8),
+) -> Result<()> {
+	let Some(challenge) = active_challenge(database, settings.guild_id).await? else {
+		if start(http, database, maps, settings, color)
+			.await?
+			.is_none()
+		{
+			warn!("No maps match the settings of {}.", settings.guild_id);
+		}
+
+		return Ok(());
+	};
+
+	let entries = update_entries(database, gokz_client, &challenge).await?;
+
+	if Utc::now().timestamp() < challenge.ends_at {
+		return Ok(());
+	}
+
+	finish_challenge(database, challenge.id).await?;
+	info!("Finished challenge #{} on `{}`.", challenge.id, challenge.map_name);
+
+	let challenge = Challenge { finished: true, ..challenge };
+	let embed = challenge_embed(&challenge, settings.title(), &entries, color);
+	if let Err(why) = ChannelId(settings.channel_id)
+		.send_message(http, |msg| msg.set_embed(embed))
+		.await
+	{
+		warn!("Failed to post results in <#{}>: {why:?}", settings.channel_id);
+	}
+
+	if start(http, database, maps, settings, color)
+		.await?
+		.is_none()
+	{
+		warn!("No maps match the settings of {}.", settings.guild_id);
+	}
+
+	Ok(())
+}
+
+/// Checks every guild's challenge every `interval`.
+pub fn spawn_scheduler(
+	http: Arc<Http>,
+	database: Pool<MySql>,
+	gokz_client: gokz_rs::Client,
+	global_maps: MapCache,
+	color: (u8, u8, u8),
+	interval: Duration,
+) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(interval);
+
+		loop {
+			interval.tick().await;
+
+			let settings = match settings(&database).await {
+				Ok(settings) => settings,
+				Err(why) => {
+					error!("Failed to fetch challenge settings: {why:?}");
+					continue;
+				}
+			};
+
+			let maps = global_maps.maps();
+			for settings in &settings {
+				if let Err(why) =
+					check_guild(&http, &database, &gokz_client, &maps, settings, color).await
+				{
+					warn!("Failed to check challenge for {}: {why:?}", settings.guild_id);
+				}
+			}
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use {
+		super::*,
+		chrono::{DateTime, NaiveDateTime},
+		rand::SeedableRng,
+	};
+
+	fn global_map(id: u16, name: &str, tier: Tier, vnl: bool) -> GlobalMap {
+		GlobalMap {
+			id,
+			name: name.to_owned(),
+			tier,
+			courses: Vec::new(),
+			kzt: true,
+			skz: true,
+			vnl,
+			mapper_name: String::from("AlphaKeks"),
+			mapper_steam_id: None,
+			filesize: 0,
+			validated: true,
+			created_on: NaiveDateTime::default(),
+			updated_on: NaiveDateTime::default(),
+			url: format!("https://kzgo.eu/maps/{name}"),
+			thumbnail: String::new(),
+		}
+	}
+
+	fn settings(mode: Mode, min_tier: Option<Tier>, max_tier: Option<Tier>) -> Settings {
+		Settings {
+			guild_id: 0,
+			channel_id: 0,
+			mode,
+			min_tier,
+			max_tier,
+			days: 7,
+		}
+	}
+
+	fn record(time: f64, teleports: u32, created_on: i64) -> Record {
+		Record {
+			id: 1,
+			player_name: String::from("AlphaKeks"),
+			steam_id: SteamID::new("STEAM_1:1:161178172").unwrap(),
+			map_name: String::from("kz_lionharder"),
+			course: 0,
+			mode: Mode::SimpleKZ,
+			time,
+			teleports,
+			place: None,
+			replay: None,
+			created_on: DateTime::from_timestamp(created_on, 0)
+				.unwrap()
+				.naive_utc(),
+		}
+	}
+
+	fn entry(name: &str, tp: Option<(f64, u32)>, pro: Option<f64>) -> Entry {
+		Entry {
+			challenge_id: 1,
+			discord_id: 0,
+			steam_id: None,
+			name: name.to_owned(),
+			tp,
+			pro,
+		}
+	}
+
+	#[test]
+	fn pick_maps() {
+		let maps = [
+			global_map(1, "kz_beginnerblock_go", Tier::VeryEasy, true),
+			global_map(2, "kz_checkmate", Tier::Medium, false),
+			global_map(3, "kz_lionharder", Tier::VeryHard, false),
+		];
+		let mut rng = rand::rngs::StdRng::seed_from_u64(727);
+
+		let vnl = settings(Mode::Vanilla, None, None);
+		for _ in 0..10 {
+			let map = pick_map(&maps, &vnl, &HashSet::new(), &mut rng).unwrap();
+			assert_eq!(map.id, 1);
+		}
+
+		let hard = settings(Mode::SimpleKZ, Some(Tier::Hard), Some(Tier::Death));
+		assert_eq!(
+			pick_map(&maps, &hard, &HashSet::new(), &mut rng)
+				.unwrap()
+				.id,
+			3
+		);
+
+		let none = settings(Mode::KZTimer, Some(Tier::Death), None);
+		assert!(pick_map(&maps, &none, &HashSet::new(), &mut rng).is_none());
+
+		// No repeats until every map has been played.
+		let skz = settings(Mode::SimpleKZ, None, None);
+		let history = HashSet::from([1, 3]);
+		for _ in 0..10 {
+			assert_eq!(
+				pick_map(&maps, &skz, &history, &mut rng)
+					.unwrap()
+					.id,
+				2
+			);
+		}
+
+		let history = HashSet::from([1, 2, 3]);
+		assert!(pick_map(&maps, &skz, &history, &mut rng).is_some());
+	}
+
+	#[test]
+	fn count_records() {
+		let challenge = Challenge {
+			id: 1,
+			guild_id: 0,
+			map_id: 1,
+			map_name: String::from("kz_lionharder"),
+			map_tier: Tier::VeryHard,
+			mode: Mode::SimpleKZ,
+			started_at: 1_000,
+			ends_at: 2_000,
+			finished: false,
+		};
+
+		assert!(challenge.counts(&record(727.0, 0, 1_500)));
+		assert!(!challenge.counts(&record(727.0, 0, 500)));
+		assert!(!challenge.counts(&record(727.0, 0, 2_500)));
+
+		let kzt = Record {
+			mode: Mode::KZTimer,
+			..record(727.0, 0, 1_500)
+		};
+		assert!(!challenge.counts(&kzt));
+
+		let bonus = Record { course: 1, ..record(727.0, 0, 1_500) };
+		assert!(!challenge.counts(&bonus));
+	}
+
+	#[test]
+	fn keep_best_times() {
+		let mut entry = entry("AlphaKeks", None, None);
+
+		assert!(entry.record(&record(800.0, 3, 0)));
+		assert!(entry.record(&record(700.0, 0, 0)));
+		assert!(!entry.record(&record(900.0, 1, 0)));
+		assert!(!entry.record(&record(700.0, 0, 0)));
+		assert!(entry.record(&record(750.0, 1, 0)));
+
+		assert_eq!(entry.tp, Some((750.0, 1)));
+		assert_eq!(entry.pro, Some(700.0));
+	}
+
+	#[test]
+	fn leaderboard_order() {
+		let entries = [
+			entry("a", Some((300.0, 5)), None),
+			entry("b", Some((200.0, 2)), Some(400.0)),
+			entry("c", None, Some(350.0)),
+			entry("d", None, None),
+		];
+
+		let names = |tp| {
+			leaderboard(&entries, tp)
+				.into_iter()
+				.map(|(entry, _)| entry.name.as_str())
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(names(true), ["b", "a"]);
+		assert_eq!(names(false), ["c", "b"]);
+	}
+}
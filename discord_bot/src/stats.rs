@@ -0,0 +1,399 @@
+//! Cached stats of every registered player, used for server leaderboards. Fetching the records of
+//! every registered player for every `/serverlb` would take hundreds of GlobalAPI requests, so a
+//! background task collects them once every [`DEFAULT_REFRESH_INTERVAL`] instead.
+
+use {
+	crate::{db::UserRepository, error::Result},
+	chrono::{DateTime, Utc},
+	gokz_rs::{global_api, kzgo_api::CompletionStats, Mode, SteamID, Tier},
+	schnosebot::{
+		commands::{no_records_ok, profile::ProfileStats},
+		global_maps::{GlobalMap, MapCache},
+	},
+	std::{
+		collections::{HashMap, HashSet},
+		sync::{Arc, RwLock},
+		time::Duration,
+	},
+	tokio::task::JoinHandle,
+	tracing::{error, info, warn},
+};
+
+/// How often to re-fetch every player's records. This defaults to 1 hour.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Pause between players so a refresh doesn't hammer the GlobalAPI.
+const PLAYER_DELAY: Duration = Duration::from_millis(500);
+
+const MODES: [Mode; 3] = [
+	Mode::KZTimer,
+	Mode::SimpleKZ,
+	Mode::Vanilla,
+];
+
+/// A single player's stats in a single mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStats {
+	/// The player's most recent in-game name.
+	pub name: String,
+	pub steam_id: SteamID,
+	pub mode: Mode,
+	pub stats: ProfileStats,
+
+	/// Map ID -> PB on the main course.
+	pub tp_times: HashMap<u16, f64>,
+
+	/// Map ID -> PB on the main course.
+	pub pro_times: HashMap<u16, f64>,
+}
+
+impl PlayerStats {
+	/// `None` if the player has no records in `mode`.
+	pub fn new(
+		steam_id: SteamID,
+		mode: Mode,
+		tp: &[global_api::Record],
+		pro: &[global_api::Record],
+		map_pool: &[GlobalMap],
+	) -> Option<Self> {
+		let name = tp
+			.iter()
+			.chain(pro)
+			.max_by_key(|record| record.created_on)?
+			.player_name
+			.clone();
+
+		let times = |records: &[global_api::Record]| {
+			records
+				.iter()
+				.map(|record| (record.map_id, record.time))
+				.collect()
+		};
+
+		Some(Self {
+			name,
+			steam_id,
+			mode,
+			stats: ProfileStats::compute(tp, pro, map_pool),
+			tp_times: times(tp),
+			pro_times: times(pro),
+		})
+	}
+}
+
+/// What to rank players by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+	Points,
+	Wrs,
+
+	/// `None` means all tiers.
+	Completion(Option<Tier>),
+
+	/// PBs on the map with this ID.
+	Map(u16),
+}
+
+impl Category {
+	/// The value to rank `player` by. `tp` picks the runtype; `None` adds up TP and PRO, except
+	/// for map times, where it means PRO. Returns `None` if the player has nothing to show.
+	pub fn value(&self, player: &PlayerStats, tp: Option<bool>) -> Option<f64> {
+		let pick = |(tp_value, pro_value): (u32, u32)| match tp {
+			None => tp_value + pro_value,
+			Some(true) => tp_value,
+			Some(false) => pro_value,
+		};
+
+		let value = match self {
+			Self::Points => pick(player.stats.points),
+			Self::Wrs => pick(player.stats.wrs),
+			Self::Completion(tier) => {
+				pick(player.stats.completions[tier.map_or(0, |tier| tier as usize)])
+			}
+			Self::Map(map_id) => {
+				let times = if tp == Some(true) { &player.tp_times } else { &player.pro_times };
+				return times.get(map_id).copied();
+			}
+		};
+
+		(value > 0).then_some(value as f64)
+	}
+
+	/// Lower values are better for map times and worse for everything else.
+	pub const fn ascending(&self) -> bool {
+		matches!(self, Self::Map(_))
+	}
+}
+
+/// Every player in `players` with a value for `category`, best first.
+pub fn leaderboard(
+	players: &[PlayerStats],
+	category: Category,
+	tp: Option<bool>,
+) -> Vec<(&PlayerStats, f64)> {
+	let mut leaderboard = players
+		.iter()
+		.filter_map(|player| Some((player, category.value(player, tp)?)))
+		.collect::<Vec<_>>();
+
+	leaderboard.sort_by(
+		|(_, a), (_, b)| {
+			if category.ascending() {
+				a.total_cmp(b)
+			} else {
+				b.total_cmp(a)
+			}
+		},
+	);
+
+	leaderboard
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+	players: HashMap<(SteamID, Mode), PlayerStats>,
+	completion_stats: HashMap<Mode, CompletionStats>,
+	updated_at: Option<DateTime<Utc>>,
+}
+
+/// Stats of every registered player. Cloning this is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct StatsCache {
+	inner: Arc<RwLock<Inner>>,
+}
+
+impl StatsCache {
+	/// The cached stats of everyone in `steam_ids` who has records in `mode`.
+	pub fn players(&self, mode: Mode, steam_ids: &HashSet<SteamID>) -> Vec<PlayerStats> {
+		self.inner
+			.read()
+			.expect("Stats cache lock poisoned.")
+			.players
+			.values()
+			.filter(|player| player.mode == mode && steam_ids.contains(&player.steam_id))
+			.cloned()
+			.collect()
+	}
+
+	/// How many maps there are to complete in `mode`.
+	pub fn completion_stats(&self, mode: Mode) -> Option<CompletionStats> {
+		self.inner
+			.read()
+			.expect("Stats cache lock poisoned.")
+			.completion_stats
+			.get(&mode)
+			.cloned()
+	}
+
+	/// When the last refresh finished. `None` until the first one is done.
+	pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+		self.inner
+			.read()
+			.expect("Stats cache lock poisoned.")
+			.updated_at
+	}
+
+	/// Updates (or removes) a single player's stats.
+	pub fn insert(&self, steam_id: SteamID, mode: Mode, stats: Option<PlayerStats>) {
+		let mut inner = self
+			.inner
+			.write()
+			.expect("Stats cache lock poisoned.");
+
+		match stats {
+			Some(stats) => inner
+				.players
+				.insert((steam_id, mode), stats),
+			None => inner.players.remove(&(steam_id, mode)),
+		};
+	}
+
+	/// Re-fetches the records of every registered player. Players whose records can't be fetched
+	/// keep their old stats. Returns how many players were refreshed.
+	#[tracing::instrument(skip_all)]
+	pub async fn refresh(
+		&self,
+		users: &dyn UserRepository,
+		gokz_client: &gokz_rs::Client,
+		map_pool: &[GlobalMap],
+	) -> Result<usize> {
+		for mode in MODES {
+			match gokz_rs::kzgo_api::get_completions(mode, gokz_client).await {
+				Ok(completion_stats) => {
+					self.inner
+						.write()
+						.expect("Stats cache lock poisoned.")
+						.completion_stats
+						.insert(mode, completion_stats);
+				}
+				Err(why) => warn!("Failed to fetch completion stats for {mode}: {why:?}"),
+			}
+		}
+
+		let steam_ids = users
+			.with_steam_id()
+			.await?
+			.into_iter()
+			.filter_map(|user| user.steam_id)
+			.collect::<HashSet<_>>();
+
+		let mut refreshed = 0;
+		for steam_id in &steam_ids {
+			let mut failed = false;
+
+			for mode in MODES {
+				let (tp, pro) = tokio::join!(
+					global_api::get_player_records(
+						(*steam_id).into(),
+						mode,
+						true,
+						0,
+						9999,
+						gokz_client
+					),
+					global_api::get_player_records(
+						(*steam_id).into(),
+						mode,
+						false,
+						0,
+						9999,
+						gokz_client
+					),
+				);
+
+				match (no_records_ok(tp), no_records_ok(pro)) {
+					(Ok(tp), Ok(pro)) => {
+						let stats = PlayerStats::new(*steam_id, mode, &tp, &pro, map_pool);
+						self.insert(*steam_id, mode, stats);
+					}
+					(Err(why), _) | (_, Err(why)) => {
+						warn!("Failed to fetch {mode} records of {steam_id}: {why:?}");
+						failed = true;
+					}
+				}
+			}
+
+			if !failed {
+				refreshed += 1;
+			}
+
+			tokio::time::sleep(PLAYER_DELAY).await;
+		}
+
+		let mut inner = self
+			.inner
+			.write()
+			.expect("Stats cache lock poisoned.");
+
+		// Forget players who deleted their SteamID.
+		inner
+			.players
+			.retain(|(steam_id, _), _| steam_ids.contains(steam_id));
+		inner.updated_at = Some(Utc::now());
+
+		Ok(refreshed)
+	}
+
+	/// Spawns a background task that calls [`Self::refresh`] every `interval`, starting right away.
+	pub fn spawn_refresh_task(
+		&self,
+		users: Arc<dyn UserRepository>,
+		gokz_client: gokz_rs::Client,
+		global_maps: MapCache,
+		interval: Duration,
+	) -> JoinHandle<()> {
+		let cache = self.clone();
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(interval);
+
+			loop {
+				interval.tick().await;
+				match cache
+					.refresh(users.as_ref(), &gokz_client, &global_maps.maps())
+					.await
+				{
+					Ok(refreshed) => info!("Refreshed stats of {refreshed} players."),
+					Err(why) => error!("Failed to refresh player stats: {why:?}"),
+				}
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn player(
+		name: &str,
+		points: (u32, u32),
+		wrs: (u32, u32),
+		pro_time: Option<f64>,
+	) -> PlayerStats {
+		let mut completions = [(0, 0); 8];
+		completions[0] = (points.0 / 500, points.1 / 500);
+
+		PlayerStats {
+			name: name.to_owned(),
+			steam_id: SteamID::new("STEAM_1:1:161178172").unwrap(),
+			mode: Mode::SimpleKZ,
+			stats: ProfileStats { points, wrs, completions },
+			tp_times: HashMap::new(),
+			pro_times: pro_time
+				.map(|time| HashMap::from([(1, time)]))
+				.unwrap_or_default(),
+		}
+	}
+
+	fn names(leaderboard: Vec<(&PlayerStats, f64)>) -> Vec<&str> {
+		leaderboard
+			.into_iter()
+			.map(|(player, _)| player.name.as_str())
+			.collect()
+	}
+
+	#[test]
+	fn rank_players() {
+		let players = [
+			player("a", (5000, 1000), (0, 1), Some(120.0)),
+			player("b", (1000, 4000), (2, 0), None),
+			player("c", (0, 0), (0, 0), Some(60.0)),
+		];
+
+		assert_eq!(names(leaderboard(&players, Category::Points, None)), ["a", "b"]);
+		assert_eq!(names(leaderboard(&players, Category::Points, Some(false))), ["b", "a"]);
+		assert_eq!(names(leaderboard(&players, Category::Wrs, Some(true))), ["b"]);
+		assert_eq!(names(leaderboard(&players, Category::Completion(None), None)), ["a", "b"]);
+		assert!(leaderboard(&players, Category::Completion(Some(Tier::Death)), None).is_empty());
+
+		// Faster times first, and players without a time don't show up.
+		assert_eq!(names(leaderboard(&players, Category::Map(1), None)), ["c", "a"]);
+		assert!(leaderboard(&players, Category::Map(1), Some(true)).is_empty());
+	}
+
+	#[test]
+	fn cache_players() {
+		let cache = StatsCache::default();
+		let stats = player("a", (1, 1), (0, 0), None);
+		let steam_id = stats.steam_id;
+
+		cache.insert(steam_id, Mode::SimpleKZ, Some(stats));
+		assert_eq!(
+			cache
+				.players(Mode::SimpleKZ, &HashSet::from([steam_id]))
+				.len(),
+			1
+		);
+		assert!(cache
+			.players(Mode::KZTimer, &HashSet::from([steam_id]))
+			.is_empty());
+		assert!(cache
+			.players(Mode::SimpleKZ, &HashSet::new())
+			.is_empty());
+
+		cache.insert(steam_id, Mode::SimpleKZ, None);
+		assert!(cache
+			.players(Mode::SimpleKZ, &HashSet::from([steam_id]))
+			.is_empty());
+	}
+}
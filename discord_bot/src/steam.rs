@@ -1,72 +1,421 @@
-//! Steam WebAPI functions.
+//! Typed client for the parts of the Steam WebAPI we need: player summaries and vanity URL
+//! resolution. Responses are cached for [`DEFAULT_CACHE_TTL`] since Steam rate limits API keys.
 
 use {
 	crate::Error,
 	color_eyre::{eyre::eyre, Result as Eyre},
+	gokz_rs::SteamID,
 	serde::Deserialize,
-	serde_json::Value as JsonValue,
+	std::{
+		collections::{HashMap, HashSet},
+		sync::{Arc, RwLock},
+		time::{Duration, Instant},
+	},
 };
 
-#[tracing::instrument]
-pub async fn get_steam_avatar(
-	steam_api_key: &str,
-	steam_id64: u64,
-	client: &gokz_rs::Client,
-) -> Eyre<String> {
-	let mut response = client
-		.get(format!("https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v0002/?key={steam_api_key}&steamids={steam_id64}"))
-		.send()
-		.await?
-		.json::<Response>()
-		.await
-		.map_err(|_| Error::ParseJSON)
-		.map(|response| response.response.players)?;
-
-	let steam_user = if response.is_empty() {
-		return Err(eyre!("Empty response from SteamAPI."));
-	} else {
-		response.remove(0)
-	};
-
-	if let Some(avatar_url) = steam_user.avatarfull {
-		Ok(avatar_url)
-	} else if let Some(avatarmedium) = steam_user.avatarmedium {
-		Ok(avatarmedium)
-	} else if let Some(avatar) = steam_user.avatar {
-		Ok(avatar)
-	} else {
-		Err(eyre!("Could not find an avatar for `{steam_id64}`."))
+const BASE_URL: &str = "https://api.steampowered.com/ISteamUser";
+
+/// `GetPlayerSummaries` accepts at most this many SteamIDs per request.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// How long summaries and resolved vanity URLs stay cached. This defaults to 1 hour.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A user's public Steam profile.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PlayerSummary {
+	#[serde(rename = "steamid")]
+	pub steam_id: SteamID,
+
+	#[serde(rename = "personaname")]
+	pub persona_name: String,
+
+	#[serde(rename = "profileurl")]
+	pub profile_url: String,
+
+	/// 32x32
+	pub avatar: String,
+
+	/// 64x64
+	#[serde(rename = "avatarmedium")]
+	pub avatar_medium: String,
+
+	/// 184x184
+	#[serde(rename = "avatarfull")]
+	pub avatar_full: String,
+
+	/// ISO 3166 code of the country the user put on their profile.
+	#[serde(rename = "loccountrycode")]
+	pub country_code: Option<String>,
+
+	#[serde(rename = "realname")]
+	pub real_name: Option<String>,
+
+	/// Unix timestamp. Only visible on public profiles.
+	#[serde(rename = "timecreated")]
+	pub created_on: Option<i64>,
+}
+
+impl PlayerSummary {
+	/// The largest avatar Steam gave us.
+	pub fn avatar_url(&self) -> &str {
+		[
+			&self.avatar_full, &self.avatar_medium, &self.avatar,
+		]
+		.into_iter()
+		.find(|url| !url.is_empty())
+		.unwrap_or(&self.avatar)
+	}
+
+	/// The country flag emoji for [`Self::country_code`], if the user set one.
+	pub fn flag(&self) -> Option<String> {
+		let code = self.country_code.as_deref()?;
+
+		if code.len() != 2
+			|| !code
+				.chars()
+				.all(|c| c.is_ascii_alphabetic())
+		{
+			return None;
+		}
+
+		// Regional indicator symbols start at U+1F1E6 ('A').
+		code.to_ascii_uppercase()
+			.chars()
+			.map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+			.collect()
+	}
+}
+
+/// A link to somebody's Steam profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileUrl {
+	/// `steamcommunity.com/id/<vanity>`
+	Vanity(String),
+
+	/// `steamcommunity.com/profiles/<id64>`
+	SteamID(SteamID),
+}
+
+impl ProfileUrl {
+	/// Returns `None` if `input` is not a link to a Steam profile.
+	pub fn parse(input: &str) -> Option<Self> {
+		let path = input
+			.trim()
+			.trim_start_matches("https://")
+			.trim_start_matches("http://")
+			.trim_start_matches("www.")
+			.strip_prefix("steamcommunity.com/")?;
+
+		let mut segments = path.split(['/', '?', '#']);
+		let kind = segments.next()?;
+		let value = segments
+			.next()
+			.filter(|value| !value.is_empty())?;
+
+		match kind {
+			"id" => Some(Self::Vanity(value.to_owned())),
+			"profiles" => value
+				.parse::<u64>()
+				.ok()
+				.and_then(|steam_id64| SteamID::from_id64(steam_id64).ok())
+				.map(Self::SteamID),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+	summaries: HashMap<SteamID, (Instant, PlayerSummary)>,
+	vanity_urls: HashMap<String, (Instant, SteamID)>,
+}
+
+impl Cache {
+	/// Drops every entry older than `ttl` so the cache doesn't grow forever.
+	fn prune(&mut self, ttl: Duration) {
+		self.summaries
+			.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+		self.vanity_urls
+			.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+	}
+}
+
+/// Client for the Steam WebAPI. Cloning this is cheap and clones share their cache.
+#[derive(Debug, Clone)]
+pub struct SteamClient {
+	api_key: String,
+	http: gokz_rs::Client,
+	cache_ttl: Duration,
+	cache: Arc<RwLock<Cache>>,
+}
+
+impl SteamClient {
+	pub fn new(api_key: impl Into<String>, http: gokz_rs::Client) -> Self {
+		Self {
+			api_key: api_key.into(),
+			http,
+			cache_ttl: DEFAULT_CACHE_TTL,
+			cache: Arc::default(),
+		}
+	}
+
+	/// The summary of a single user.
+	#[tracing::instrument(skip(self))]
+	pub async fn get_player_summary(&self, steam_id: SteamID) -> Eyre<PlayerSummary> {
+		self.get_player_summaries(&[steam_id])
+			.await?
+			.remove(&steam_id)
+			.ok_or_else(|| eyre!("Could not find a Steam profile for `{steam_id}`."))
+	}
+
+	/// Summaries of every user in `steam_ids`, in batches of [`MAX_BATCH_SIZE`]. Users that Steam
+	/// doesn't know about are missing from the result.
+	#[tracing::instrument(skip(self))]
+	pub async fn get_player_summaries(
+		&self,
+		steam_ids: &[SteamID],
+	) -> Eyre<HashMap<SteamID, PlayerSummary>> {
+		let mut summaries = HashMap::new();
+		let mut missing = Vec::new();
+
+		for steam_id in steam_ids
+			.iter()
+			.copied()
+			.collect::<HashSet<_>>()
+		{
+			match self.cached_summary(steam_id) {
+				Some(summary) => {
+					summaries.insert(steam_id, summary);
+				}
+				None => missing.push(steam_id),
+			}
+		}
+
+		for batch in missing.chunks(MAX_BATCH_SIZE) {
+			let steam_ids = batch
+				.iter()
+				.map(|steam_id| steam_id.as_id64().to_string())
+				.collect::<Vec<_>>()
+				.join(",");
+
+			let players = self
+				.http
+				.get(format!("{BASE_URL}/GetPlayerSummaries/v0002/"))
+				.query(&[
+					("key", self.api_key.as_str()),
+					("steamids", &steam_ids),
+				])
+				.send()
+				.await?
+				.error_for_status()?
+				.json::<Response<PlayersResponse>>()
+				.await
+				.map_err(|_| Error::ParseJSON)?
+				.response
+				.players;
+
+			self.cache_summaries(&players);
+			summaries.extend(
+				players
+					.into_iter()
+					.map(|summary| (summary.steam_id, summary)),
+			);
+		}
+
+		Ok(summaries)
+	}
+
+	/// The largest avatar of a user.
+	pub async fn get_avatar(&self, steam_id: SteamID) -> Eyre<String> {
+		self.get_player_summary(steam_id)
+			.await
+			.map(|summary| summary.avatar_url().to_owned())
+	}
+
+	/// Turns the `<vanity>` in `steamcommunity.com/id/<vanity>` into a [`SteamID`].
+	#[tracing::instrument(skip(self))]
+	pub async fn resolve_vanity_url(&self, vanity: &str) -> Eyre<SteamID> {
+		let vanity = vanity.to_lowercase();
+
+		if let Some(steam_id) = self.cached_vanity_url(&vanity) {
+			return Ok(steam_id);
+		}
+
+		let response = self
+			.http
+			.get(format!("{BASE_URL}/ResolveVanityURL/v0001/"))
+			.query(&[
+				("key", self.api_key.as_str()),
+				("vanityurl", &vanity),
+			])
+			.send()
+			.await?
+			.error_for_status()?
+			.json::<Response<VanityResponse>>()
+			.await
+			.map_err(|_| Error::ParseJSON)?
+			.response;
+
+		let steam_id = match response {
+			VanityResponse { success: 1, steam_id: Some(steam_id), .. } => steam_id,
+			VanityResponse { message, .. } => {
+				return Err(eyre!(
+					"Could not resolve vanity URL `{vanity}`: {}",
+					message
+						.as_deref()
+						.unwrap_or("unknown error")
+				));
+			}
+		};
+
+		let mut cache = self
+			.cache
+			.write()
+			.expect("Steam cache lock poisoned.");
+
+		cache.prune(self.cache_ttl);
+		cache
+			.vanity_urls
+			.insert(vanity, (Instant::now(), steam_id));
+
+		Ok(steam_id)
+	}
+
+	fn cached_summary(&self, steam_id: SteamID) -> Option<PlayerSummary> {
+		self.cache
+			.read()
+			.expect("Steam cache lock poisoned.")
+			.summaries
+			.get(&steam_id)
+			.filter(|(cached_at, _)| cached_at.elapsed() < self.cache_ttl)
+			.map(|(_, summary)| summary.clone())
+	}
+
+	fn cache_summaries(&self, summaries: &[PlayerSummary]) {
+		let mut cache = self
+			.cache
+			.write()
+			.expect("Steam cache lock poisoned.");
+
+		cache.prune(self.cache_ttl);
+		for summary in summaries {
+			cache
+				.summaries
+				.insert(summary.steam_id, (Instant::now(), summary.clone()));
+		}
+	}
+
+	fn cached_vanity_url(&self, vanity: &str) -> Option<SteamID> {
+		self.cache
+			.read()
+			.expect("Steam cache lock poisoned.")
+			.vanity_urls
+			.get(vanity)
+			.filter(|(cached_at, _)| cached_at.elapsed() < self.cache_ttl)
+			.map(|(_, steam_id)| *steam_id)
 	}
 }
 
 #[derive(Debug, Clone, Deserialize)]
-#[allow(unused)]
-struct SteamUser {
-	steamid: Option<JsonValue>,
-	communityvisibilitystate: Option<JsonValue>,
-	profilestate: Option<JsonValue>,
-	personaname: Option<JsonValue>,
-	commentpermission: Option<JsonValue>,
-	profileurl: Option<JsonValue>,
-	pub avatar: Option<String>,
-	pub avatarmedium: Option<String>,
-	pub avatarfull: Option<String>,
-	avatarhash: Option<JsonValue>,
-	lastlogoff: Option<JsonValue>,
-	personastate: Option<JsonValue>,
-	realname: Option<JsonValue>,
-	primaryclanid: Option<JsonValue>,
-	timecreated: Option<JsonValue>,
-	personastateflags: Option<JsonValue>,
-	loccountrycode: Option<JsonValue>,
+struct Response<T> {
+	response: T,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct InnerResponse {
-	pub players: Vec<SteamUser>,
+struct PlayersResponse {
+	players: Vec<PlayerSummary>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct Response {
-	pub response: InnerResponse,
+struct VanityResponse {
+	success: u8,
+
+	#[serde(rename = "steamid")]
+	steam_id: Option<SteamID>,
+	message: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ALPHAKEKS: &str = "76561198282622073";
+
+	fn summary() -> PlayerSummary {
+		serde_json::from_str::<Response<PlayersResponse>>(&format!(
+			r#"{{"response":{{"players":[{{
+				"steamid": "{ALPHAKEKS}",
+				"communityvisibilitystate": 3,
+				"personaname": "AlphaKeks",
+				"profileurl": "https://steamcommunity.com/id/AlphaKeks/",
+				"avatar": "small.jpg",
+				"avatarmedium": "medium.jpg",
+				"avatarfull": "full.jpg",
+				"loccountrycode": "de",
+				"timecreated": 1457366540
+			}}]}}}}"#
+		))
+		.unwrap()
+		.response
+		.players
+		.remove(0)
+	}
+
+	#[test]
+	fn parse_profile_urls() {
+		let steam_id = SteamID::new(ALPHAKEKS).unwrap();
+
+		assert_eq!(
+			ProfileUrl::parse("https://steamcommunity.com/id/AlphaKeks/"),
+			Some(ProfileUrl::Vanity(String::from("AlphaKeks")))
+		);
+		assert_eq!(
+			ProfileUrl::parse(&format!("steamcommunity.com/profiles/{ALPHAKEKS}")),
+			Some(ProfileUrl::SteamID(steam_id))
+		);
+		assert_eq!(
+			ProfileUrl::parse(&format!(
+				"http://www.steamcommunity.com/profiles/{ALPHAKEKS}/?l=german"
+			)),
+			Some(ProfileUrl::SteamID(steam_id))
+		);
+
+		assert_eq!(ProfileUrl::parse("https://steamcommunity.com/id/"), None);
+		assert_eq!(ProfileUrl::parse("https://steamcommunity.com/profiles/AlphaKeks"), None);
+		assert_eq!(ProfileUrl::parse("https://steamcommunity.com/groups/kz"), None);
+		assert_eq!(ProfileUrl::parse("AlphaKeks"), None);
+	}
+
+	#[test]
+	fn deserialize_summary() {
+		let summary = summary();
+
+		assert_eq!(summary.steam_id, SteamID::new(ALPHAKEKS).unwrap());
+		assert_eq!(summary.persona_name, "AlphaKeks");
+		assert_eq!(summary.avatar_url(), "full.jpg");
+		assert_eq!(summary.flag().as_deref(), Some("🇩🇪"));
+		assert_eq!(summary.real_name, None);
+	}
+
+	#[test]
+	fn cache_summaries() {
+		let mut client = SteamClient::new("", gokz_rs::Client::new());
+		let summary = summary();
+
+		client.cache_summaries(std::slice::from_ref(&summary));
+		assert_eq!(client.cached_summary(summary.steam_id), Some(summary.clone()));
+
+		client.cache_ttl = Duration::ZERO;
+		assert_eq!(client.cached_summary(summary.steam_id), None);
+
+		// Expired entries are dropped the next time anything gets cached.
+		client.cache_summaries(&[]);
+		assert!(client
+			.cache
+			.read()
+			.unwrap()
+			.summaries
+			.is_empty());
+	}
 }
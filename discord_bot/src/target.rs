@@ -2,8 +2,9 @@
 
 use {
 	crate::{
-		db,
+		db::{self, UserRepository},
 		error::{Error, Result},
+		steam::ProfileUrl,
 		Context, State,
 	},
 	gokz_rs::{global_api, schnose_api, PlayerIdentifier, SteamID},
@@ -20,9 +21,13 @@ pub enum Target {
 	/// The user @mention'd somebody -> we take that `UserID`.
 	Mention(u64),
 
-	/// The user put in a valid `SteamID` -> we take that.
+	/// The user put in a valid `SteamID` or a `steamcommunity.com/profiles/<id64>` link -> we take
+	/// that.
 	SteamID(SteamID),
 
+	/// The user put in a `steamcommunity.com/id/<vanity>` link -> we ask Steam for the `SteamID`.
+	Vanity(String),
+
 	/// The user specified none of the above. We interpret that as a name.
 	Name(String),
 }
@@ -36,6 +41,12 @@ impl std::str::FromStr for Target {
 			return Ok(Self::SteamID(steam_id));
 		}
 
+		match ProfileUrl::parse(s) {
+			Some(ProfileUrl::SteamID(steam_id)) => return Ok(Self::SteamID(steam_id)),
+			Some(ProfileUrl::Vanity(vanity)) => return Ok(Self::Vanity(vanity)),
+			None => {}
+		}
+
 		if Regex::new(r#"<@[0-9]+>"#)
 			.unwrap()
 			.is_match(s)
@@ -79,26 +90,13 @@ impl Target {
 		match self {
 			Self::None(_) => {
 				if let Ok(user) = db_entry {
-					if let Some(steam_id) = user.steam_id {
-						Ok(steam_id.into())
-					} else {
-						Ok(user.name.into())
-					}
+					Ok(registered_player(user))
 				} else {
 					Ok(ctx.author().name.clone().into())
 				}
 			}
 			Self::Mention(user_id) => {
-				if let Ok(player_identifier) = ctx
-					.find_user_by_id(user_id)
-					.await
-					.map(|user| {
-						if let Some(steam_id) = user.steam_id {
-							PlayerIdentifier::from(steam_id)
-						} else {
-							PlayerIdentifier::from(user.name)
-						}
-					}) {
+				if let Some(player_identifier) = find_registered(ctx.users(), &self).await? {
 					Ok(player_identifier)
 				} else {
 					// If the user @mention'd somebody who isn't in the database, scan the current
@@ -117,13 +115,17 @@ impl Target {
 				}
 			}
 			Self::SteamID(steam_id) => Ok(steam_id.into()),
-			Self::Name(name) => {
-				if let Ok(user) = ctx.find_user_by_name(&name).await {
-					if let Some(steam_id) = user.steam_id {
-						Ok(steam_id.into())
-					} else {
-						Ok(user.name.into())
-					}
+			Self::Vanity(vanity) => ctx
+				.steam()
+				.resolve_vanity_url(&vanity)
+				.await
+				.map(PlayerIdentifier::from)
+				.map_err(|_| {
+					Error::Custom(format!("Couldn't find a Steam profile for `{vanity}`."))
+				}),
+			Self::Name(ref name) => {
+				if let Some(player_identifier) = find_registered(ctx.users(), &self).await? {
+					Ok(player_identifier)
 				} else if let Ok(player) =
 					schnose_api::get_player(name.clone().into(), ctx.gokz_client()).await
 				{
@@ -133,9 +135,93 @@ impl Target {
 				{
 					Ok(player.steam_id.into())
 				} else {
-					Ok(name.into())
+					Ok(name.clone().into())
 				}
 			}
 		}
 	}
 }
+
+/// Users who saved a SteamID are looked up by that, everyone else by name.
+fn registered_player(user: db::User) -> PlayerIdentifier {
+	if let Some(steam_id) = user.steam_id {
+		PlayerIdentifier::from(steam_id)
+	} else {
+		PlayerIdentifier::from(user.name)
+	}
+}
+
+/// Looks up mentions and names in the database. `Ok(None)` if nobody matches or `target` is
+/// neither.
+async fn find_registered(
+	users: &dyn UserRepository,
+	target: &Target,
+) -> Result<Option<PlayerIdentifier>> {
+	let user = match target {
+		Target::Mention(user_id) => users.find_by_discord_id(*user_id).await,
+		Target::Name(name) => users.find_by_name(name).await,
+		_ => return Ok(None),
+	};
+
+	match user {
+		Ok(user) => Ok(Some(registered_player(user))),
+		Err(Error::NoDatabaseEntries) => Ok(None),
+		Err(why) => Err(why),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, crate::db::InMemoryUsers};
+
+	#[test]
+	fn parse_targets() {
+		let steam_id = SteamID::new("76561198282622073").unwrap();
+
+		assert!(matches!("<@1234>".parse(), Ok(Target::Mention(1234))));
+		assert!(matches!("STEAM_1:1:161178172".parse(), Ok(Target::SteamID(id)) if id == steam_id));
+		assert!(matches!(
+			"https://steamcommunity.com/profiles/76561198282622073".parse(),
+			Ok(Target::SteamID(id)) if id == steam_id
+		));
+		assert!(matches!(
+			"https://steamcommunity.com/id/AlphaKeks".parse(),
+			Ok(Target::Vanity(vanity)) if vanity == "AlphaKeks"
+		));
+		assert!(matches!("AlphaKeks".parse(), Ok(Target::Name(name)) if name == "AlphaKeks"));
+	}
+
+	#[tokio::test]
+	async fn find_registered_users() {
+		let users = InMemoryUsers::default();
+		let steam_id = SteamID::new("STEAM_1:1:161178172").unwrap();
+
+		users
+			.upsert_steam_id(1, "AlphaKeks", &steam_id)
+			.await
+			.unwrap();
+		users
+			.set_mode(2, "Szwagi", None)
+			.await
+			.unwrap();
+
+		let find = |target| {
+			let users = &users;
+			async move {
+				find_registered(users, &target)
+					.await
+					.unwrap()
+			}
+		};
+
+		assert_eq!(find(Target::Mention(1)).await, Some(PlayerIdentifier::SteamID(steam_id)));
+		assert_eq!(find(Target::Name(String::from("alpha"))).await, Some(steam_id.into()));
+		assert_eq!(
+			find(Target::Mention(2)).await,
+			Some(PlayerIdentifier::Name(String::from("Szwagi")))
+		);
+		assert_eq!(find(Target::Mention(3)).await, None);
+		assert_eq!(find(Target::Name(String::from("GameChaos"))).await, None);
+		assert_eq!(find(Target::SteamID(steam_id)).await, None);
+	}
+}
@@ -0,0 +1,239 @@
+//! TTL cache for GlobalAPI record lookups. The overlay asks for the same WRs and PBs over and
+//! over, so we only hit the API once per [`RecordCache::TTL`] for every map / mode / course.
+
+use {
+	gokz_rs::{global_api, MapIdentifier, Mode, SteamID},
+	serde::Serialize,
+	std::{
+		collections::HashMap,
+		sync::Mutex,
+		time::{Duration, Instant},
+	},
+	tracing::debug,
+};
+
+/// `(TP, PRO)`
+pub type Records = (Option<global_api::Record>, Option<global_api::Record>);
+
+/// `steam_id` is `None` for world records.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordKey {
+	pub steam_id: Option<SteamID>,
+	pub map: MapIdentifier,
+	pub mode: Mode,
+	pub course: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+	pub entries: usize,
+	pub hits: u64,
+	pub misses: u64,
+	pub invalidations: u64,
+}
+
+impl CacheStats {
+	/// Percentage of lookups that didn't have to go to the API.
+	pub fn hit_rate(&self) -> f64 {
+		match self.hits + self.misses {
+			0 => 0.0,
+			total => self.hits as f64 / total as f64 * 100.0,
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+	entries: HashMap<RecordKey, (Instant, Records)>,
+	stats: CacheStats,
+}
+
+#[derive(Debug, Default)]
+pub struct RecordCache {
+	inner: Mutex<Inner>,
+}
+
+impl RecordCache {
+	pub const TTL: Duration = Duration::from_secs(120);
+
+	fn get(&self, key: &RecordKey, now: Instant) -> Option<Records> {
+		let mut inner = self
+			.inner
+			.lock()
+			.expect("Cache lock poisoned.");
+
+		match inner.entries.get(key) {
+			Some((inserted_at, records)) if now.duration_since(*inserted_at) < Self::TTL => {
+				let records = records.clone();
+				inner.stats.hits += 1;
+				Some(records)
+			}
+			expired => {
+				if expired.is_some() {
+					inner.entries.remove(key);
+				}
+				inner.stats.misses += 1;
+				None
+			}
+		}
+	}
+
+	fn insert(&self, key: RecordKey, records: Records, now: Instant) {
+		self.inner
+			.lock()
+			.expect("Cache lock poisoned.")
+			.entries
+			.insert(key, (now, records));
+	}
+
+	/// Drops every entry. Called whenever the map changes, since the records we care about now
+	/// are for a different map and old PBs might have been beaten in the meantime.
+	pub fn invalidate(&self) {
+		let mut inner = self
+			.inner
+			.lock()
+			.expect("Cache lock poisoned.");
+		inner.entries.clear();
+		inner.stats.invalidations += 1;
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		let inner = self
+			.inner
+			.lock()
+			.expect("Cache lock poisoned.");
+		CacheStats {
+			entries: inner.entries.len(),
+			..inner.stats
+		}
+	}
+
+	/// TP and PRO world records on a map.
+	#[tracing::instrument(skip(self, gokz_client))]
+	pub async fn wrs(
+		&self,
+		map: MapIdentifier,
+		mode: Mode,
+		course: u8,
+		gokz_client: &gokz_rs::Client,
+	) -> Records {
+		let key = RecordKey {
+			steam_id: None,
+			map: map.clone(),
+			mode,
+			course,
+		};
+
+		self.get_or_fetch(key, || async {
+			tokio::join!(
+				global_api::get_wr(map.clone(), mode, true, course, gokz_client),
+				global_api::get_wr(map.clone(), mode, false, course, gokz_client),
+			)
+		})
+		.await
+	}
+
+	/// A player's TP and PRO personal bests on a map.
+	#[tracing::instrument(skip(self, gokz_client))]
+	pub async fn pbs(
+		&self,
+		steam_id: SteamID,
+		map: MapIdentifier,
+		mode: Mode,
+		course: u8,
+		gokz_client: &gokz_rs::Client,
+	) -> Records {
+		let key = RecordKey {
+			steam_id: Some(steam_id),
+			map: map.clone(),
+			mode,
+			course,
+		};
+
+		self.get_or_fetch(key, || async {
+			tokio::join!(
+				global_api::get_pb(steam_id.into(), map.clone(), mode, true, course, gokz_client),
+				global_api::get_pb(steam_id.into(), map.clone(), mode, false, course, gokz_client),
+			)
+		})
+		.await
+	}
+
+	async fn get_or_fetch<F, Fut>(&self, key: RecordKey, fetch: F) -> Records
+	where
+		F: FnOnce() -> Fut,
+		Fut: std::future::Future<
+			Output = (gokz_rs::Result<global_api::Record>, gokz_rs::Result<global_api::Record>),
+		>,
+	{
+		if let Some(records) = self.get(&key, Instant::now()) {
+			debug!("Cache hit for {key:?}.");
+			return records;
+		}
+
+		debug!("Cache miss for {key:?}.");
+		let (tp, pro) = fetch().await;
+
+		// Empty responses just mean there is no record (yet), which is worth caching. Anything
+		// else was probably a network error, so we try again next time.
+		let cacheable = [&tp, &pro]
+			.into_iter()
+			.all(|result| matches!(result, Ok(_) | Err(gokz_rs::Error::EmptyResponse)));
+
+		let records = (tp.ok(), pro.ok());
+
+		if cacheable {
+			self.insert(key, records.clone(), Instant::now());
+		}
+
+		records
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(map: &str) -> RecordKey {
+		RecordKey {
+			steam_id: None,
+			map: MapIdentifier::Name(map.to_owned()),
+			mode: Mode::KZTimer,
+			course: 0,
+		}
+	}
+
+	#[test]
+	fn ttl() {
+		let cache = RecordCache::default();
+		let start = Instant::now();
+
+		assert_eq!(cache.get(&key("kz_lionharder"), start), None);
+		cache.insert(key("kz_lionharder"), (None, None), start);
+		assert_eq!(
+			cache.get(&key("kz_lionharder"), start + Duration::from_secs(5)),
+			Some((None, None))
+		);
+		assert_eq!(cache.get(&key("kz_lionharder"), start + RecordCache::TTL), None);
+
+		let stats = cache.stats();
+		assert_eq!((stats.entries, stats.hits, stats.misses), (0, 1, 2));
+	}
+
+	#[test]
+	fn invalidate() {
+		let cache = RecordCache::default();
+		let now = Instant::now();
+
+		cache.insert(key("kz_lionharder"), (None, None), now);
+		cache.insert(key("kz_beginnerblock_go"), (None, None), now);
+		assert_eq!(cache.stats().entries, 2);
+
+		cache.invalidate();
+		assert_eq!(cache.get(&key("kz_lionharder"), now), None);
+
+		let stats = cache.stats();
+		assert_eq!((stats.entries, stats.invalidations), (0, 1));
+		assert_eq!(stats.hit_rate(), 0.0);
+	}
+}
@@ -0,0 +1,128 @@
+//! GOKZ puts the player's mode and rank into their clan tag, e.g. `[SKZ Expert+]`. Servers often
+//! add their own tags on top of that (`[VIP]`, `[ADMIN | KZT Pro]`, ...), so we can't just split on
+//! the first space.
+
+use gokz_rs::{Mode, Rank};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClanTag {
+	pub mode: Option<Mode>,
+	pub rank: Option<Rank>,
+	/// Anything that isn't a mode or rank, one entry per bracket group.
+	pub tags: Vec<String>,
+}
+
+impl ClanTag {
+	/// Never fails; parts that can't be recognized end up in [`ClanTag::tags`].
+	pub fn parse(clan: &str) -> Self {
+		let mut clan_tag = Self::default();
+
+		for group in Self::groups(clan) {
+			let mut unknown = Vec::new();
+
+			for token in group
+				.split(|c: char| c.is_whitespace() || c == '|')
+				.filter(|token| !token.is_empty())
+			{
+				// `Mode` also parses mode IDs, which we don't want to match here.
+				let is_word = token.chars().all(char::is_alphabetic);
+
+				match (token.parse::<Mode>(), token.parse::<Rank>()) {
+					(Ok(mode), _) if is_word && clan_tag.mode.is_none() => {
+						clan_tag.mode = Some(mode)
+					}
+					(_, Ok(rank)) if clan_tag.rank.is_none() => clan_tag.rank = Some(rank),
+					_ => unknown.push(token),
+				}
+			}
+
+			if !unknown.is_empty() {
+				clan_tag.tags.push(unknown.join(" "));
+			}
+		}
+
+		clan_tag
+	}
+
+	/// The contents of every `[...]` group, or the whole string if there are no complete groups.
+	fn groups(clan: &str) -> Vec<&str> {
+		let groups = clan
+			.split('[')
+			.skip(1)
+			.filter_map(|group| group.split_once(']'))
+			.map(|(group, _)| group.trim())
+			.collect::<Vec<_>>();
+
+		if groups.is_empty() {
+			vec![clan.trim().trim_matches(['[', ']'])]
+		} else {
+			groups
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn clan_tag(mode: Option<Mode>, rank: Option<Rank>, tags: &[&str]) -> ClanTag {
+		ClanTag {
+			mode,
+			rank,
+			tags: tags
+				.iter()
+				.map(|tag| (*tag).to_owned())
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn gokz_tags() {
+		let samples = [
+			("[SKZ]", clan_tag(Some(Mode::SimpleKZ), None, &[])),
+			("[KZT Beginner]", clan_tag(Some(Mode::KZTimer), Some(Rank::Beginner), &[])),
+			("[VNL Pro]", clan_tag(Some(Mode::Vanilla), Some(Rank::Pro), &[])),
+			("[SKZ Expert+]", clan_tag(Some(Mode::SimpleKZ), Some(Rank::ExpertPlus), &[])),
+			("[KZT Regular-]", clan_tag(Some(Mode::KZTimer), Some(Rank::RegularMinus), &[])),
+			("[SKZ Semipro]", clan_tag(Some(Mode::SimpleKZ), Some(Rank::Semipro), &[])),
+			("[KZT New]", clan_tag(Some(Mode::KZTimer), Some(Rank::New), &[])),
+		];
+
+		for (clan, expected) in samples {
+			assert_eq!(ClanTag::parse(clan), expected, "{clan}");
+		}
+	}
+
+	#[test]
+	fn custom_tags() {
+		let samples = [
+			("[VIP] [SKZ Legend]", clan_tag(Some(Mode::SimpleKZ), Some(Rank::Legend), &["VIP"])),
+			("[ADMIN | KZT Pro]", clan_tag(Some(Mode::KZTimer), Some(Rank::Pro), &["ADMIN"])),
+			(
+				"[Server Owner][VNL Master]",
+				clan_tag(Some(Mode::Vanilla), Some(Rank::Master), &["Server Owner"]),
+			),
+			("[SKZ Casual] 1337", clan_tag(Some(Mode::SimpleKZ), Some(Rank::Casual), &[])),
+		];
+
+		for (clan, expected) in samples {
+			assert_eq!(ClanTag::parse(clan), expected, "{clan}");
+		}
+	}
+
+	#[test]
+	fn unexpected_tags() {
+		let samples = [
+			("", clan_tag(None, None, &[])),
+			("[]", clan_tag(None, None, &[])),
+			("skz", clan_tag(Some(Mode::SimpleKZ), None, &[])),
+			("[SKZ Beginner", clan_tag(Some(Mode::SimpleKZ), Some(Rank::Beginner), &[])),
+			("[KZ 2]", clan_tag(None, None, &["KZ 2"])),
+			("[speedrun.gg]", clan_tag(None, None, &["speedrun.gg"])),
+		];
+
+		for (clan, expected) in samples {
+			assert_eq!(ClanTag::parse(clan), expected, "{clan}");
+		}
+	}
+}
@@ -1,17 +1,85 @@
 use {
-	color_eyre::{eyre::bail as yeet, Result},
+	crate::timer::Trigger,
+	chrono::Utc,
+	color_eyre::{
+		eyre::{bail as yeet, eyre},
+		Result,
+	},
 	serde::{Deserialize, Serialize},
-	std::{fs::File, path::PathBuf},
+	std::{
+		fmt::Display,
+		path::{Path, PathBuf},
+	},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
 	pub csgo_cfg_path: PathBuf,
 	pub schnose_api_key: String,
+
+	/// Where to send state updates. Can be pointed at a local server for testing.
+	#[serde(default = "Config::default_schnose_api_url")]
+	pub schnose_api_url: String,
 	pub gsi_port: u16,
+
+	/// Port for the overlay and the rest of the HTTP API.
+	#[serde(default = "Config::default_overlay_port")]
+	pub overlay_port: u16,
+
+	/// Directory containing custom overlay templates and assets. Empty if there is none.
+	#[serde(default)]
+	pub overlay_dir: PathBuf,
+
+	/// The template served at `/`.
+	#[serde(default = "Config::default_overlay_template")]
+	pub overlay_template: String,
+
+	/// What else counts as starting a new attempt, besides map and mode changes.
+	#[serde(default = "Config::default_timer_triggers")]
+	pub timer_triggers: Vec<Trigger>,
+
+	/// Run without a window, same as passing `--headless`.
+	#[serde(default)]
+	pub headless: bool,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			csgo_cfg_path: PathBuf::new(),
+			schnose_api_key: String::new(),
+			schnose_api_url: Self::default_schnose_api_url(),
+			gsi_port: 8888,
+			overlay_port: Self::default_overlay_port(),
+			overlay_dir: PathBuf::new(),
+			overlay_template: Self::default_overlay_template(),
+			timer_triggers: Self::default_timer_triggers(),
+			headless: false,
+		}
+	}
 }
 
 impl Config {
+	fn default_schnose_api_url() -> String {
+		String::from(crate::schnose_api::DEFAULT_URL)
+	}
+
+	fn default_overlay_port() -> u16 {
+		crate::server::DEFAULT_PORT
+	}
+
+	fn default_overlay_template() -> String {
+		String::from("full")
+	}
+
+	fn default_timer_triggers() -> Vec<Trigger> {
+		vec![Trigger::Death]
+	}
+
+	pub fn overlay_dir(&self) -> Option<&Path> {
+		(!self.overlay_dir.as_os_str().is_empty()).then_some(self.overlay_dir.as_path())
+	}
+
 	#[tracing::instrument]
 	pub fn get_path() -> Result<PathBuf> {
 		// Try to follow XDG standard, but fall back to `/home/user/.config` if the user's system
@@ -39,49 +107,175 @@ impl Config {
 		Ok(config_dir)
 	}
 
-	#[tracing::instrument]
-	pub fn load() -> Result<Self> {
-		use std::io::{Read, Write};
+	/// Everything that's wrong with the config, phrased so we can show it to the user.
+	pub fn problems(&self) -> Vec<String> {
+		let mut problems = Vec::new();
+
+		if self.gsi_port == 0 {
+			problems.push(String::from("`gsi_port` can't be 0."));
+		}
+
+		if self.overlay_port == 0 {
+			problems.push(String::from("`overlay_port` can't be 0."));
+		}
+
+		if self.gsi_port == self.overlay_port {
+			problems
+				.push(format!("`gsi_port` and `overlay_port` can't both be {}.", self.gsi_port));
+		}
+
+		if !self
+			.schnose_api_url
+			.starts_with("http://")
+			&& !self
+				.schnose_api_url
+				.starts_with("https://")
+		{
+			problems.push(format!(
+				"`schnose_api_url` (`{}`) has to start with `http://` or `https://`.",
+				self.schnose_api_url
+			));
+		}
+
+		if !crate::server::is_valid_template_name(&self.overlay_template) {
+			problems.push(format!(
+				"`overlay_template` (`{}`) may only contain letters, numbers, `-` and `_`.",
+				self.overlay_template
+			));
+		}
+
+		problems
+	}
+
+	/// Parses and validates the contents of a config file.
+	pub fn parse(config_text: &str) -> Result<Self> {
+		let config = toml::from_str::<Self>(config_text)
+			.map_err(|why| eyre!("Failed to parse config file: {why}"))?;
 
-		let config_file = Self::get_path()?;
+		let problems = config.problems();
+
+		if !problems.is_empty() {
+			yeet!("Invalid config file: {}", problems.join(" "));
+		}
+
+		Ok(config)
+	}
+
+	/// Writes the config to `path`, replacing whatever was there before.
+	#[tracing::instrument(skip(self))]
+	pub fn save(&self, path: &Path) -> Result<()> {
+		std::fs::write(path, toml::to_string_pretty(self)?)?;
+		Ok(())
+	}
+
+	/// Whether the GSI server has to be restarted to pick up the changes from `self` to `new`.
+	pub fn gsi_server_changed(&self, new: &Self) -> bool {
+		self.gsi_port != new.gsi_port || self.csgo_cfg_path != new.csgo_cfg_path
+	}
+
+	/// Whether the Axum server has to be restarted to pick up the changes from `self` to `new`.
+	pub fn axum_server_changed(&self, new: &Self) -> bool {
+		self.overlay_port != new.overlay_port || self.overlay_dir != new.overlay_dir
+	}
+
+	/// Loads the config file, creating it if it doesn't exist yet. If the file is broken, it is
+	/// moved out of the way instead of being overwritten, and the defaults are used.
+	#[tracing::instrument]
+	pub fn load() -> Result<(Self, Option<Recovery>)> {
+		let config_path = Self::get_path()?;
 
 		// Create config folder if it doesn't exist. This will fail if the folder already exists so
 		// we can ignore the potential error.
-		let mut config_dir = config_file.clone();
+		let mut config_dir = config_path.clone();
 		config_dir.pop();
 		_ = std::fs::create_dir(config_dir);
 
-		let mut config_file = match File::options()
-			.read(true)
-			.write(true)
-			.open(&config_file)
-		{
-			Ok(file) => file,
-			Err(err) if err.kind() == std::io::ErrorKind::NotFound => File::options()
-				.create(true)
-				.read(true)
-				.write(true)
-				.open(config_file)?,
+		let config_text = match std::fs::read_to_string(&config_path) {
+			Ok(config_text) => config_text,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
 			Err(err) => {
 				yeet!("Error opening config file: {err:?}");
 			}
 		};
 
-		let mut config_file_contents = String::new();
-		config_file.read_to_string(&mut config_file_contents)?;
-
-		if let Ok(config) = toml::from_str::<Self>(&config_file_contents) {
-			return Ok(config);
+		if config_text.trim().is_empty() {
+			let config = Self::default();
+			config.save(&config_path)?;
+			return Ok((config, None));
 		}
 
-		let config_text = r#"csgo_cfg_path = ""
+		let reason = match Self::parse(&config_text) {
+			Ok(config) => return Ok((config, None)),
+			Err(why) => why.to_string(),
+		};
+
+		let backup_path =
+			config_path.with_extension(format!("toml.{}.bak", Utc::now().format("%Y%m%d%H%M%S")));
+
+		std::fs::rename(&config_path, &backup_path)?;
+
+		let config = Self::default();
+		config.save(&config_path)?;
+
+		Ok((config, Some(Recovery { reason, backup_path })))
+	}
+}
+
+/// Returned by [`Config::load`] if the config file was broken and had to be replaced.
+#[derive(Debug, Clone)]
+pub struct Recovery {
+	pub reason: String,
+	/// Where the broken file was moved to.
+	pub backup_path: PathBuf,
+}
+
+impl Display for Recovery {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{} The old config file was moved to `{}` and the defaults are used instead.",
+			self.reason,
+			self.backup_path.display()
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_roundtrip() {
+		let config_text = toml::to_string_pretty(&Config::default()).unwrap();
+		assert_eq!(Config::parse(&config_text).unwrap(), Config::default());
+	}
+
+	#[test]
+	fn missing_fields() {
+		let config = Config::parse(
+			r#"csgo_cfg_path = ""
 schnose_api_key = ""
-gsi_port = 8888"#;
+gsi_port = 8888"#,
+		)
+		.unwrap();
 
-		let config = toml::from_str::<Self>(config_text)?;
+		assert_eq!(config, Config::default());
+		assert!(Config::parse("gsi_port = 8888").is_err());
+	}
 
-		config_file.write_all(config_text.as_bytes())?;
+	#[test]
+	fn problems() {
+		assert!(Config::default().problems().is_empty());
 
-		Ok(config)
+		let config = Config {
+			gsi_port: 9999,
+			overlay_port: 9999,
+			schnose_api_url: String::from("schnose.xyz"),
+			overlay_template: String::from("../secrets"),
+			..Default::default()
+		};
+
+		assert_eq!(config.problems().len(), 3);
+		assert!(Config::parse(&toml::to_string_pretty(&config).unwrap()).is_err());
 	}
 }
@@ -1,15 +1,32 @@
 use {
-	crate::{config::Config, gui::state::State},
-	color_eyre::Result,
+	crate::{
+		cache::RecordCache,
+		config::Config,
+		gui::state::State,
+		history::History,
+		schnose_api::Delivery,
+		server::OverlayEvent,
+		timer::{Snapshot, Timer},
+	},
+	chrono::Utc,
+	color_eyre::{eyre::eyre, Result},
 	schnose_gsi::{GSIConfigBuilder, GSIServer, Subscription},
+	schnosebot::global_maps::MapCache,
 	std::{sync::Arc, time::Duration},
-	tokio::sync::Mutex,
-	tracing::{debug, error, info},
+	tokio::sync::{watch, Mutex},
+	tracing::{debug, info},
 };
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_server(
 	state: Arc<Mutex<Option<State>>>,
 	config: Arc<Mutex<Config>>,
+	history: Option<Arc<Mutex<History>>>,
+	events: watch::Sender<Option<OverlayEvent>>,
+	cache: Arc<RecordCache>,
+	timer: Arc<Mutex<Timer>>,
+	map_pool: MapCache,
+	delivery: Delivery,
 ) -> schnose_gsi::ServerHandle {
 	let mut config_builder = GSIConfigBuilder::new("schnose-gsi-client");
 
@@ -18,6 +35,9 @@ pub fn run_server(
 		.subscribe_multiple([
 			Subscription::Map,
 			Subscription::PlayerID,
+			Subscription::PlayerState,
+			Subscription::PlayerMatchStats,
+			Subscription::Round,
 		]);
 
 	let gsi_config = config_builder.build();
@@ -50,47 +70,80 @@ pub fn run_server(
 
 	let gokz_client = Arc::new(gokz_rs::Client::new());
 	let prev_event = Arc::new(Mutex::new(None));
+	let history = history.map(History::spawn_writer);
 
 	gsi_server.add_async_event_listener(move |event| {
 		let gokz_client = Arc::clone(&gokz_client);
 		let state = Arc::clone(&state);
 		let config = Arc::clone(&config);
 		let old_event = Arc::clone(&prev_event);
+		let history = history.clone();
+		let events = events.clone();
+		let cache = Arc::clone(&cache);
+		let timer = Arc::clone(&timer);
+		let map_pool = map_pool.clone();
+		let delivery = delivery.clone();
 
 		Box::pin(async move {
 			info!("New GSI Event.");
 			debug!("{event:#?}");
 
+			if let Some(snapshot) = Snapshot::from_event(&event) {
+				let triggers = config
+					.lock()
+					.await
+					.timer_triggers
+					.clone();
+				let mut timer = timer.lock().await;
+
+				if timer.update(snapshot, &triggers, Utc::now().timestamp()) {
+					let status = timer.status();
+					events.send_modify(|overlay_event| {
+						if let Some(overlay_event) = overlay_event {
+							overlay_event.timer = status;
+						}
+					});
+				}
+			}
+
 			// Check if the new event is the same as the previous one, and return early if it is.
 			// No need to re-fetch the same information.
 			{
+				let event = state_relevant(&event);
 				let mut prev_event = old_event.lock().await;
 				if (*prev_event).as_ref() == Some(&event) {
+					// Still counts as time spent on the current map.
+					if let (Some(history), Some(state)) = (&history, &*state.lock().await) {
+						let _ = history.send(state.clone());
+					}
 					return;
 				}
-				*prev_event = Some(event.clone());
+				*prev_event = Some(event);
 			}
 
-			let new_state = match State::new(event, &gokz_client).await {
-				Ok(state) => state,
-				Err(why) => return error!("Failed to create state from event: {why:?}"),
-			};
-
-			*state.lock().await = Some(new_state.clone());
+			let new_state = State::new(event, &map_pool, &gokz_client).await;
 
-			let schnose_api_key = config
-				.lock()
-				.await
-				.schnose_api_key
-				.clone();
+			// Queue the history write while holding the lock, so writes happen in the same order
+			// as state updates.
+			let old_state = {
+				let mut state = state.lock().await;
+				if let Some(history) = &history {
+					let _ = history.send(new_state.clone());
+				}
+				state.replace(new_state.clone())
+			};
 
-			if schnose_api_key.is_empty() {
-				return;
+			if old_state.is_some_and(|old_state| old_state.map.name != new_state.map.name) {
+				info!("Map changed to `{}`, invalidating record cache.", new_state.map.name);
+				cache.invalidate();
 			}
 
-			if let Err(why) = post_to_schnose_api(new_state, &schnose_api_key, &gokz_client).await {
-				error!("Failed to POST to SchnoseAPI: {why:?}");
-			}
+			let timer_status = timer.lock().await.status();
+			let overlay_event =
+				OverlayEvent::new(new_state.clone(), timer_status, &cache, &gokz_client).await;
+			events.send_replace(Some(overlay_event));
+
+			delivery.push(new_state);
 		})
 	});
 
@@ -101,23 +154,38 @@ pub fn run_server(
 		.expect("Failed to run GSI server.")
 }
 
-async fn post_to_schnose_api(state: State, api_key: &str, client: &gokz_rs::Client) -> Result<()> {
-	match client
-		.post("https://schnose.xyz/api/twitch_info")
-		.json(&state)
-		.header("x-schnose-auth-key", api_key)
-		.send()
-		.await
-		.map(|res| res.error_for_status())
-	{
-		Ok(Ok(res)) => {
-			info!("[SchnoseAPI] POST successful: {res:#?}");
-			Ok(())
-		}
-		Ok(Err(why)) | Err(why) => {
-			error!("[SchnoseAPI] OST failed.");
-			debug!("[SchnoseAPI] OST failed: {why:#?}");
-			Err(why.into())
+/// How often [`watch_server`] checks whether the GSI server is still accepting connections.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Fails if something else is already listening on `port`. [`run_server`] can't report that, its
+/// HTTP server just stops silently.
+pub fn check_port(port: u16) -> Result<()> {
+	std::net::TcpListener::bind(("127.0.0.1", port))
+		.map(drop)
+		.map_err(|why| eyre!("Cannot listen for CS:GO events on port {port}: {why}"))
+}
+
+/// Resolves with an error once the GSI server on `port` stops accepting connections.
+pub async fn watch_server(port: u16) -> Result<()> {
+	loop {
+		tokio::time::sleep(WATCH_INTERVAL).await;
+
+		if let Err(why) = tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+			return Err(eyre!("GSI server on port {port} stopped: {why}"));
 		}
 	}
 }
+
+/// The parts of an event that [`State::new`] cares about. Player state, match stats and rounds
+/// change all the time, but are only used by the [`Timer`].
+fn state_relevant(event: &schnose_gsi::Event) -> schnose_gsi::Event {
+	let mut event = event.clone();
+	event.round = None;
+
+	if let Some(player) = &mut event.player {
+		player.state = None;
+		player.match_stats = None;
+	}
+
+	event
+}
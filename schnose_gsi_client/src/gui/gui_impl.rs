@@ -4,7 +4,6 @@ use {
 	super::{Client, Tab},
 	crate::config::Config,
 	eframe::egui::TopBottomPanel,
-	std::{fs::File, io::Write},
 	tracing::{error, info},
 };
 
@@ -16,6 +15,8 @@ impl eframe::App for Client {
 
 			ui.horizontal(|ui| {
 				ui.selectable_value(&mut self.current_tab, Tab::Main, "Main");
+				ui.selectable_value(&mut self.current_tab, Tab::Settings, "Settings");
+				ui.selectable_value(&mut self.current_tab, Tab::History, "History");
 				ui.selectable_value(&mut self.current_tab, Tab::Logs, "Logs");
 			});
 
@@ -29,6 +30,8 @@ impl eframe::App for Client {
 			ui.add_space(12.0);
 			match self.current_tab {
 				Tab::Main => self.render_main(ui),
+				Tab::Settings => self.render_settings(ui),
+				Tab::History => self.render_history(ui),
 				Tab::Logs => self.render_logs(ui),
 			};
 		});
@@ -44,15 +47,7 @@ impl eframe::App for Client {
 
 		let config = tokio::task::block_in_place(|| self.config.blocking_lock().clone());
 
-		let Ok(mut config_file) = File::options().write(true).create(true).open(&config_path) else {
-			return error!("Failed to open config file at `{}`.", config.csgo_cfg_path.display());
-		};
-
-		let Ok(config_as_toml) = toml::to_string_pretty(&config) else {
-			return error!("Failed to serialize config as toml. ({:#?})", self.config);
-		};
-
-		if let Err(why) = config_file.write_all(config_as_toml.as_bytes()) {
+		if let Err(why) = config.save(&config_path) {
 			return error!("Failed to write to config file: {why:#?}");
 		}
 
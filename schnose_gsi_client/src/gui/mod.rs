@@ -2,20 +2,31 @@
 //! The implementation for `App` is in `./gui_impl.rs`.
 
 use {
-	crate::{config::Config, logger::LogReceiver},
-	chrono::Utc,
+	crate::{
+		cache::RecordCache,
+		config::{Config, Recovery},
+		history::{Entry, History},
+		logger::LogReceiver,
+		schnose_api::Delivery,
+		timer::{Timer, Trigger},
+	},
+	chrono::{Local, TimeZone, Utc},
 	color_eyre::Result,
 	eframe::{
 		egui::{
-			style::Selection, Button, FontData, FontDefinitions, RichText, ScrollArea, Style,
-			TextEdit, TextStyle, Ui, Visuals,
+			style::Selection, Button, ComboBox, DragValue, FontData, FontDefinitions, Grid,
+			RichText, ScrollArea, Style, TextEdit, TextStyle, Ui, Visuals,
 		},
 		epaint::{FontFamily, FontId},
 		HardwareAcceleration, NativeOptions, Theme,
 	},
 	rfd::FileDialog,
-	std::{collections::BTreeMap, fs::File, sync::Arc},
-	tokio::{sync::Mutex, task::JoinHandle},
+	schnosebot::global_maps::MapCache,
+	std::{collections::BTreeMap, fs::File, path::PathBuf, sync::Arc},
+	tokio::{
+		sync::{watch, Mutex},
+		task::JoinHandle,
+	},
 	tracing::{error, info},
 };
 
@@ -29,6 +40,15 @@ pub struct Client {
 	/// Global App config.
 	pub config: Arc<Mutex<Config>>,
 
+	/// The config as it is being edited in the settings tab. Only applied once it's saved.
+	pub settings: Config,
+
+	/// The file the config was loaded from. Saved settings are written back to it.
+	pub config_path: PathBuf,
+
+	/// Set if the config file was broken and got replaced with the defaults.
+	pub config_recovery: Option<Recovery>,
+
 	/// For retrieving logging information. This is `None` if the application is logging to STDOUT.
 	pub logger: Option<LogReceiver>,
 
@@ -37,6 +57,24 @@ pub struct Client {
 
 	pub state: Arc<Mutex<Option<state::State>>>,
 
+	/// Maps played in this session. This is `None` if the history database couldn't be opened.
+	pub history: Option<Arc<Mutex<History>>>,
+
+	/// Pushes state updates to overlays connected to `/events`.
+	pub events: watch::Sender<Option<crate::server::OverlayEvent>>,
+
+	/// GlobalAPI records shared by the GSI and Axum servers.
+	pub cache: Arc<RecordCache>,
+
+	/// Attempts and time spent per map.
+	pub timer: Arc<Mutex<Timer>>,
+
+	/// All global maps, refreshed in the background.
+	pub map_pool: MapCache,
+
+	/// Sends state updates to the SchnoseAPI.
+	pub delivery: Delivery,
+
 	/// A handle to shutdown the GSI Server running in the background.
 	pub gsi_server_handle: Option<schnose_gsi::ServerHandle>,
 
@@ -47,6 +85,8 @@ pub struct Client {
 #[derive(Debug, PartialEq)]
 pub enum Tab {
 	Main,
+	Settings,
+	History,
 	Logs,
 }
 
@@ -56,12 +96,31 @@ impl Client {
 	pub const MONOSPACE_FONT: &str = "Fira Code";
 
 	#[tracing::instrument]
-	pub async fn init(config: Config, logger: Option<LogReceiver>) -> Result<()> {
+	pub async fn init(
+		config: Config,
+		config_path: PathBuf,
+		config_recovery: Option<Recovery>,
+		logger: Option<LogReceiver>,
+	) -> Result<()> {
+		let history = History::open_default().await;
+
+		let settings = config.clone();
+		let config = Arc::new(Mutex::new(config));
+
 		let client = Self {
-			config: Arc::new(Mutex::new(config)),
+			delivery: Delivery::spawn(Arc::clone(&config), gokz_rs::Client::new()),
+			config,
+			settings,
+			config_path,
+			config_recovery,
 			logger,
 			current_tab: Tab::Main,
 			state: Arc::new(Mutex::new(None)),
+			history,
+			events: watch::channel(None).0,
+			cache: Arc::new(RecordCache::default()),
+			timer: Arc::new(Mutex::new(Timer::default())),
+			map_pool: crate::maps::spawn_load(gokz_rs::Client::new()),
 			gsi_server_handle: None,
 			axum_server_handle: None,
 		};
@@ -165,13 +224,27 @@ impl Client {
 
 	#[tracing::instrument(skip(self))]
 	pub fn spawn_gsi_server(&mut self) {
-		self.gsi_server_handle =
-			Some(crate::gsi::run_server(Arc::clone(&self.state), Arc::clone(&self.config)));
+		self.gsi_server_handle = Some(crate::gsi::run_server(
+			Arc::clone(&self.state),
+			Arc::clone(&self.config),
+			self.history.clone(),
+			self.events.clone(),
+			Arc::clone(&self.cache),
+			Arc::clone(&self.timer),
+			self.map_pool.clone(),
+			self.delivery.clone(),
+		));
 	}
 
 	#[tracing::instrument(skip(self))]
 	pub fn spawn_axum_server(&mut self) {
-		self.axum_server_handle = Some(tokio::spawn(crate::server::run(Arc::clone(&self.state))));
+		self.axum_server_handle = Some(tokio::spawn(crate::server::run(
+			Arc::clone(&self.config),
+			Arc::clone(&self.state),
+			self.history.clone(),
+			self.events.clone(),
+			Arc::clone(&self.cache),
+		)));
 	}
 
 	#[tracing::instrument(skip(self))]
@@ -201,10 +274,10 @@ impl Client {
 			);
 
 			if self.server_running() {
-				ui.hyperlink_to(
-					"Open Overlay",
-					format!("http://localhost:{}", crate::server::PORT),
-				);
+				let overlay_port =
+					tokio::task::block_in_place(|| self.config.blocking_lock().overlay_port);
+
+				ui.hyperlink_to("Open Overlay", format!("http://localhost:{overlay_port}"));
 			}
 		});
 	}
@@ -228,6 +301,55 @@ impl Client {
 		button.on_hover_text(format!("Current folder: {current_folder}"));
 	}
 
+	/// A button to select a directory with custom overlay templates and a dropdown to choose the
+	/// template served at `/`.
+	pub fn render_overlay_prompt(config: &mut Config, ui: &mut Ui) {
+		let button = ui.add(Button::new("Select your overlay folder").fill(colors::SURFACE2));
+
+		if button.clicked() {
+			if let Some(overlay_dir) = FileDialog::new().pick_folder() {
+				config.overlay_dir = overlay_dir;
+			}
+		}
+
+		button.on_hover_text(match config.overlay_dir() {
+			Some(overlay_dir) => format!("Current folder: {}", overlay_dir.display()),
+			None => String::from("Only the built-in overlays are available."),
+		});
+
+		let mut templates = crate::server::TEMPLATES
+			.iter()
+			.map(|(name, _)| (*name).to_owned())
+			.collect::<Vec<_>>();
+
+		if let Some(Ok(files)) = config
+			.overlay_dir()
+			.map(std::fs::read_dir)
+		{
+			for path in files.flatten().map(|file| file.path()) {
+				if path
+					.extension()
+					.is_some_and(|extension| extension == "html")
+				{
+					if let Some(name) = path.file_stem() {
+						templates.push(name.to_string_lossy().into_owned());
+					}
+				}
+			}
+		}
+
+		templates.sort();
+		templates.dedup();
+
+		ComboBox::from_label("Overlay")
+			.selected_text(&config.overlay_template)
+			.show_ui(ui, |ui| {
+				for template in templates {
+					ui.selectable_value(&mut config.overlay_template, template.clone(), template);
+				}
+			});
+	}
+
 	/// Prompt to enter the user's API key (if they have one).
 	/// This will be censored so streamers don't leak it.
 	pub fn render_api_key_prompt(config: &mut Config, ui: &mut Ui) {
@@ -237,6 +359,50 @@ impl Client {
 			.show(ui);
 	}
 
+	/// Where to send state updates to.
+	pub fn render_api_url_prompt(config: &mut Config, ui: &mut Ui) {
+		ui.label("SchnoseAPI URL: ");
+		TextEdit::singleline(&mut config.schnose_api_url).show(ui);
+	}
+
+	/// Ports for CS:GO to send events to and for the overlay.
+	pub fn render_port_prompts(config: &mut Config, ui: &mut Ui) {
+		ui.horizontal(|ui| {
+			ui.label("GSI port: ");
+			ui.add(DragValue::new(&mut config.gsi_port).clamp_range(1..=u16::MAX));
+			ui.add_space(12.0);
+			ui.label("Overlay port: ");
+			ui.add(DragValue::new(&mut config.overlay_port).clamp_range(1..=u16::MAX));
+		});
+	}
+
+	/// Checkboxes for which events start a new attempt.
+	pub fn render_timer_triggers_prompt(config: &mut Config, ui: &mut Ui) {
+		ui.horizontal(|ui| {
+			ui.label("New attempt on: ");
+
+			for (trigger, label) in [
+				(Trigger::Death, "Death"),
+				(Trigger::Score, "Score change"),
+				(Trigger::RoundStart, "Round start"),
+			] {
+				let mut enabled = config.timer_triggers.contains(&trigger);
+
+				if ui
+					.checkbox(&mut enabled, label)
+					.changed()
+				{
+					match enabled {
+						true => config.timer_triggers.push(trigger),
+						false => config
+							.timer_triggers
+							.retain(|enabled| *enabled != trigger),
+					}
+				}
+			}
+		});
+	}
+
 	/// Button to start/stop the GSI and Axum servers.
 	pub fn render_run_button(&mut self, ui: &mut Ui) {
 		if self.server_running() {
@@ -254,23 +420,267 @@ impl Client {
 		}
 	}
 
-	/// The main window displaying [`render_api_key_prompt`] and [`render_run_button`].
+	/// The main window displaying [`render_run_button`] and what's currently going on.
 	pub fn render_main(&mut self, ui: &mut Ui) {
 		ui.vertical_centered(|ui| {
-			tokio::task::block_in_place(|| {
-				let mut config = self.config.blocking_lock();
+			if self.config_recovery.is_some() {
+				ui.label(
+					RichText::new("Your config file was broken and got reset. See the settings.")
+						.color(colors::RED),
+				);
+				ui.add_space(12.0);
+			}
 
-				Self::render_cfg_prompt(&mut config, ui);
-				Self::render_api_key_prompt(&mut config, ui);
-			});
+			self.render_run_button(ui);
+			ui.add_space(12.0);
+
+			self.render_sync_status(ui);
+			self.render_timer(ui);
+		});
+	}
+
+	/// The settings tab. Changes are only applied once they are saved.
+	pub fn render_settings(&mut self, ui: &mut Ui) {
+		ui.vertical_centered(|ui| {
+			if let Some(recovery) = &self.config_recovery {
+				ui.label(RichText::new(recovery.to_string()).color(colors::RED));
+				ui.add_space(12.0);
+			}
+
+			Self::render_cfg_prompt(&mut self.settings, ui);
+			Self::render_api_key_prompt(&mut self.settings, ui);
+			Self::render_api_url_prompt(&mut self.settings, ui);
+			ui.add_space(12.0);
+
+			Self::render_port_prompts(&mut self.settings, ui);
+			Self::render_overlay_prompt(&mut self.settings, ui);
+			Self::render_timer_triggers_prompt(&mut self.settings, ui);
+			ui.checkbox(&mut self.settings.headless, "Start without a window next time");
 
 			ui.add_space(12.0);
 			ui.separator();
 			ui.add_space(12.0);
 
-			self.render_run_button(ui);
-			ui.add_space(12.0);
+			self.render_save_button(ui);
+		});
+	}
+
+	/// Buttons to save or discard changes in the settings tab. Saving is only possible if the
+	/// settings are valid.
+	pub fn render_save_button(&mut self, ui: &mut Ui) {
+		let problems = self.settings.problems();
+
+		for problem in &problems {
+			ui.label(RichText::new(problem).color(colors::RED));
+		}
+
+		let config = tokio::task::block_in_place(|| self.config.blocking_lock().clone());
+		let has_changes = config != self.settings;
+
+		let save_button = Button::new("Save").fill(colors::SURFACE2);
+		if ui
+			.add_enabled(has_changes && problems.is_empty(), save_button)
+			.clicked()
+		{
+			self.save_settings(&config);
+		}
+
+		let discard_button = Button::new("Discard changes").fill(colors::SURFACE2);
+		if ui
+			.add_enabled(has_changes, discard_button)
+			.clicked()
+		{
+			self.settings = config;
+		}
+	}
+
+	/// Writes the settings to the config file and applies them. The servers only read some
+	/// settings on startup, so they get restarted if any of those changed.
+	#[tracing::instrument(skip(self))]
+	pub fn save_settings(&mut self, old_config: &Config) {
+		if let Err(why) = self.settings.save(&self.config_path) {
+			return error!("Failed to write to config file: {why:?}");
+		}
+
+		info!("Saved config to `{}`.", self.config_path.display());
+
+		tokio::task::block_in_place(|| *self.config.blocking_lock() = self.settings.clone());
+		self.config_recovery = None;
+
+		if !self.server_running() {
+			return;
+		}
+
+		if old_config.gsi_server_changed(&self.settings) {
+			info!("Restarting GSI server.");
+			self.kill_gsi_server();
+			self.spawn_gsi_server();
+		}
+
+		if old_config.axum_server_changed(&self.settings) {
+			info!("Restarting Axum server.");
+			self.kill_axum_server();
+			self.spawn_axum_server();
+		}
+	}
+
+	/// When we last managed to send the state to the SchnoseAPI.
+	pub fn render_sync_status(&self, ui: &mut Ui) {
+		let has_api_key = tokio::task::block_in_place(|| {
+			!self
+				.config
+				.blocking_lock()
+				.schnose_api_key
+				.is_empty()
+		});
+
+		if !has_api_key {
+			return;
+		}
+
+		let status = self.delivery.status();
+		let last_sync = match status.last_success {
+			None => String::from("never"),
+			Some(timestamp) => {
+				format!("{} ago", Self::fmt_duration(Utc::now().timestamp() - timestamp))
+			}
+		};
+
+		match &status.last_error {
+			None => {
+				ui.label(
+					RichText::new(format!("SchnoseAPI: last sync {last_sync}"))
+						.color(colors::GREEN),
+				);
+			}
+			Some(why) => {
+				ui.label(
+					RichText::new(format!(
+						"SchnoseAPI: last sync {last_sync}, {} failed attempts",
+						status.failures
+					))
+					.color(colors::RED),
+				)
+				.on_hover_text(why);
+			}
+		}
+
+		ui.ctx()
+			.request_repaint_after(std::time::Duration::from_secs(1));
+	}
+
+	/// Current attempt and time spent on the map.
+	pub fn render_timer(&self, ui: &mut Ui) {
+		let Some(status) = tokio::task::block_in_place(|| self.timer.blocking_lock().status())
+		else {
+			return;
+		};
+
+		let now = Utc::now().timestamp();
+
+		ui.separator();
+		ui.add_space(12.0);
+		ui.label(RichText::new(&status.map).color(colors::MAUVE));
+		ui.label(format!(
+			"Attempt #{} - {}",
+			status.attempt,
+			Self::fmt_duration(now - status.run_started_at)
+		));
+		ui.label(format!(
+			"Time on map: {}",
+			Self::fmt_duration(status.map_seconds + now - status.map_entered_at)
+		));
+
+		if let Some(last_run) = status.last_run_seconds {
+			ui.label(format!("Last attempt: {}", Self::fmt_duration(last_run)));
+		}
+
+		// The durations above should tick even if nothing else happens.
+		ui.ctx()
+			.request_repaint_after(std::time::Duration::from_secs(1));
+	}
+
+	/// The window showing which maps were played in the current session.
+	pub fn render_history(&mut self, ui: &mut Ui) {
+		let Some(history) = &self.history else {
+			ui.vertical_centered(|ui| {
+				ui.label("Session history is not available. Check the logs.")
+			});
+			return;
+		};
+
+		let session = tokio::task::block_in_place(|| history.blocking_lock().current.clone());
+
+		ui.vertical_centered(|ui| {
+			ui.label(format!(
+				"Session #{} - started at {} - {}",
+				session.info.id,
+				Self::fmt_timestamp(session.info.started_at),
+				Self::fmt_duration(session.info.ended_at - session.info.started_at)
+			));
 		});
+
+		if session.entries.is_empty() {
+			ui.vertical_centered(|ui| ui.label("Nothing played yet."));
+			return;
+		}
+
+		ui.add_space(12.0);
+
+		ScrollArea::new([false, true])
+			.auto_shrink([false; 2])
+			.show(ui, |ui| {
+				ui.label(RichText::new("Maps").color(colors::MAUVE));
+				Grid::new("history-maps")
+					.striped(true)
+					.show(ui, |ui| {
+						for map in session.maps() {
+							ui.label(map.map_name);
+							ui.label(
+								map.map_tier
+									.map_or_else(String::new, |tier| format!("T{tier}")),
+							);
+							ui.label(Self::fmt_duration(map.seconds));
+							ui.end_row();
+						}
+					});
+
+				ui.add_space(12.0);
+				ui.label(
+					RichText::new(format!("Timeline ({} mode switches)", session.mode_switches()))
+						.color(colors::MAUVE),
+				);
+				Grid::new("history-timeline")
+					.striped(true)
+					.show(ui, |ui| {
+						for Entry { map_name, mode, started_at, ended_at, .. } in
+							session.entries.iter().rev()
+						{
+							ui.label(Self::fmt_timestamp(*started_at));
+							ui.label(map_name);
+							ui.label(mode.as_deref().unwrap_or("-"));
+							ui.label(Self::fmt_duration(ended_at - started_at));
+							ui.end_row();
+						}
+					});
+			});
+	}
+
+	/// `HH:MM:SS` in local time.
+	fn fmt_timestamp(timestamp: i64) -> String {
+		Local
+			.timestamp_opt(timestamp, 0)
+			.single()
+			.map_or_else(String::new, |time| time.format("%H:%M:%S").to_string())
+	}
+
+	/// `1h 02m 03s`
+	fn fmt_duration(seconds: i64) -> String {
+		let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+		match hours {
+			0 => format!("{minutes}m {seconds:02}s"),
+			hours => format!("{hours}h {minutes:02}m {seconds:02}s"),
+		}
 	}
 
 	/// The window showing the log output.
@@ -287,6 +697,9 @@ impl Client {
 
 		let logs = LogReceiver::formatted(&logs);
 
+		self.render_cache_stats(ui);
+		ui.separator();
+
 		ScrollArea::new([true; 2])
 			.auto_shrink([false; 2])
 			.stick_to_bottom(true)
@@ -321,6 +734,31 @@ impl Client {
 		ui.add_space(4.0);
 	}
 
+	/// How well the GlobalAPI record cache is doing.
+	pub fn render_cache_stats(&self, ui: &mut Ui) {
+		let stats = self.cache.stats();
+
+		ui.horizontal(|ui| {
+			ui.add_space(4.0);
+			ui.label(RichText::new("Record cache").color(colors::MAUVE));
+			ui.separator();
+			ui.label(format!("{} entries", stats.entries));
+			ui.separator();
+			ui.label(format!(
+				"{} hits / {} misses ({:.1}%)",
+				stats.hits,
+				stats.misses,
+				stats.hit_rate()
+			));
+			ui.separator();
+			ui.label(format!("{} invalidations", stats.invalidations));
+
+			if ui.button("Clear").clicked() {
+				self.cache.invalidate();
+			}
+		});
+	}
+
 	/// Render a button to save current logs.
 	pub fn render_save_logs_button(&mut self, ui: &mut Ui) {
 		use std::io::Write;
@@ -329,7 +767,10 @@ impl Client {
 			let date = Utc::now();
 			let file_name = format!("{}-schnose-gsi.log", date.format("%Y%m%d%H%M%S"));
 
-			let Some(log_path) = FileDialog::new().set_file_name(&file_name).save_file() else {
+			let Some(log_path) = FileDialog::new()
+				.set_file_name(&file_name)
+				.save_file()
+			else {
 				return;
 			};
 
@@ -1,7 +1,9 @@
 use {
-	color_eyre::Result,
-	gokz_rs::{global_api, MapIdentifier, Mode, SteamID, Tier},
-	serde::{Deserialize, Serialize, Serializer},
+	crate::clan_tag::ClanTag,
+	gokz_rs::{global_api, MapIdentifier, Mode, Rank, SteamID, Tier},
+	schnosebot::global_maps::MapCache,
+	serde::{Deserialize, Deserializer, Serialize, Serializer},
+	tracing::warn,
 };
 
 /// Global state of the game. This is stored in an `Arc<Mutex<State>>` to keep in sync with all
@@ -20,6 +22,13 @@ pub struct Player {
 	pub steam_id: SteamID,
 	#[serde(serialize_with = "serialize_mode_for_js")]
 	pub mode: Option<Mode>,
+	/// The player's rank in their current mode, as shown in their clan tag.
+	#[serde(
+		default,
+		serialize_with = "serialize_rank_for_js",
+		deserialize_with = "deserialize_rank"
+	)]
+	pub rank: Option<Rank>,
 }
 
 fn serialize_mode_for_js<S>(mode: &Option<Mode>, serializer: S) -> Result<S::Ok, S::Error>
@@ -30,65 +39,85 @@ where
 		.serialize(serializer)
 }
 
+/// `Expert+` instead of `ExpertPlus`.
+fn serialize_rank_for_js<S>(rank: &Option<Rank>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	rank.map(|rank| rank.to_string())
+		.serialize(serializer)
+}
+
+fn deserialize_rank<'de, D>(deserializer: D) -> Result<Option<Rank>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	Option::<String>::deserialize(deserializer)?
+		.map(|rank| {
+			rank.parse()
+				.map_err(serde::de::Error::custom)
+		})
+		.transpose()
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Map {
 	pub name: String,
+	/// `None` for maps that aren't global.
 	pub tier: Option<Tier>,
+	/// Set if the map was loaded from the Steam workshop.
+	#[serde(default)]
+	pub workshop_id: Option<u64>,
 }
 
-impl State {
-	/// Parse a [`schnose_gsi::Event`] into [`Self`], fetching information from the GlobalAPI. This
-	/// should not be called in short intervals, or you will get rate-limited.
-	pub async fn new(event: schnose_gsi::Event, client: &gokz_rs::Client) -> Result<Self> {
-		let player = event.player.map(|e_player| Player {
-			name: e_player.name.clone(),
-			steam_id: e_player.steam_id,
-			mode: e_player
-				.clan
-				.and_then(|clan| match clan.contains(' ') {
-					// [SKZ Beginner] Player Name
-					true => clan
-						.split_once(' ')
-						.and_then(|(mode, _rank)| {
-							mode.replace('[', "")
-								.parse::<Mode>()
-								.ok()
-						}),
-					// [SKZ] Player Name
-					false => clan
-						.replace(['[', ']'], "")
-						.parse::<Mode>()
-						.ok(),
-				}),
-		});
+impl Map {
+	/// Looks up `map_name` in the global map pool. If the pool hasn't been loaded yet, we ask the
+	/// GlobalAPI instead; maps that can't be found there are treated as non-global.
+	#[tracing::instrument(skip(map_pool, client))]
+	pub async fn new(map_name: &str, map_pool: &MapCache, client: &gokz_rs::Client) -> Self {
+		let (name, workshop_id) = Self::parse_name(map_name);
+		let maps = map_pool.maps();
 
-		let map = match event.map.map(|map| {
-			if map.name.contains('/') {
-				map.name
-					.rsplit_once('/')
-					.map(|(_, map_name)| map_name.to_owned())
-					.unwrap()
-			} else {
-				map.name.clone()
-			}
-		}) {
-			None => Map {
-				name: String::from("unknown map"),
-				tier: None,
-			},
-			Some(map_name) if !Self::is_valid_map_name(&map_name) => Map {
-				name: String::from("unknown map"),
-				tier: None,
-			},
-			Some(map_name) => global_api::get_map(&MapIdentifier::Name(map_name), client)
+		let tier = if !maps.is_empty() {
+			maps.iter()
+				.find(|map| map.name.eq_ignore_ascii_case(name))
+				.map(|map| map.tier)
+		} else if Self::is_valid_map_name(name) {
+			global_api::get_map(&MapIdentifier::Name(name.to_owned()), client)
 				.await
-				.map(|map| Map {
-					name: map.name,
-					tier: Some(map.difficulty),
-				})?,
+				.map_err(|why| warn!("Failed to fetch map `{name}`: {why:?}"))
+				.ok()
+				.map(|map| map.difficulty)
+		} else {
+			None
 		};
 
-		Ok(Self { player, map })
+		Self { name: name.to_owned(), tier, workshop_id }
+	}
+
+	pub fn unknown() -> Self {
+		Self {
+			name: String::from("unknown map"),
+			tier: None,
+			workshop_id: None,
+		}
+	}
+
+	/// Splits a map name as reported by CS:GO into the actual name and the workshop ID, if
+	/// there is one. Workshop maps are called something like `workshop/123456789/kz_lionharder`.
+	pub fn parse_name(map_name: &str) -> (&str, Option<u64>) {
+		let mut parts = map_name.split('/');
+		let name = map_name
+			.rsplit('/')
+			.next()
+			.unwrap_or(map_name);
+
+		let workshop_id = match (parts.next(), parts.next()) {
+			(Some("workshop"), Some(id)) => id.parse().ok(),
+			_ => None,
+		};
+
+		(name, workshop_id)
 	}
 
 	fn is_valid_map_name(map_name: &str) -> bool {
@@ -99,3 +128,62 @@ impl State {
 		.any(|prefix| map_name.starts_with(prefix))
 	}
 }
+
+impl State {
+	/// Parse a [`schnose_gsi::Event`] into [`Self`]. This may fetch information from the
+	/// GlobalAPI, so it should not be called in short intervals, or you will get rate-limited.
+	pub async fn new(
+		event: schnose_gsi::Event,
+		map_pool: &MapCache,
+		client: &gokz_rs::Client,
+	) -> Self {
+		let player = event.player.map(|e_player| {
+			let clan_tag = e_player
+				.clan
+				.as_deref()
+				.map(ClanTag::parse)
+				.unwrap_or_default();
+
+			Player {
+				name: e_player.name.clone(),
+				steam_id: e_player.steam_id,
+				mode: clan_tag.mode,
+				rank: clan_tag.rank,
+			}
+		});
+
+		let map = match event.map {
+			None => Map::unknown(),
+			Some(map) => Map::new(&map.name, map_pool, client).await,
+		};
+
+		Self { player, map }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_map_names() {
+		assert_eq!(Map::parse_name("kz_lionharder"), ("kz_lionharder", None));
+		assert_eq!(
+			Map::parse_name("workshop/793414645/kz_lionharder"),
+			("kz_lionharder", Some(793414645))
+		);
+		assert_eq!(Map::parse_name("workshop/793414645"), ("793414645", Some(793414645)));
+		assert_eq!(Map::parse_name("maps/kz_my_local_map"), ("kz_my_local_map", None));
+	}
+
+	#[tokio::test]
+	async fn non_global_maps() {
+		let map_pool = MapCache::from_maps(Vec::new(), true);
+		let client = gokz_rs::Client::new();
+
+		// Doesn't look like a KZ map, so we shouldn't even ask the API.
+		let map = Map::new("workshop/123/de_dust2", &map_pool, &client).await;
+		assert_eq!(map.name, "de_dust2");
+		assert_eq!((map.tier, map.workshop_id), (None, Some(123)));
+	}
+}
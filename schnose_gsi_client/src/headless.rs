@@ -0,0 +1,75 @@
+//! Runs the GSI and Axum servers without a window, e.g. on a dedicated streaming machine.
+
+use {
+	crate::{
+		cache::RecordCache, config::Config, history::History, schnose_api::Delivery, timer::Timer,
+	},
+	color_eyre::{eyre::eyre, Result},
+	std::sync::Arc,
+	tokio::sync::{watch, Mutex},
+	tracing::info,
+};
+
+#[tracing::instrument(skip(config))]
+pub async fn run(config: Config) -> Result<()> {
+	let config = Arc::new(Mutex::new(config));
+	let state = Arc::new(Mutex::new(None));
+	let history = History::open_default().await;
+	let events = watch::channel(None).0;
+	let cache = Arc::new(RecordCache::default());
+	let timer = Arc::new(Mutex::new(Timer::default()));
+	let map_pool = crate::maps::spawn_load(gokz_rs::Client::new());
+	let delivery = Delivery::spawn(Arc::clone(&config), gokz_rs::Client::new());
+
+	let gsi_port = config.lock().await.gsi_port;
+	crate::gsi::check_port(gsi_port)?;
+
+	let gsi_server = crate::gsi::run_server(
+		Arc::clone(&state),
+		Arc::clone(&config),
+		history.clone(),
+		events.clone(),
+		Arc::clone(&cache),
+		timer,
+		map_pool,
+		delivery,
+	);
+
+	let mut axum_server = tokio::spawn(crate::server::run(config, state, history, events, cache));
+
+	info!("Running headless. Press Ctrl+C to stop.");
+
+	let result = tokio::select! {
+		signal = shutdown_signal() => signal,
+		result = crate::gsi::watch_server(gsi_port) => result,
+		result = &mut axum_server => Err(match result {
+			Ok(()) => eyre!("Axum server stopped unexpectedly."),
+			Err(why) => eyre!("Axum server crashed: {why:?}"),
+		}),
+	};
+
+	info!("Shutting down.");
+	gsi_server.abort();
+	axum_server.abort();
+
+	result
+}
+
+/// Resolves on SIGINT (Ctrl+C), or SIGTERM on unix.
+async fn shutdown_signal() -> Result<()> {
+	#[cfg(unix)]
+	{
+		let mut sigterm =
+			tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+		tokio::select! {
+			signal = tokio::signal::ctrl_c() => signal?,
+			_ = sigterm.recv() => {}
+		}
+	}
+
+	#[cfg(not(unix))]
+	tokio::signal::ctrl_c().await?;
+
+	Ok(())
+}
@@ -0,0 +1,429 @@
+//! Session history. Every time the map or mode changes, a new [`Entry`] is written to a local
+//! SQLite database, so streamers can look at what they played later.
+
+use {
+	crate::{config::Config, gui::state::State},
+	chrono::Utc,
+	color_eyre::Result,
+	serde::Serialize,
+	sqlx::{
+		sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+		FromRow, Pool, Sqlite,
+	},
+	std::{collections::HashMap, path::PathBuf, sync::Arc},
+	tokio::sync::{mpsc, Mutex},
+	tracing::{error, info},
+};
+
+/// A stretch of time spent on one map in one mode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+pub struct Entry {
+	pub map_name: String,
+	pub map_tier: Option<u8>,
+	pub mode: Option<String>,
+	/// Unix timestamp.
+	pub started_at: i64,
+	/// Unix timestamp. Moves forward while the entry is still the current one.
+	pub ended_at: i64,
+}
+
+impl Entry {
+	fn new(state: &State, now: i64) -> Self {
+		Self {
+			map_name: state.map.name.clone(),
+			map_tier: state.map.tier.map(|tier| tier as u8),
+			mode: state
+				.player
+				.as_ref()
+				.and_then(|player| player.mode)
+				.map(|mode| mode.short()),
+			started_at: now,
+			ended_at: now,
+		}
+	}
+
+	/// Whether `state` still belongs to this entry.
+	fn matches(&self, other: &Self) -> bool {
+		self.map_name == other.map_name && self.mode == other.mode
+	}
+
+	pub const fn seconds(&self) -> i64 {
+		self.ended_at - self.started_at
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+pub struct SessionInfo {
+	pub id: i64,
+	pub started_at: i64,
+	pub ended_at: i64,
+}
+
+/// Time spent on a single map across a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MapSummary {
+	pub map_name: String,
+	pub map_tier: Option<u8>,
+	pub seconds: i64,
+	/// How often the map was loaded (or the mode changed on it).
+	pub entries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Session {
+	#[serde(flatten)]
+	pub info: SessionInfo,
+	/// Oldest first.
+	pub entries: Vec<Entry>,
+}
+
+impl Session {
+	/// Time per map, most played first.
+	pub fn maps(&self) -> Vec<MapSummary> {
+		let mut maps = HashMap::<&str, MapSummary>::new();
+
+		for entry in &self.entries {
+			let summary = maps
+				.entry(&entry.map_name)
+				.or_insert_with(|| MapSummary {
+					map_name: entry.map_name.clone(),
+					map_tier: entry.map_tier,
+					seconds: 0,
+					entries: 0,
+				});
+			summary.seconds += entry.seconds();
+			summary.entries += 1;
+		}
+
+		let mut maps = maps.into_values().collect::<Vec<_>>();
+		maps.sort_by(|a, b| {
+			b.seconds
+				.cmp(&a.seconds)
+				.then_with(|| a.map_name.cmp(&b.map_name))
+		});
+		maps
+	}
+
+	/// How often the mode changed without the map changing.
+	pub fn mode_switches(&self) -> usize {
+		self.entries
+			.windows(2)
+			.filter(|pair| pair[0].map_name == pair[1].map_name && pair[0].mode != pair[1].mode)
+			.count()
+	}
+
+	/// Adds a new entry if the map or mode changed, otherwise extends the current one. Returns
+	/// `true` if a new entry was added.
+	fn record(&mut self, entry: Entry) -> bool {
+		self.info.ended_at = entry.ended_at;
+
+		match self.entries.last_mut() {
+			Some(current) if current.matches(&entry) => {
+				current.ended_at = entry.ended_at;
+				false
+			}
+			current => {
+				if let Some(current) = current {
+					current.ended_at = entry.started_at;
+				}
+				self.entries.push(entry);
+				true
+			}
+		}
+	}
+}
+
+/// JSON representation of a session for the HTTP server.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionReport {
+	#[serde(flatten)]
+	pub session: Session,
+	pub maps: Vec<MapSummary>,
+	pub mode_switches: usize,
+}
+
+impl From<Session> for SessionReport {
+	fn from(session: Session) -> Self {
+		Self {
+			maps: session.maps(),
+			mode_switches: session.mode_switches(),
+			session,
+		}
+	}
+}
+
+/// How often (in seconds) the end of the current entry is written to disk while the map and mode
+/// stay the same. CS:GO sends an event every second, so writing all of them would be wasteful.
+const FLUSH_INTERVAL: i64 = 30;
+
+/// The current session, kept in memory and mirrored to disk.
+#[derive(Debug)]
+pub struct History {
+	database: Pool<Sqlite>,
+	pub current: Session,
+	/// Row ID of the last entry in `current`.
+	current_entry: Option<i64>,
+	/// Unix timestamp of the last write to disk.
+	last_flush: i64,
+}
+
+impl History {
+	/// `history.sqlite` next to the config file.
+	pub fn default_path() -> Result<PathBuf> {
+		Ok(Config::get_path()?.with_file_name("history.sqlite"))
+	}
+
+	/// Opens (or creates) the database and starts a new session.
+	#[tracing::instrument]
+	pub async fn open(path: PathBuf) -> Result<Self> {
+		let options = SqliteConnectOptions::new()
+			.filename(&path)
+			.create_if_missing(true);
+
+		let database = SqlitePoolOptions::new()
+			.max_connections(1)
+			.connect_with(options)
+			.await?;
+
+		sqlx::query(
+			r#"
+			CREATE TABLE IF NOT EXISTS sessions (
+			    id         INTEGER  PRIMARY KEY AUTOINCREMENT,
+			    started_at INTEGER  NOT NULL,
+			    ended_at   INTEGER  NOT NULL
+			)
+			"#,
+		)
+		.execute(&database)
+		.await?;
+
+		sqlx::query(
+			r#"
+			CREATE TABLE IF NOT EXISTS entries (
+			    id         INTEGER  PRIMARY KEY AUTOINCREMENT,
+			    session_id INTEGER  NOT NULL REFERENCES sessions (id),
+			    map_name   TEXT     NOT NULL,
+			    map_tier   INTEGER,
+			    mode       TEXT,
+			    started_at INTEGER  NOT NULL,
+			    ended_at   INTEGER  NOT NULL
+			)
+			"#,
+		)
+		.execute(&database)
+		.await?;
+
+		let now = Utc::now().timestamp();
+		let id = sqlx::query("INSERT INTO sessions (started_at, ended_at) VALUES (?, ?)")
+			.bind(now)
+			.bind(now)
+			.execute(&database)
+			.await?
+			.last_insert_rowid();
+
+		info!("Recording session #{id} to `{}`.", path.display());
+
+		Ok(Self {
+			database,
+			current: Session {
+				info: SessionInfo { id, started_at: now, ended_at: now },
+				entries: Vec::new(),
+			},
+			current_entry: None,
+			last_flush: now,
+		})
+	}
+
+	/// Opens the database at [`History::default_path`]. Session history is optional, so errors are
+	/// only logged.
+	pub async fn open_default() -> Option<Arc<Mutex<Self>>> {
+		let history = match Self::default_path() {
+			Ok(path) => Self::open(path).await,
+			Err(why) => Err(why),
+		};
+
+		history
+			.map(|history| Arc::new(Mutex::new(history)))
+			.map_err(|why| error!("Failed to open session history: {why:?}"))
+			.ok()
+	}
+
+	#[tracing::instrument(skip(self))]
+	pub async fn record(&mut self, state: &State) -> Result<()> {
+		let now = Utc::now().timestamp();
+		let session_id = self.current.info.id;
+
+		let new_entry = self
+			.current
+			.record(Entry::new(state, now));
+
+		if !new_entry && now - self.last_flush < FLUSH_INTERVAL {
+			return Ok(());
+		}
+
+		// `record` also moves the end of the previous entry if there is a new one.
+		if let Some(entry_id) = self.current_entry {
+			sqlx::query("UPDATE entries SET ended_at = ? WHERE id = ?")
+				.bind(now)
+				.bind(entry_id)
+				.execute(&self.database)
+				.await?;
+		}
+
+		if new_entry {
+			let entry = self
+				.current
+				.entries
+				.last()
+				.expect("We just pushed an entry.");
+
+			let entry_id = sqlx::query(
+				r#"
+				INSERT INTO entries
+				    (session_id, map_name, map_tier, mode, started_at, ended_at)
+				VALUES
+				    (?, ?, ?, ?, ?, ?)
+				"#,
+			)
+			.bind(session_id)
+			.bind(&entry.map_name)
+			.bind(entry.map_tier)
+			.bind(&entry.mode)
+			.bind(entry.started_at)
+			.bind(entry.ended_at)
+			.execute(&self.database)
+			.await?
+			.last_insert_rowid();
+
+			self.current_entry = Some(entry_id);
+		}
+
+		sqlx::query("UPDATE sessions SET ended_at = ? WHERE id = ?")
+			.bind(now)
+			.bind(session_id)
+			.execute(&self.database)
+			.await?;
+
+		self.last_flush = now;
+
+		Ok(())
+	}
+
+	/// Spawns a task that [records](Self::record) every state sent to it, one after another in the
+	/// order they were sent. The GSI server's event listeners have to be `Sync`, which database
+	/// queries are not, so they can't await the writes themselves.
+	pub fn spawn_writer(history: Arc<Mutex<Self>>) -> mpsc::UnboundedSender<State> {
+		let (sender, mut receiver) = mpsc::unbounded_channel::<State>();
+
+		tokio::spawn(async move {
+			while let Some(state) = receiver.recv().await {
+				if let Err(why) = history
+					.lock()
+					.await
+					.record(&state)
+					.await
+				{
+					error!("Failed to record session history: {why:?}");
+				}
+			}
+		});
+
+		sender
+	}
+
+	/// All recorded sessions, newest first.
+	pub async fn sessions(&self) -> Result<Vec<SessionInfo>> {
+		Ok(sqlx::query_as("SELECT * FROM sessions ORDER BY id DESC")
+			.fetch_all(&self.database)
+			.await?)
+	}
+
+	pub async fn session(&self, id: i64) -> Result<Option<Session>> {
+		if id == self.current.info.id {
+			return Ok(Some(self.current.clone()));
+		}
+
+		let Some(info) = sqlx::query_as::<_, SessionInfo>("SELECT * FROM sessions WHERE id = ?")
+			.bind(id)
+			.fetch_optional(&self.database)
+			.await?
+		else {
+			return Ok(None);
+		};
+
+		let entries = sqlx::query_as(
+			r#"
+			SELECT map_name, map_tier, mode, started_at, ended_at
+			FROM entries
+			WHERE session_id = ?
+			ORDER BY id
+			"#,
+		)
+		.bind(id)
+		.fetch_all(&self.database)
+		.await?;
+
+		Ok(Some(Session { info, entries }))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(map_name: &str, mode: &str, started_at: i64) -> Entry {
+		Entry {
+			map_name: map_name.to_owned(),
+			map_tier: Some(7),
+			mode: Some(mode.to_owned()),
+			started_at,
+			ended_at: started_at,
+		}
+	}
+
+	#[test]
+	fn record_session() {
+		let mut session = Session {
+			info: SessionInfo { id: 1, started_at: 0, ended_at: 0 },
+			entries: Vec::new(),
+		};
+
+		assert!(session.record(entry("kz_lionharder", "SKZ", 0)));
+		assert!(!session.record(entry("kz_lionharder", "SKZ", 100)));
+		assert!(session.record(entry("kz_lionharder", "KZT", 300)));
+		assert!(session.record(entry("kz_beginnerblock_go", "KZT", 400)));
+		assert!(!session.record(entry("kz_beginnerblock_go", "KZT", 450)));
+		assert!(session.record(entry("kz_lionharder", "KZT", 500)));
+		assert!(!session.record(entry("kz_lionharder", "KZT", 1000)));
+
+		let spans = session
+			.entries
+			.iter()
+			.map(|entry| (entry.started_at, entry.ended_at))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			spans,
+			[
+				(0, 300),
+				(300, 400),
+				(400, 500),
+				(500, 1000)
+			]
+		);
+		assert_eq!(session.info.ended_at, 1000);
+		assert_eq!(session.mode_switches(), 1);
+
+		let maps = session
+			.maps()
+			.into_iter()
+			.map(|map| (map.map_name, map.seconds, map.entries))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			maps,
+			[
+				(String::from("kz_lionharder"), 900, 3),
+				(String::from("kz_beginnerblock_go"), 100, 1)
+			]
+		);
+	}
+}
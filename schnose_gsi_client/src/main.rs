@@ -5,16 +5,23 @@ use {
 	color_eyre::Result,
 	config::Config,
 	gui::Client,
-	std::{path::PathBuf, sync::Arc},
-	tracing::Level,
+	std::{fs::File, path::PathBuf, sync::Arc},
+	tracing::{warn, Level},
 	tracing_subscriber::fmt::format::FmtSpan,
 };
 
+mod cache;
+mod clan_tag;
 mod config;
 mod gsi;
 mod gui;
+mod headless;
+mod history;
 mod logger;
+mod maps;
+mod schnose_api;
 mod server;
+mod timer;
 
 #[derive(Debug, Parser)]
 struct Args {
@@ -31,6 +38,15 @@ struct Args {
 	/// Custom config file to use instead of the default one.
 	#[arg(short, long = "config")]
 	config_path: Option<PathBuf>,
+
+	/// Run the GSI and overlay servers without opening a window. Can also be set in the config.
+	#[arg(long)]
+	#[clap(default_value = "false")]
+	headless: bool,
+
+	/// Write logs to this file instead of STDOUT when running headless.
+	#[arg(long)]
+	log_file: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -38,6 +54,19 @@ async fn main() -> Result<()> {
 	color_eyre::install()?;
 	let args = Args::parse();
 
+	let (config, recovery, config_path) = match args.config_path {
+		None => {
+			let (config, recovery) = Config::load()?;
+			(config, recovery, Config::get_path()?)
+		}
+		Some(config_path) => {
+			let config_file = std::fs::read_to_string(&config_path)?;
+			(Config::parse(&config_file)?, None, config_path)
+		}
+	};
+
+	let headless = args.headless || config.headless;
+
 	let subscriber = tracing_subscriber::fmt()
 		.compact()
 		.with_file(true)
@@ -48,6 +77,29 @@ async fn main() -> Result<()> {
 			false => Level::INFO,
 		});
 
+	if headless {
+		match args.log_file {
+			None => subscriber.init(),
+			Some(log_file) => {
+				let log_file = File::options()
+					.create(true)
+					.append(true)
+					.open(log_file)?;
+
+				subscriber
+					.with_ansi(false)
+					.with_writer(Arc::new(log_file))
+					.init();
+			}
+		}
+
+		if let Some(recovery) = &recovery {
+			warn!("{recovery}");
+		}
+
+		return headless::run(config).await;
+	}
+
 	let logger = match args.logs_to_stdout {
 		true => {
 			subscriber.init();
@@ -65,13 +117,9 @@ async fn main() -> Result<()> {
 		}
 	};
 
-	let config = match args.config_path {
-		None => Config::load()?,
-		Some(config_path) => {
-			let config_file = std::fs::read_to_string(config_path)?;
-			toml::from_str(&config_file)?
-		}
-	};
+	if let Some(recovery) = &recovery {
+		warn!("{recovery}");
+	}
 
-	Client::init(config, logger).await
+	Client::init(config, config_path, recovery, logger).await
 }
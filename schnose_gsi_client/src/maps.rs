@@ -0,0 +1,41 @@
+//! The global map pool. Knowing which maps are global (and their tiers) locally means we don't
+//! have to ask the GlobalAPI about every map the player loads, including local or unreleased ones.
+
+use {
+	crate::config::Config,
+	schnosebot::global_maps::{MapCache, MapSnapshot, DEFAULT_REFRESH_INTERVAL},
+	tracing::{error, info},
+};
+
+/// Returns an empty pool right away and fills it in the background, first from the snapshot
+/// next to the config file (if there is one) and then from the APIs.
+#[tracing::instrument(skip(gokz_client))]
+pub fn spawn_load(gokz_client: gokz_rs::Client) -> MapCache {
+	let mut maps = MapCache::from_maps(Vec::new(), true);
+
+	match Config::get_path() {
+		Ok(config_path) => {
+			let snapshot_path = config_path.with_file_name("maps.json");
+
+			if let Ok(snapshot) = MapSnapshot::load(&snapshot_path) {
+				info!("Loaded {} maps from `{}`.", snapshot.maps.len(), snapshot_path.display());
+				maps.replace(snapshot.maps);
+			}
+
+			maps = maps.with_snapshot_path(snapshot_path);
+		}
+		Err(why) => error!("Failed to locate map snapshot: {why:?}"),
+	}
+
+	let pool = maps.clone();
+	tokio::spawn(async move {
+		match pool.refresh(&gokz_client).await {
+			Ok(diff) => info!("Fetched {} global maps. ({diff})", pool.maps().len()),
+			Err(why) => error!("Failed to fetch global maps: {why:?}"),
+		}
+
+		pool.spawn_refresh_task(gokz_client, DEFAULT_REFRESH_INTERVAL);
+	});
+
+	maps
+}
@@ -0,0 +1,191 @@
+//! Delivers the current [`State`] to the SchnoseAPI, which is where the Twitch bot gets the
+//! streamer's map and mode from. Only the latest state matters, so updates that pile up while a
+//! request is failing are coalesced, and the last one is re-sent every [`KEEPALIVE`] in case the
+//! API lost it.
+
+use {
+	crate::{config::Config, gui::state::State},
+	chrono::Utc,
+	color_eyre::Result,
+	std::{sync::Arc, time::Duration},
+	tokio::sync::{watch, Mutex},
+	tracing::{debug, error, info},
+};
+
+pub const DEFAULT_URL: &str = "https://schnose.xyz/api/twitch_info";
+
+/// How often the latest state gets re-sent if nothing changes.
+pub const KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Exponential backoff for failed requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+	next: Duration,
+}
+
+impl Backoff {
+	pub const INITIAL: Duration = Duration::from_secs(1);
+	pub const MAX: Duration = Duration::from_secs(60);
+
+	pub const fn new() -> Self {
+		Self { next: Self::INITIAL }
+	}
+
+	/// How long to wait before the next attempt. Doubles every time, up to [`Backoff::MAX`].
+	pub fn next_delay(&mut self) -> Duration {
+		let delay = self.next;
+		self.next = (self.next * 2).min(Self::MAX);
+		delay
+	}
+}
+
+impl Default for Backoff {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStatus {
+	/// Unix timestamp of the last successful POST.
+	pub last_success: Option<i64>,
+	/// Set while requests are failing.
+	pub last_error: Option<String>,
+	/// Failed attempts since the last successful one.
+	pub failures: u32,
+}
+
+/// Handle to the background task sending updates. Cloning this is cheap.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+	sender: watch::Sender<Option<State>>,
+	status: Arc<std::sync::Mutex<SyncStatus>>,
+}
+
+impl Delivery {
+	/// Spawns the delivery task. It stops once every handle has been dropped.
+	pub fn spawn(config: Arc<Mutex<Config>>, gokz_client: gokz_rs::Client) -> Self {
+		let (sender, receiver) = watch::channel(None);
+		let delivery = Self {
+			sender,
+			status: Arc::new(std::sync::Mutex::new(SyncStatus::default())),
+		};
+
+		tokio::spawn(Self::run(receiver, Arc::clone(&delivery.status), config, gokz_client));
+
+		delivery
+	}
+
+	/// Queues `state` for delivery, replacing anything that hasn't been sent yet.
+	pub fn push(&self, state: State) {
+		self.sender.send_replace(Some(state));
+	}
+
+	pub fn status(&self) -> SyncStatus {
+		self.status
+			.lock()
+			.expect("Sync status lock poisoned.")
+			.clone()
+	}
+
+	#[tracing::instrument(skip_all)]
+	async fn run(
+		mut receiver: watch::Receiver<Option<State>>,
+		status: Arc<std::sync::Mutex<SyncStatus>>,
+		config: Arc<Mutex<Config>>,
+		gokz_client: gokz_rs::Client,
+	) {
+		loop {
+			// Either a new state came in, or it's time for a keepalive.
+			if let Ok(Err(_)) = tokio::time::timeout(KEEPALIVE, receiver.changed()).await {
+				return debug!("All delivery handles dropped.");
+			}
+
+			let mut backoff = Backoff::new();
+
+			loop {
+				let Some(state) = receiver.borrow_and_update().clone() else {
+					break;
+				};
+
+				let (url, api_key) = {
+					let config = config.lock().await;
+					(config.schnose_api_url.clone(), config.schnose_api_key.clone())
+				};
+
+				if api_key.is_empty() {
+					break;
+				}
+
+				let result = post(&state, &url, &api_key, &gokz_client).await;
+
+				{
+					let mut status = status
+						.lock()
+						.expect("Sync status lock poisoned.");
+
+					match result {
+						Ok(()) => {
+							status.last_success = Some(Utc::now().timestamp());
+							status.last_error = None;
+							status.failures = 0;
+						}
+						Err(why) => {
+							status.last_error = Some(why.to_string());
+							status.failures += 1;
+						}
+					}
+
+					if status.failures == 0 {
+						break;
+					}
+				}
+
+				// Retry after the backoff, or right away with the newer state if one comes in.
+				let delay = backoff.next_delay();
+				info!("[SchnoseAPI] Retrying in {delay:?}.");
+
+				if let Ok(Err(_)) = tokio::time::timeout(delay, receiver.changed()).await {
+					return;
+				}
+			}
+		}
+	}
+}
+
+#[tracing::instrument(skip(state, api_key, client))]
+async fn post(state: &State, url: &str, api_key: &str, client: &gokz_rs::Client) -> Result<()> {
+	match client
+		.post(url)
+		.json(state)
+		.header("x-schnose-auth-key", api_key)
+		.send()
+		.await
+		.map(|res| res.error_for_status())
+	{
+		Ok(Ok(res)) => {
+			info!("[SchnoseAPI] POST successful: {res:#?}");
+			Ok(())
+		}
+		Ok(Err(why)) | Err(why) => {
+			error!("[SchnoseAPI] POST failed.");
+			debug!("[SchnoseAPI] POST failed: {why:#?}");
+			Err(why.into())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn backoff() {
+		let mut backoff = Backoff::new();
+		let delays = (0..8)
+			.map(|_| backoff.next_delay().as_secs())
+			.collect::<Vec<_>>();
+
+		assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+	}
+}
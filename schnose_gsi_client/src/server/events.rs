@@ -0,0 +1,81 @@
+use {
+	super::AxumState,
+	crate::{
+		cache::{RecordCache, Records},
+		gui::state::{Player, State},
+		timer::TimerStatus,
+	},
+	axum::{
+		extract::State as StateExtractor,
+		response::sse::{Event, KeepAlive, Sse},
+	},
+	futures::{Stream, StreamExt},
+	serde::Serialize,
+	std::convert::Infallible,
+};
+
+/// Everything the overlay needs to display. A new one is pushed to `/events` whenever the state
+/// changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct OverlayEvent {
+	#[serde(flatten)]
+	pub state: State,
+	pub wrs: Records,
+	pub pbs: Records,
+	/// `None` until the player is on a map.
+	pub timer: Option<TimerStatus>,
+}
+
+impl OverlayEvent {
+	/// Fetches WRs and PBs for the current map, if it's global and we know the player's mode.
+	pub async fn new(
+		state: State,
+		timer: Option<TimerStatus>,
+		cache: &RecordCache,
+		gokz_client: &gokz_rs::Client,
+	) -> Self {
+		let (Some(Player { steam_id, mode: Some(mode), .. }), Some(_)) =
+			(&state.player, state.map.tier)
+		else {
+			return Self {
+				state,
+				wrs: (None, None),
+				pbs: (None, None),
+				timer,
+			};
+		};
+
+		let map = || state.map.name.clone().into();
+		let (wrs, pbs) = tokio::join!(
+			cache.wrs(map(), *mode, 0, gokz_client),
+			cache.pbs(*steam_id, map(), *mode, 0, gokz_client),
+		);
+
+		Self { state, wrs, pbs, timer }
+	}
+}
+
+/// Server-Sent Events. Sends the current state right away and then every time it changes.
+pub async fn handler(
+	StateExtractor(AxumState { events, .. }): StateExtractor<AxumState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+	let receiver = events.subscribe();
+
+	let stream = futures::stream::unfold((receiver, true), |(mut receiver, first)| async move {
+		if !first && receiver.changed().await.is_err() {
+			return None;
+		}
+
+		let event = receiver.borrow_and_update().clone();
+		Some((event, (receiver, false)))
+	})
+	.filter_map(|event| async move {
+		Event::default()
+			.event("state")
+			.json_data(event?)
+			.ok()
+	})
+	.map(Ok);
+
+	Sse::new(stream).keep_alive(KeepAlive::default())
+}
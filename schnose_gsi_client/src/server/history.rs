@@ -0,0 +1,58 @@
+use {
+	super::AxumState,
+	crate::history::SessionReport,
+	axum::{
+		extract::{Json, Path, State as StateExtractor},
+		http::StatusCode,
+		response::IntoResponse,
+	},
+	tracing::error,
+};
+
+/// All recorded sessions, newest first.
+pub async fn sessions(
+	StateExtractor(AxumState { history, .. }): StateExtractor<AxumState>,
+) -> impl IntoResponse {
+	let Some(history) = history else {
+		return StatusCode::NOT_FOUND.into_response();
+	};
+
+	let sessions = history.lock().await.sessions().await;
+
+	match sessions {
+		Ok(sessions) => Json(sessions).into_response(),
+		Err(why) => {
+			error!("Failed to fetch sessions: {why:?}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+/// A single session with time spent per map. `current` is the session that is being recorded
+/// right now.
+pub async fn session(
+	Path(session_id): Path<String>,
+	StateExtractor(AxumState { history, .. }): StateExtractor<AxumState>,
+) -> impl IntoResponse {
+	let Some(history) = history else {
+		return StatusCode::NOT_FOUND.into_response();
+	};
+
+	let history = history.lock().await;
+	let session_id = match session_id.as_str() {
+		"current" => history.current.info.id,
+		id => match id.parse() {
+			Ok(id) => id,
+			Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+		},
+	};
+
+	match history.session(session_id).await {
+		Ok(Some(session)) => Json(SessionReport::from(session)).into_response(),
+		Ok(None) => StatusCode::NOT_FOUND.into_response(),
+		Err(why) => {
+			error!("Failed to fetch session #{session_id}: {why:?}");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
@@ -1,41 +1,108 @@
 use {
-	crate::gui::state::State,
+	crate::{cache::RecordCache, config::Config, gui::state::State, history::History},
 	axum::{http::StatusCode, response::IntoResponse, routing::get, Json, Router, Server},
 	gokz_rs::{MapIdentifier, Mode, SteamID},
 	serde::{Deserialize, Serialize},
-	std::{net::SocketAddr, sync::Arc},
-	tokio::sync::Mutex,
+	std::{
+		net::SocketAddr,
+		sync::Arc,
+		time::{Duration, Instant},
+	},
+	tokio::sync::{watch, Mutex},
+	tower_http::services::ServeDir,
+	tracing::{debug, error, info},
 };
 
+mod events;
 mod gsi;
+mod history;
 mod overlay;
 mod pbs;
+mod status;
 mod wrs;
 
-pub const PORT: u16 = 9999;
+pub use {
+	events::OverlayEvent,
+	overlay::{is_valid_name as is_valid_template_name, TEMPLATES},
+};
+
+/// Used if the config doesn't specify an `overlay_port`.
+pub const DEFAULT_PORT: u16 = 9999;
 
 #[derive(Debug, Clone)]
 pub struct AxumState {
+	config: Arc<Mutex<Config>>,
 	state: Arc<Mutex<Option<State>>>,
+	history: Option<Arc<Mutex<History>>>,
+	events: watch::Sender<Option<OverlayEvent>>,
+	cache: Arc<RecordCache>,
+	started_at: Instant,
 	gokz_client: gokz_rs::Client,
 }
 
-pub async fn run(state: Arc<Mutex<Option<State>>>) {
+pub async fn run(
+	config: Arc<Mutex<Config>>,
+	state: Arc<Mutex<Option<State>>>,
+	history: Option<Arc<Mutex<History>>>,
+	events: watch::Sender<Option<OverlayEvent>>,
+	cache: Arc<RecordCache>,
+) {
+	let (port, overlay_dir) = {
+		let config = config.lock().await;
+		(
+			config.overlay_port,
+			config
+				.overlay_dir()
+				.map(ToOwned::to_owned),
+		)
+	};
+
 	let axum_state = AxumState {
+		config,
 		state,
+		history,
+		events,
+		cache,
+		started_at: Instant::now(),
 		gokz_client: gokz_rs::Client::new(),
 	};
 
-	let addr = SocketAddr::from(([127, 0, 0, 1], PORT));
+	let addr = SocketAddr::from(([127, 0, 0, 1], port));
 
-	let router = Router::new()
+	let mut router = Router::new()
 		.route("/", get(overlay::handler))
+		.route("/overlay/:template", get(overlay::named))
 		.route("/gsi", get(gsi::handler))
+		.route("/events", get(events::handler))
 		.route("/pbs", get(pbs::handler))
 		.route("/wrs", get(wrs::handler))
+		.route("/history", get(history::sessions))
+		.route("/history/:id", get(history::session))
+		.route("/status", get(status::handler))
 		.with_state(axum_state);
 
-	Server::bind(&addr)
+	if let Some(overlay_dir) = overlay_dir {
+		info!("Serving overlay assets from `{}`.", overlay_dir.display());
+		router = router.nest_service("/assets", ServeDir::new(overlay_dir));
+	}
+
+	// If we just got restarted, the previous server might not have released the port yet.
+	let mut attempts = 0;
+	let server = loop {
+		match Server::try_bind(&addr) {
+			Ok(server) => break server,
+			Err(why) if attempts < 10 => {
+				debug!("Failed to bind to {addr}, retrying: {why:?}");
+				attempts += 1;
+				tokio::time::sleep(Duration::from_millis(200)).await;
+			}
+			Err(why) => return error!("Failed to bind to {addr}: {why:?}"),
+		}
+	};
+
+	info!("Serving the overlay on {addr}.");
+
+	server
 		.serve(router.into_make_service())
 		.await
 		.expect("Failed to run Axum server.");
@@ -60,4 +127,6 @@ pub struct GlobalAPIParams {
 	pub steam_id: SteamID,
 	pub map_identifier: MapIdentifier,
 	pub mode: Mode,
+	#[serde(default)]
+	pub course: u8,
 }
@@ -1,17 +1,231 @@
+//! The overlay itself. There are a few built-in templates, but users can also put their own
+//! `<name>.html` files into [`Config::overlay_dir`], which take precedence over the built-in ones.
+//! Anything else in that directory (CSS, fonts, images) is served under `/assets`.
+//!
+//! Colors and the font size can be overridden with query parameters, e.g.
+//! `/overlay/vertical?tp_color=e3ad39&font_size=20`. Templates pick them up as CSS variables
+//! (`--text-color`, `--tp-color`, `--pro-color`, `--pb-color`, `--background`, `--font-size`).
+
 use {
-	axum::response::{Html, IntoResponse},
-	std::path::PathBuf,
+	super::AxumState,
+	crate::config::Config,
+	axum::{
+		extract::{Path, Query, State as StateExtractor},
+		http::StatusCode,
+		response::{Html, IntoResponse},
+	},
+	serde::Deserialize,
+	std::fmt::Write,
+	tracing::{error, warn},
 };
 
-pub async fn handler() -> impl IntoResponse {
-	if let Ok(reload_path) = std::env::var("RELOAD_GSI_OVERLAY") {
-		let path = PathBuf::from(reload_path);
-		return Html(
-			tokio::fs::read_to_string(path)
-				.await
-				.expect("Failed to read HTML"),
+/// `(name, HTML)`
+pub const TEMPLATES: [(&str, &str); 3] = [
+	("full", include_str!("../../assets/overlay/full.html")),
+	("minimal", include_str!("../../assets/overlay/minimal.html")),
+	("vertical", include_str!("../../assets/overlay/vertical.html")),
+];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Theme {
+	pub text_color: Option<String>,
+	pub tp_color: Option<String>,
+	pub pro_color: Option<String>,
+	pub pb_color: Option<String>,
+	pub background: Option<String>,
+	/// In pixels.
+	pub font_size: Option<f32>,
+}
+
+impl Theme {
+	/// A `<style>` block setting all the CSS variables that were specified. Invalid values are
+	/// skipped, so nobody can inject arbitrary CSS or HTML through the URL.
+	pub fn to_style(&self) -> Option<String> {
+		let colors = [
+			("text-color", &self.text_color),
+			("tp-color", &self.tp_color),
+			("pro-color", &self.pro_color),
+			("pb-color", &self.pb_color),
+			("background", &self.background),
+		];
+
+		let mut variables = String::new();
+
+		for (name, color) in colors {
+			let Some(color) = color else {
+				continue;
+			};
+
+			match sanitize_color(color) {
+				Some(color) => _ = write!(variables, " --{name}: {color};"),
+				None => warn!("Ignoring invalid color `{color}` for `{name}`."),
+			}
+		}
+
+		match self.font_size {
+			Some(size) if (1.0..=500.0).contains(&size) => {
+				_ = write!(variables, " --font-size: {size}px;");
+			}
+			Some(size) => warn!("Ignoring invalid font size `{size}`."),
+			None => {}
+		}
+
+		(!variables.is_empty()).then(|| format!("<style>:root {{{variables} }}</style>"))
+	}
+
+	/// Inserts [`Theme::to_style`] at the end of the `<head>` (or the start of the document, if
+	/// there is none).
+	pub fn apply(&self, mut html: String) -> String {
+		let Some(style) = self.to_style() else {
+			return html;
+		};
+
+		match html.find("</head>") {
+			Some(idx) => html.insert_str(idx, &style),
+			None => html.insert_str(0, &style),
+		}
+
+		html
+	}
+}
+
+/// Hex colors may be passed without a leading `#`, since that would need to be escaped in a URL.
+/// Otherwise only things like `red` or `rgba(0, 0, 0, 0.5)` are allowed.
+fn sanitize_color(color: &str) -> Option<String> {
+	let color = color.trim();
+	let hex = color.strip_prefix('#').unwrap_or(color);
+
+	if matches!(hex.len(), 3 | 4 | 6 | 8)
+		&& hex
+			.chars()
+			.all(|c| c.is_ascii_hexdigit())
+	{
+		return Some(format!("#{hex}"));
+	}
+
+	let valid = !color.is_empty()
+		&& color
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || "(),.% ".contains(c));
+
+	valid.then(|| color.to_owned())
+}
+
+/// Only plain names, so nobody can read files outside of the overlay directory.
+pub fn is_valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Looks for `<name>.html` in the user's overlay directory first, then in the built-in
+/// templates.
+#[tracing::instrument]
+pub async fn load_template(name: &str, config: &Config) -> Option<String> {
+	if !is_valid_name(name) {
+		return None;
+	}
+
+	if let Some(overlay_dir) = config.overlay_dir() {
+		let path = overlay_dir.join(format!("{name}.html"));
+
+		match tokio::fs::read_to_string(&path).await {
+			Ok(html) => return Some(html),
+			Err(why) if why.kind() == std::io::ErrorKind::NotFound => {}
+			Err(why) => error!("Failed to read overlay template `{}`: {why:?}", path.display()),
+		}
+	}
+
+	TEMPLATES
+		.iter()
+		.find(|(template, _)| *template == name)
+		.map(|(_, html)| (*html).to_owned())
+}
+
+async fn render(name: &str, theme: &Theme, state: &AxumState) -> impl IntoResponse {
+	let config = state.config.lock().await.clone();
+
+	match load_template(name, &config).await {
+		Some(html) => Html(theme.apply(html)).into_response(),
+		None => {
+			(StatusCode::NOT_FOUND, format!("There is no overlay called `{name}`.")).into_response()
+		}
+	}
+}
+
+/// The template selected in the config.
+pub async fn handler(
+	Query(theme): Query<Theme>,
+	StateExtractor(state): StateExtractor<AxumState>,
+) -> impl IntoResponse {
+	let name = state
+		.config
+		.lock()
+		.await
+		.overlay_template
+		.clone();
+
+	render(&name, &theme, &state).await
+}
+
+/// A specific template.
+pub async fn named(
+	Path(name): Path<String>,
+	Query(theme): Query<Theme>,
+	StateExtractor(state): StateExtractor<AxumState>,
+) -> impl IntoResponse {
+	render(&name, &theme, &state).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn colors() {
+		assert_eq!(sanitize_color("e3ad39").as_deref(), Some("#e3ad39"));
+		assert_eq!(sanitize_color("#FFF").as_deref(), Some("#FFF"));
+		assert_eq!(sanitize_color("red").as_deref(), Some("red"));
+		assert_eq!(sanitize_color("rgba(0, 0, 0, 0.5)").as_deref(), Some("rgba(0, 0, 0, 0.5)"));
+		assert_eq!(sanitize_color("red; } body { display: none"), None);
+		assert_eq!(sanitize_color("</style><script>"), None);
+		assert_eq!(sanitize_color(""), None);
+	}
+
+	#[test]
+	fn apply_theme() {
+		let html = String::from("<html><head></head><body></body></html>");
+		assert_eq!(Theme::default().apply(html.clone()), html);
+
+		let theme = Theme {
+			tp_color: Some(String::from("e3ad39")),
+			pb_color: Some(String::from("}")),
+			font_size: Some(20.0),
+			..Default::default()
+		};
+
+		assert_eq!(
+			theme.apply(html),
+			"<html><head><style>:root { --tp-color: #e3ad39; --font-size: 20px; }</style></head><body></body></html>"
 		);
 	}
 
-	Html(include_str!("../../assets/overlay/index.html").to_owned())
+	#[tokio::test]
+	async fn builtin_templates() {
+		let config = Config::default();
+
+		for (name, _) in TEMPLATES {
+			assert!(load_template(name, &config)
+				.await
+				.is_some());
+		}
+
+		assert!(load_template("doesnt_exist", &config)
+			.await
+			.is_none());
+		assert!(load_template("../config", &config)
+			.await
+			.is_none());
+	}
 }
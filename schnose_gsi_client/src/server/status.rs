@@ -0,0 +1,56 @@
+use {
+	super::AxumState,
+	crate::cache::CacheStats,
+	axum::{
+		extract::{Json, State as StateExtractor},
+		response::IntoResponse,
+	},
+	serde::Serialize,
+};
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+	pub version: &'static str,
+	pub uptime_seconds: u64,
+	pub gsi_port: u16,
+	pub overlay_port: u16,
+	/// Whether we have received any events from CS:GO yet.
+	pub connected: bool,
+	pub map: Option<String>,
+	pub player: Option<String>,
+	pub session_id: Option<i64>,
+	pub cache: CacheStats,
+}
+
+/// A quick health check, mostly useful when running with `--headless`.
+pub async fn handler(StateExtractor(axum_state): StateExtractor<AxumState>) -> impl IntoResponse {
+	let (gsi_port, overlay_port) = {
+		let config = axum_state.config.lock().await;
+		(config.gsi_port, config.overlay_port)
+	};
+	let state = axum_state.state.lock().await.clone();
+
+	let session_id = match &axum_state.history {
+		Some(history) => Some(history.lock().await.current.info.id),
+		None => None,
+	};
+
+	Json(Status {
+		version: env!("CARGO_PKG_VERSION"),
+		uptime_seconds: axum_state
+			.started_at
+			.elapsed()
+			.as_secs(),
+		gsi_port,
+		overlay_port,
+		connected: state.is_some(),
+		map: state
+			.as_ref()
+			.map(|state| state.map.name.clone()),
+		player: state
+			.and_then(|state| state.player)
+			.map(|player| player.name),
+		session_id,
+		cache: axum_state.cache.stats(),
+	})
+}
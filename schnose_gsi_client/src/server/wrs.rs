@@ -4,20 +4,15 @@ use {
 		extract::{Json, Query, State as StateExtractor},
 		response::IntoResponse,
 	},
-	gokz_rs::global_api,
 };
 
 pub async fn handler(
-	Query(GlobalAPIParams { map_identifier, mode, .. }): Query<GlobalAPIParams>,
-	StateExtractor(AxumState { gokz_client, .. }): StateExtractor<AxumState>,
+	Query(GlobalAPIParams { map_identifier, mode, course, .. }): Query<GlobalAPIParams>,
+	StateExtractor(AxumState { cache, gokz_client, .. }): StateExtractor<AxumState>,
 ) -> impl IntoResponse {
-	let tp = global_api::get_wr(map_identifier.clone(), mode, true, 0, &gokz_client)
-		.await
-		.ok();
-
-	let pro = global_api::get_wr(map_identifier.clone(), mode, false, 0, &gokz_client)
-		.await
-		.ok();
-
-	Json((tp, pro))
+	Json(
+		cache
+			.wrs(map_identifier, mode, course, &gokz_client)
+			.await,
+	)
 }
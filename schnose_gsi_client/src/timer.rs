@@ -0,0 +1,251 @@
+//! A rough run timer. GOKZ doesn't expose its timer over GSI, so we count attempts instead: a new
+//! attempt starts whenever the map or the mode in the player's clan tag changes, or when one of
+//! the configured [`Trigger`]s fires.
+
+use {
+	crate::{clan_tag::ClanTag, gui::state::Map},
+	gokz_rs::Mode,
+	schnose_gsi::event::round::Phase,
+	serde::{Deserialize, Serialize},
+	std::collections::HashMap,
+	tracing::info,
+};
+
+/// Additional events that count as starting a new attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+	/// The player died, e.g. by falling into a kill trigger.
+	Death,
+	/// The player's scoreboard score changed.
+	Score,
+	/// A new round started.
+	RoundStart,
+}
+
+impl Trigger {
+	fn fired(&self, previous: &Snapshot, current: &Snapshot) -> bool {
+		match self {
+			Self::Death => previous.alive == Some(true) && current.alive == Some(false),
+			Self::Score => previous.score.is_some() && previous.score != current.score,
+			Self::RoundStart => {
+				previous.round != Some(Phase::Live) && current.round == Some(Phase::Live)
+			}
+		}
+	}
+}
+
+/// The parts of a GSI event the timer cares about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+	pub map: String,
+	pub mode: Option<Mode>,
+	pub alive: Option<bool>,
+	pub score: Option<usize>,
+	pub round: Option<Phase>,
+}
+
+impl Snapshot {
+	/// `None` if the player isn't on a map.
+	pub fn from_event(event: &schnose_gsi::Event) -> Option<Self> {
+		let (map, _) = Map::parse_name(&event.map.as_ref()?.name);
+		let player = event.player.as_ref();
+
+		Some(Self {
+			map: map.to_owned(),
+			mode: player
+				.and_then(|player| player.clan.as_deref())
+				.and_then(|clan| ClanTag::parse(clan).mode),
+			alive: player
+				.and_then(|player| player.state.as_ref())
+				.map(|state| state.health > 0),
+			score: player
+				.and_then(|player| player.match_stats.as_ref())
+				.map(|stats| stats.score),
+			round: event.round.map(|round| round.phase),
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Run {
+	pub map: String,
+	/// Starts at 1 for every map.
+	pub attempt: u32,
+	/// Unix timestamp.
+	pub started_at: i64,
+	/// Unix timestamp. `None` while the run is still going.
+	pub ended_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapStats {
+	pub attempts: u32,
+	/// Time spent on the map, not counting the current visit.
+	pub seconds: i64,
+}
+
+/// What the overlay and GUI display.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimerStatus {
+	pub map: String,
+	pub attempt: u32,
+	/// Unix timestamp.
+	pub run_started_at: i64,
+	/// Unix timestamp of when the player loaded into the map this time.
+	pub map_entered_at: i64,
+	/// Time spent on the map during previous visits.
+	pub map_seconds: i64,
+	/// How long the previous attempt took.
+	pub last_run_seconds: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Timer {
+	maps: HashMap<String, MapStats>,
+	previous: Option<Snapshot>,
+	current: Option<Run>,
+	last: Option<Run>,
+	map_entered_at: i64,
+}
+
+impl Timer {
+	/// Feeds a new snapshot into the timer. Returns `true` if a new attempt started.
+	pub fn update(&mut self, snapshot: Snapshot, triggers: &[Trigger], now: i64) -> bool {
+		let map_changed = self
+			.previous
+			.as_ref()
+			.map(|previous| &previous.map)
+			!= Some(&snapshot.map);
+
+		let reason = match &self.previous {
+			None => Some("first event"),
+			Some(previous) if map_changed => {
+				self.maps
+					.entry(previous.map.clone())
+					.or_default()
+					.seconds += now - self.map_entered_at;
+				Some("map change")
+			}
+			Some(previous) if previous.mode != snapshot.mode => Some("mode change"),
+			Some(previous) => triggers
+				.iter()
+				.any(|trigger| trigger.fired(previous, &snapshot))
+				.then_some("trigger"),
+		};
+
+		let Some(reason) = reason else {
+			self.previous = Some(snapshot);
+			return false;
+		};
+
+		if map_changed {
+			self.map_entered_at = now;
+		}
+
+		if let Some(mut run) = self.current.take() {
+			run.ended_at = Some(now);
+			self.last = Some(run);
+		}
+
+		let stats = self
+			.maps
+			.entry(snapshot.map.clone())
+			.or_default();
+		stats.attempts += 1;
+
+		info!("Starting attempt #{} on `{}` ({reason}).", stats.attempts, snapshot.map);
+
+		self.current = Some(Run {
+			map: snapshot.map.clone(),
+			attempt: stats.attempts,
+			started_at: now,
+			ended_at: None,
+		});
+
+		self.previous = Some(snapshot);
+		true
+	}
+
+	pub fn status(&self) -> Option<TimerStatus> {
+		let run = self.current.as_ref()?;
+
+		Some(TimerStatus {
+			map: run.map.clone(),
+			attempt: run.attempt,
+			run_started_at: run.started_at,
+			map_entered_at: self.map_entered_at,
+			map_seconds: self
+				.maps
+				.get(&run.map)
+				.map_or(0, |stats| stats.seconds),
+			last_run_seconds: self
+				.last
+				.as_ref()
+				.filter(|last| last.map == run.map)
+				.and_then(|last| Some(last.ended_at? - last.started_at)),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn snapshot(map: &str, clan: &str) -> Snapshot {
+		Snapshot {
+			map: map.to_owned(),
+			mode: ClanTag::parse(clan).mode,
+			alive: Some(true),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn attempts() {
+		let mut timer = Timer::default();
+		let triggers = [Trigger::Death];
+
+		assert!(timer.update(snapshot("kz_lionharder", "[KZT]"), &triggers, 0));
+		assert!(!timer.update(snapshot("kz_lionharder", "[KZT]"), &triggers, 10));
+
+		let dead = Snapshot {
+			alive: Some(false),
+			..snapshot("kz_lionharder", "[KZT]")
+		};
+		assert!(timer.update(dead, &triggers, 20));
+		assert!(!timer.update(snapshot("kz_lionharder", "[KZT]"), &triggers, 25));
+
+		let status = timer.status().unwrap();
+		assert_eq!((status.attempt, status.run_started_at), (2, 20));
+		assert_eq!(status.last_run_seconds, Some(20));
+
+		// Mode change
+		assert!(timer.update(snapshot("kz_lionharder", "[SKZ]"), &triggers, 30));
+		assert_eq!(timer.status().unwrap().attempt, 3);
+
+		// Deaths don't count if the trigger is disabled.
+		let dead = Snapshot {
+			alive: Some(false),
+			..snapshot("kz_lionharder", "[SKZ]")
+		};
+		assert!(!timer.update(dead, &[], 40));
+	}
+
+	#[test]
+	fn map_changes() {
+		let mut timer = Timer::default();
+
+		timer.update(snapshot("kz_lionharder", "[KZT]"), &[], 0);
+		timer.update(snapshot("kz_beginnerblock_go", "[KZT]"), &[], 100);
+
+		let status = timer.status().unwrap();
+		assert_eq!((status.attempt, status.map_entered_at, status.map_seconds), (1, 100, 0));
+		assert_eq!(status.last_run_seconds, None);
+
+		timer.update(snapshot("kz_lionharder", "[KZT]"), &[], 250);
+
+		let status = timer.status().unwrap();
+		assert_eq!((status.attempt, status.map_entered_at, status.map_seconds), (2, 250, 100));
+	}
+}
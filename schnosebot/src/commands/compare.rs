@@ -0,0 +1,238 @@
+use {
+	super::no_records_ok,
+	crate::global_maps::GlobalMap,
+	color_eyre::{eyre::eyre, Result},
+	gokz_rs::{global_api, Mode, PlayerIdentifier, Tier},
+	std::collections::HashMap,
+};
+
+/// Head-to-head comparison of two players. All tuples are `(first, second)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+	pub players: (String, String),
+	pub mode: Mode,
+	pub tp: bool,
+
+	/// Maps both players have finished, sorted by name.
+	pub shared: Vec<SharedMap>,
+	pub only_first: Vec<GlobalMap>,
+	pub only_second: Vec<GlobalMap>,
+
+	/// Points per tier. Index 0 is the total, the other indices match their tier.
+	pub points: [(u32, u32); 8],
+}
+
+/// A map both players have finished.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedMap {
+	pub name: String,
+	pub tier: Tier,
+	pub times: (f64, f64),
+}
+
+impl SharedMap {
+	/// Negative if the first player is faster.
+	pub fn difference(&self) -> f64 {
+		self.times.0 - self.times.1
+	}
+}
+
+impl Comparison {
+	/// Only records on maps in `map_pool` are counted, and only once per map.
+	pub fn compute(
+		players: (String, String),
+		mode: Mode,
+		tp: bool,
+		records: (&[global_api::Record], &[global_api::Record]),
+		map_pool: &[GlobalMap],
+	) -> Self {
+		let maps = map_pool
+			.iter()
+			.map(|map| (map.id, map))
+			.collect::<HashMap<_, _>>();
+
+		let (first, second) = (best_times(records.0, &maps), best_times(records.1, &maps));
+		let mut points = [(0, 0); 8];
+		let mut shared = Vec::new();
+		let mut only_first = Vec::new();
+		let mut only_second = Vec::new();
+
+		for (map_id, (map, time, record_points)) in &first {
+			points[0].0 += record_points;
+			points[map.tier as usize].0 += record_points;
+
+			match second.get(map_id) {
+				Some((_, other_time, _)) => shared.push(SharedMap {
+					name: map.name.clone(),
+					tier: map.tier,
+					times: (*time, *other_time),
+				}),
+				None => only_first.push((*map).to_owned()),
+			}
+		}
+
+		for (map_id, (map, _, record_points)) in &second {
+			points[0].1 += record_points;
+			points[map.tier as usize].1 += record_points;
+
+			if !first.contains_key(map_id) {
+				only_second.push((*map).to_owned());
+			}
+		}
+
+		shared.sort_by(|a, b| a.name.cmp(&b.name));
+		only_first.sort_by(|a, b| a.name.cmp(&b.name));
+		only_second.sort_by(|a, b| a.name.cmp(&b.name));
+
+		Self {
+			players,
+			mode,
+			tp,
+			shared,
+			only_first,
+			only_second,
+			points,
+		}
+	}
+
+	/// How many shared maps each player is faster on. Ties don't count for either.
+	pub fn wins(&self) -> (usize, usize) {
+		self.shared
+			.iter()
+			.fold((0, 0), |(first, second), map| {
+				let difference = map.difference();
+				if difference < 0.0 {
+					(first + 1, second)
+				} else if difference > 0.0 {
+					(first, second + 1)
+				} else {
+					(first, second)
+				}
+			})
+	}
+}
+
+/// The fastest record per map, ignoring maps that aren't in `maps`.
+fn best_times<'a>(
+	records: &[global_api::Record],
+	maps: &HashMap<u16, &'a GlobalMap>,
+) -> HashMap<u16, (&'a GlobalMap, f64, u32)> {
+	let mut times = HashMap::<u16, (&GlobalMap, f64, u32)>::new();
+	for record in records {
+		let Some(map) = maps.get(&record.map_id) else {
+			continue;
+		};
+
+		match times.get(&record.map_id) {
+			Some((_, time, _)) if *time <= record.time => {}
+			_ => {
+				times.insert(record.map_id, (*map, record.time, record.points));
+			}
+		}
+	}
+	times
+}
+
+/// Fetches both players' records and compares them.
+#[tracing::instrument(skip(map_pool, gokz_client))]
+pub async fn execute(
+	first: PlayerIdentifier,
+	second: PlayerIdentifier,
+	mode: Mode,
+	tp: bool,
+	map_pool: &[GlobalMap],
+	gokz_client: &gokz_rs::Client,
+) -> Result<Comparison> {
+	let (first_records, second_records) = tokio::join!(
+		global_api::get_player_records(first.clone(), mode, tp, 0, 9999, gokz_client),
+		global_api::get_player_records(second.clone(), mode, tp, 0, 9999, gokz_client),
+	);
+
+	let first_records = no_records_ok(first_records)?;
+	let second_records = no_records_ok(second_records)?;
+
+	let player_name = |records: &[global_api::Record], player: &PlayerIdentifier| {
+		records
+			.first()
+			.map(|record| record.player_name.clone())
+			.ok_or_else(|| eyre!("`{player}` has no records."))
+	};
+
+	let players = (player_name(&first_records, &first)?, player_name(&second_records, &second)?);
+
+	Ok(Comparison::compute(players, mode, tp, (&first_records, &second_records), map_pool))
+}
+
+#[cfg(test)]
+mod tests {
+	use {
+		super::*,
+		crate::commands::tests::{global_api_record, global_map},
+	};
+
+	fn record(id: u32, map_id: u16, time: f64, points: u32) -> global_api::Record {
+		let mut record = global_api_record(id, 0, 0);
+		record.map_id = map_id;
+		record.time = time;
+		record.points = points;
+		record
+	}
+
+	#[test]
+	fn compare_players() {
+		let lionharder = global_map();
+		let mut beginnerblock = global_map();
+		beginnerblock.id = 291;
+		beginnerblock.name = String::from("kz_beginnerblock_go");
+		beginnerblock.tier = Tier::VeryEasy;
+		let mut checkmate = global_map();
+		checkmate.id = 197;
+		checkmate.name = String::from("kz_checkmate");
+		checkmate.tier = Tier::Medium;
+
+		let first = [
+			record(1, 992, 700.0, 1000),
+			// slower duplicate, ignored
+			record(3, 291, 20.0, 500),
+			record(2, 291, 10.0, 900),
+			// not in the map pool
+			record(4, 1, 5.0, 1000),
+		];
+		let second = [
+			record(5, 992, 727.727, 800),
+			record(6, 291, 9.5, 950),
+			record(7, 197, 60.0, 700),
+		];
+
+		let comparison = Comparison::compute(
+			(String::from("AlphaKeks"), String::from("Szwagi")),
+			Mode::SimpleKZ,
+			false,
+			(&first, &second),
+			&[
+				lionharder,
+				beginnerblock,
+				checkmate.clone(),
+			],
+		);
+
+		let shared = comparison
+			.shared
+			.iter()
+			.map(|map| (map.name.as_str(), map.times))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			shared,
+			[
+				("kz_beginnerblock_go", (10.0, 9.5)),
+				("kz_lionharder", (700.0, 727.727))
+			]
+		);
+		assert_eq!(comparison.wins(), (1, 1));
+		assert!(comparison.only_first.is_empty());
+		assert_eq!(comparison.only_second, [checkmate]);
+		assert_eq!(comparison.points[0], (1900, 2450));
+		assert_eq!(comparison.points[Tier::VeryEasy as usize], (900, 950));
+		assert_eq!(comparison.points[Tier::Medium as usize], (0, 700));
+	}
+}
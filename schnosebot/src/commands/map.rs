@@ -0,0 +1,372 @@
+use {
+	super::{no_records_ok, pb, Record, RecordLookup},
+	crate::global_maps::GlobalMap,
+	chrono::NaiveDateTime,
+	gokz_rs::{
+		global_api::{self, records::top},
+		Mode, PlayerIdentifier, Tier,
+	},
+	std::{
+		collections::HashMap,
+		sync::{Arc, Mutex},
+		time::{Duration, Instant},
+	},
+	tracing::warn,
+};
+
+/// Upper limit for the records fetched per mode and runtype. [`global_api::get_maptop`] stops at
+/// 100, which would make every popular map look like it had exactly 100 completions.
+pub const COMPLETIONS_LIMIT: u32 = 9999;
+
+/// How long completion counts are cached for. Counting them means fetching entire leaderboards,
+/// so this only happens once per map and mode in that time.
+pub const COMPLETIONS_TTL: Duration = Duration::from_secs(60 * 30);
+
+/// Detailed information about a map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapInfo {
+	pub name: String,
+	pub tier: Tier,
+	pub bonuses: usize,
+	pub mapper_name: String,
+
+	/// The mapper's Steam profile, if we know their SteamID.
+	pub mapper_url: Option<String>,
+	pub validated: bool,
+	pub created_on: NaiveDateTime,
+	pub updated_on: NaiveDateTime,
+
+	/// Which modes have a record filter on the main course.
+	pub filters: [(Mode, bool); 3],
+	pub url: String,
+	pub thumbnail: String,
+}
+
+pub fn execute(map: GlobalMap) -> MapInfo {
+	MapInfo {
+		bonuses: map.courses.len().saturating_sub(1),
+		mapper_url: map
+			.mapper_steam_id
+			.map(|steam_id| format!("https://steamcommunity.com/profiles/{}", steam_id.as_id64())),
+		filters: [
+			(Mode::KZTimer, map.kzt),
+			(Mode::SimpleKZ, map.skz),
+			(Mode::Vanilla, map.vnl),
+		],
+		name: map.name,
+		tier: map.tier,
+		mapper_name: map.mapper_name,
+		validated: map.validated,
+		created_on: map.created_on,
+		updated_on: map.updated_on,
+		url: map.url,
+		thumbnail: map.thumbnail,
+	}
+}
+
+/// A bonus course.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bonus {
+	pub course: u8,
+
+	/// The difficulty in the first mode that has a record filter on this course.
+	pub tier: Option<Tier>,
+
+	/// Which modes have a record filter on this course.
+	pub filters: [(Mode, bool); 3],
+}
+
+impl Bonus {
+	/// The bonus' KZ:GO page for `mode`.
+	pub fn url(&self, map: &MapInfo, mode: Mode) -> String {
+		format!("{}?{}=&bonus={}", map.url, mode.short().to_lowercase(), self.course)
+	}
+}
+
+/// Completions and world records in a single mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeStats {
+	pub mode: Mode,
+	pub tp_completions: usize,
+	pub pro_completions: usize,
+	pub tp_wr: Option<Record>,
+	pub pro_wr: Option<Record>,
+}
+
+impl ModeStats {
+	/// `"N+"` if the count hit [`COMPLETIONS_LIMIT`], since there might be more.
+	pub fn fmt_completions(count: usize) -> String {
+		if count >= COMPLETIONS_LIMIT as usize {
+			format!("{count}+")
+		} else {
+			count.to_string()
+		}
+	}
+}
+
+/// TP and PRO completions on a map's main course.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Completions {
+	tp: usize,
+	pro: usize,
+	fetched_at: Instant,
+}
+
+/// Completion counts per map and mode, kept for [`COMPLETIONS_TTL`]. This is cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionsCache {
+	counts: Arc<Mutex<HashMap<(u16, Mode), Completions>>>,
+}
+
+impl CompletionsCache {
+	fn get(&self, map_id: u16, mode: Mode, now: Instant) -> Option<Completions> {
+		self.counts
+			.lock()
+			.expect("Completions cache lock poisoned.")
+			.get(&(map_id, mode))
+			.filter(|completions| now.duration_since(completions.fetched_at) < COMPLETIONS_TTL)
+			.copied()
+	}
+
+	/// Also drops expired entries, so maps nobody looks up anymore don't stick around.
+	fn insert(&self, map_id: u16, mode: Mode, completions: Completions) {
+		let mut counts = self
+			.counts
+			.lock()
+			.expect("Completions cache lock poisoned.");
+
+		counts.retain(|_, cached| {
+			completions
+				.fetched_at
+				.duration_since(cached.fetched_at)
+				< COMPLETIONS_TTL
+		});
+		counts.insert((map_id, mode), completions);
+	}
+}
+
+/// [`MapInfo`] plus everything that needs extra requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapDetails {
+	pub info: MapInfo,
+	pub bonuses: Vec<Bonus>,
+
+	/// Only modes with a record filter on the main course.
+	pub modes: Vec<ModeStats>,
+
+	/// Modes with a record filter whose stats couldn't be fetched.
+	pub unavailable: Vec<Mode>,
+
+	/// The requested player's PBs, if a player was requested.
+	pub pb: Option<RecordLookup>,
+}
+
+/// The map's bonuses, sorted by course.
+pub fn bonuses(map: &GlobalMap) -> Vec<Bonus> {
+	let mut bonuses = map
+		.courses
+		.iter()
+		.filter(|course| course.stage > 0)
+		.map(|course| {
+			let filters = [
+				(Mode::KZTimer, course.kzt, course.kzt_difficulty),
+				(Mode::SimpleKZ, course.skz, course.skz_difficulty),
+				(Mode::Vanilla, course.vnl, course.vnl_difficulty),
+			];
+
+			Bonus {
+				course: course.stage,
+				tier: filters
+					.iter()
+					.find(|(_, has_filter, _)| *has_filter)
+					.and_then(|(_, _, difficulty)| Tier::try_from(*difficulty).ok()),
+				filters: filters.map(|(mode, has_filter, _)| (mode, has_filter)),
+			}
+		})
+		.collect::<Vec<_>>();
+
+	bonuses.sort_by_key(|bonus| bonus.course);
+	bonuses
+}
+
+/// Like [`execute`], but also fetches completions and world records for every mode. If `player` is
+/// specified, their PBs in the given mode are fetched as well.
+#[tracing::instrument(skip(completions, gokz_client))]
+pub async fn details(
+	map: GlobalMap,
+	player: Option<(PlayerIdentifier, Mode)>,
+	completions: &CompletionsCache,
+	gokz_client: &gokz_rs::Client,
+) -> MapDetails {
+	let bonuses = bonuses(&map);
+	let map_id = map.id;
+	let [kzt, skz, vnl] = [
+		(Mode::KZTimer, map.kzt),
+		(Mode::SimpleKZ, map.skz),
+		(Mode::Vanilla, map.vnl),
+	]
+	.map(|(mode, has_filter)| async move {
+		match has_filter {
+			true => Some((mode, mode_stats(map_id, mode, completions, gokz_client).await)),
+			false => None,
+		}
+	});
+
+	let (kzt, skz, vnl, pb) = tokio::join!(kzt, skz, vnl, async {
+		match player {
+			Some((player, mode)) => {
+				Some(pb::execute(map.clone(), player, mode, 0, gokz_client).await)
+			}
+			None => None,
+		}
+	});
+
+	let mut modes = Vec::new();
+	let mut unavailable = Vec::new();
+	for (mode, stats) in [kzt, skz, vnl].into_iter().flatten() {
+		match stats {
+			Ok(stats) => modes.push(stats),
+			Err(why) => {
+				warn!("Failed to fetch {mode} stats for map #{map_id}: {why:?}");
+				unavailable.push(mode);
+			}
+		}
+	}
+
+	MapDetails {
+		info: execute(map),
+		bonuses,
+		modes,
+		unavailable,
+		pb,
+	}
+}
+
+/// Only fetches the whole leaderboard if the completion counts aren't cached yet, otherwise just
+/// the world records.
+async fn mode_stats(
+	map_id: u16,
+	mode: Mode,
+	completions: &CompletionsCache,
+	gokz_client: &gokz_rs::Client,
+) -> Result<ModeStats, gokz_rs::Error> {
+	let cached = completions.get(map_id, mode, Instant::now());
+	let limit = if cached.is_some() { 1 } else { COMPLETIONS_LIMIT };
+	let params = |tp| top::Params {
+		map_id: Some(map_id),
+		modes_list_string: Some(mode.api()),
+		has_teleports: Some(tp),
+		stage: Some(0),
+		limit: Some(limit),
+		..Default::default()
+	};
+
+	let (tp, pro) = tokio::join!(
+		global_api::records::get_top(params(true), gokz_client),
+		global_api::records::get_top(params(false), gokz_client),
+	);
+
+	let tp = no_records_ok(tp)?;
+	let pro = no_records_ok(pro)?;
+
+	let counts = cached.unwrap_or_else(|| {
+		let counts = Completions {
+			tp: tp.len(),
+			pro: pro.len(),
+			fetched_at: Instant::now(),
+		};
+		completions.insert(map_id, mode, counts);
+		counts
+	});
+
+	Ok(ModeStats {
+		mode,
+		tp_completions: counts.tp,
+		pro_completions: counts.pro,
+		tp_wr: tp.into_iter().next().map(Record::from),
+		pro_wr: pro.into_iter().next().map(Record::from),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, crate::global_maps::tests::map, gokz_rs::schnose_api::maps::Course};
+
+	fn course(stage: u8, kzt: bool, difficulty: u8) -> Course {
+		Course {
+			id: u32::from(stage),
+			stage,
+			kzt,
+			kzt_difficulty: difficulty,
+			skz: true,
+			skz_difficulty: difficulty + 1,
+			vnl: false,
+			vnl_difficulty: difficulty,
+		}
+	}
+
+	#[test]
+	fn list_bonuses() {
+		let mut lionharder = map(992, "kz_lionharder", Tier::VeryHard);
+		lionharder.courses = vec![
+			course(0, true, 5),
+			course(2, false, 3),
+			course(1, true, 4),
+		];
+
+		let bonuses = bonuses(&lionharder);
+		assert_eq!(
+			bonuses
+				.iter()
+				.map(|bonus| (bonus.course, bonus.tier))
+				.collect::<Vec<_>>(),
+			[
+				(1, Some(Tier::Hard)),
+				(2, Some(Tier::Hard))
+			]
+		);
+		assert_eq!(
+			bonuses[1].filters,
+			[
+				(Mode::KZTimer, false),
+				(Mode::SimpleKZ, true),
+				(Mode::Vanilla, false)
+			]
+		);
+
+		let info = execute(lionharder);
+		assert_eq!(info.bonuses, 2);
+		assert_eq!(
+			bonuses[0].url(&info, Mode::SimpleKZ),
+			"https://kzgo.eu/maps/kz_lionharder?skz=&bonus=1"
+		);
+	}
+
+	#[test]
+	fn cache_completions() {
+		let cache = CompletionsCache::default();
+		let now = Instant::now();
+		let counts = Completions { tp: 727, pro: 69, fetched_at: now };
+
+		cache.insert(992, Mode::SimpleKZ, counts);
+		assert_eq!(cache.get(992, Mode::SimpleKZ, now), Some(counts));
+		assert_eq!(cache.get(992, Mode::KZTimer, now), None);
+		assert_eq!(cache.get(992, Mode::SimpleKZ, now + COMPLETIONS_TTL), None);
+
+		cache.insert(
+			1,
+			Mode::KZTimer,
+			Completions {
+				fetched_at: now + COMPLETIONS_TTL,
+				..counts
+			},
+		);
+		assert_eq!(cache.counts.lock().unwrap().len(), 1);
+	}
+
+	#[test]
+	fn mark_capped_completions() {
+		assert_eq!(ModeStats::fmt_completions(727), "727");
+		assert_eq!(ModeStats::fmt_completions(COMPLETIONS_LIMIT as usize), "9999+");
+	}
+}
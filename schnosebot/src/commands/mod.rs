@@ -0,0 +1,246 @@
+//! Transport-agnostic implementations of the commands both bots share.
+//!
+//! Every command takes structured input (map, mode, course, player, ...) and returns structured
+//! output. Turning that output into an embed or a chat message is up to the bots.
+
+use {
+	crate::global_maps::GlobalMap,
+	chrono::NaiveDateTime,
+	gokz_rs::{global_api, schnose_api, Mode, SteamID},
+};
+
+pub mod compare;
+pub mod map;
+pub mod pb;
+pub mod profile;
+pub mod recent;
+pub mod wr;
+
+/// A single record, independent of the API it was fetched from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+	pub id: u32,
+	pub player_name: String,
+	pub steam_id: SteamID,
+	pub map_name: String,
+	pub course: u8,
+	pub mode: Mode,
+	pub time: f64,
+	pub teleports: u32,
+
+	/// Leaderboard position of this record, if it was requested.
+	pub place: Option<u32>,
+	pub replay: Option<ReplayLinks>,
+	pub created_on: NaiveDateTime,
+}
+
+/// Links for a global replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayLinks {
+	/// Watch the replay online with [GC's replay viewer](https://github.com/GameChaos/GlobalReplays).
+	pub view: String,
+	pub download: String,
+}
+
+impl Record {
+	pub const fn is_tp(&self) -> bool {
+		self.teleports > 0
+	}
+
+	pub const fn runtype(&self) -> &'static str {
+		if self.is_tp() {
+			"TP"
+		} else {
+			"PRO"
+		}
+	}
+
+	/// The player's KZ:GO profile.
+	pub fn player_url(&self) -> String {
+		format!("https://kzgo.eu/players/{}?{}=", self.steam_id, self.mode.short().to_lowercase())
+	}
+}
+
+impl From<global_api::Record> for Record {
+	fn from(record: global_api::Record) -> Self {
+		let replay = match (record.replay_view_link(), record.replay_download_link()) {
+			(Some(view), Some(download)) => Some(ReplayLinks { view, download }),
+			_ => None,
+		};
+
+		Self {
+			id: record.id,
+			player_name: record.player_name,
+			steam_id: record.steam_id,
+			map_name: record.map_name,
+			course: record.stage,
+			mode: record.mode,
+			time: record.time,
+			teleports: record.teleports,
+			place: None,
+			replay,
+			created_on: record.created_on,
+		}
+	}
+}
+
+impl From<schnose_api::Record> for Record {
+	fn from(record: schnose_api::Record) -> Self {
+		Self {
+			id: record.id,
+			player_name: record.player.name,
+			steam_id: record.player.steam_id,
+			map_name: record.map_name,
+			course: record.course.stage,
+			mode: record.mode,
+			time: record.time,
+			teleports: record.teleports,
+			place: None,
+			replay: None,
+			created_on: record.created_on,
+		}
+	}
+}
+
+/// The result of looking up the TP and PRO record of a map / course.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordLookup {
+	pub map: GlobalMap,
+	pub mode: Mode,
+	pub course: u8,
+	pub tp: Option<Record>,
+	pub pro: Option<Record>,
+}
+
+impl RecordLookup {
+	/// The map's KZ:GO page for the looked up mode / course.
+	pub fn map_url(&self) -> String {
+		let mut url = format!("{}?{}=", self.map.url, self.mode.short().to_lowercase());
+		if self.course > 0 {
+			url.push_str(&format!("&bonus={}", self.course));
+		}
+		url
+	}
+
+	/// The name of whoever set the records. Prefers the PRO record's name since that one is more
+	/// likely to be recent.
+	pub fn player_name(&self) -> Option<&str> {
+		self.pro
+			.as_ref()
+			.or(self.tp.as_ref())
+			.map(|record| record.player_name.as_str())
+	}
+
+	pub const fn is_empty(&self) -> bool {
+		self.tp.is_none() && self.pro.is_none()
+	}
+}
+
+/// Fetches the place of `record` and stores it in the record. Failing to fetch the place is not
+/// considered an error.
+async fn fetch_place(mut record: Record, gokz_client: &gokz_rs::Client) -> Record {
+	record.place = global_api::get_place(record.id, gokz_client)
+		.await
+		.ok();
+	record
+}
+
+/// The GlobalAPI responds with an empty body instead of an empty list if there are no records.
+/// Every other error is passed on.
+pub fn no_records_ok<T>(result: Result<Vec<T>, gokz_rs::Error>) -> Result<Vec<T>, gokz_rs::Error> {
+	match result {
+		Err(gokz_rs::Error::EmptyResponse) => Ok(Vec::new()),
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, gokz_rs::Tier};
+
+	pub(crate) fn global_api_record(id: u32, teleports: u32, replay_id: u32) -> global_api::Record {
+		global_api::Record {
+			id,
+			player_name: String::from("AlphaKeks"),
+			steam_id: SteamID::new("STEAM_1:1:161178172").unwrap(),
+			map_id: 992,
+			map_name: String::from("kz_lionharder"),
+			stage: 0,
+			mode: Mode::SimpleKZ,
+			server_id: 0,
+			server_name: String::from("Hikari KZ"),
+			time: 727.727,
+			teleports,
+			points: 1000,
+			replay_id,
+			tickrate: 128,
+			record_filter_id: 0,
+			created_on: NaiveDateTime::default(),
+			updated_on: NaiveDateTime::default(),
+		}
+	}
+
+	pub(crate) fn global_map() -> GlobalMap {
+		GlobalMap {
+			id: 992,
+			name: String::from("kz_lionharder"),
+			tier: Tier::Extreme,
+			courses: Vec::new(),
+			kzt: true,
+			skz: true,
+			vnl: false,
+			mapper_name: String::from("Chuckles"),
+			mapper_steam_id: None,
+			filesize: 0,
+			validated: true,
+			created_on: NaiveDateTime::default(),
+			updated_on: NaiveDateTime::default(),
+			url: String::from("https://kzgo.eu/maps/kz_lionharder"),
+			thumbnail: String::new(),
+		}
+	}
+
+	#[test]
+	fn convert_global_api_record() {
+		let record = Record::from(global_api_record(1, 3, 0));
+		assert_eq!(record.runtype(), "TP");
+		assert_eq!(record.replay, None);
+		assert_eq!(record.player_url(), "https://kzgo.eu/players/STEAM_1:1:161178172?skz=");
+
+		let record = Record::from(global_api_record(2, 0, 42));
+		assert_eq!(record.runtype(), "PRO");
+		assert!(record
+			.replay
+			.unwrap()
+			.download
+			.ends_with("/records/replay/42"));
+	}
+
+	#[test]
+	fn lookup_urls_and_names() {
+		let mut lookup = RecordLookup {
+			map: global_map(),
+			mode: Mode::KZTimer,
+			course: 0,
+			tp: None,
+			pro: None,
+		};
+
+		assert!(lookup.is_empty());
+		assert_eq!(lookup.player_name(), None);
+		assert_eq!(lookup.map_url(), "https://kzgo.eu/maps/kz_lionharder?kzt=");
+
+		lookup.course = 2;
+		lookup.tp = Some(global_api_record(1, 3, 0).into());
+		assert_eq!(lookup.player_name(), Some("AlphaKeks"));
+		assert_eq!(lookup.map_url(), "https://kzgo.eu/maps/kz_lionharder?kzt=&bonus=2");
+	}
+
+	#[test]
+	fn only_empty_responses_are_ok() {
+		assert!(no_records_ok::<u8>(Err(gokz_rs::Error::EmptyResponse))
+			.unwrap()
+			.is_empty());
+		assert!(no_records_ok::<u8>(Err(gokz_rs::Error::Custom("timeout"))).is_err());
+	}
+}
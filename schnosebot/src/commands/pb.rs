@@ -0,0 +1,38 @@
+use {
+	super::{fetch_place, RecordLookup},
+	crate::global_maps::GlobalMap,
+	gokz_rs::{global_api, Mode, PlayerIdentifier},
+};
+
+/// A player's personal bests on a map, including their place on the leaderboard. `course` 0 is the
+/// main course, anything above that is a bonus.
+#[tracing::instrument(skip(gokz_client))]
+pub async fn execute(
+	map: GlobalMap,
+	player: PlayerIdentifier,
+	mode: Mode,
+	course: u8,
+	gokz_client: &gokz_rs::Client,
+) -> RecordLookup {
+	let (tp, pro) = tokio::join!(
+		global_api::get_pb(player.clone(), map.id.into(), mode, true, course, gokz_client),
+		global_api::get_pb(player.clone(), map.id.into(), mode, false, course, gokz_client),
+	);
+
+	let (tp, pro) = tokio::join!(
+		async {
+			match tp {
+				Ok(record) => Some(fetch_place(record.into(), gokz_client).await),
+				Err(_) => None,
+			}
+		},
+		async {
+			match pro {
+				Ok(record) => Some(fetch_place(record.into(), gokz_client).await),
+				Err(_) => None,
+			}
+		},
+	);
+
+	RecordLookup { map, mode, course, tp, pro }
+}
@@ -0,0 +1,213 @@
+use {
+	crate::global_maps::GlobalMap,
+	color_eyre::{eyre::eyre, Result},
+	gokz_rs::{
+		global_api,
+		kzgo_api::{self, CompletionStats},
+		schnose_api::{self, FancyPlayer},
+		Mode, PlayerIdentifier, Rank,
+	},
+	std::collections::HashMap,
+};
+
+/// Points, completion and WR stats for a player in a specific mode.
+#[derive(Debug, Clone)]
+pub struct Profile {
+	pub player: FancyPlayer,
+	pub mode: Mode,
+	pub stats: ProfileStats,
+
+	/// How many maps there are to complete in `mode`.
+	pub completion_stats: CompletionStats,
+	pub rank: Rank,
+}
+
+impl Profile {
+	/// Total amount of (TP, PRO) records in [`Self::mode`], including non-pbs.
+	pub const fn total_records(&self) -> (u32, u32) {
+		let records = match self.mode {
+			Mode::KZTimer => &self.player.records.kzt,
+			Mode::SimpleKZ => &self.player.records.skz,
+			Mode::Vanilla => &self.player.records.vnl,
+		};
+
+		(records.tp, records.pro)
+	}
+
+	/// Completion percentages per tier. Index 0 is the total, the other indices match their tier.
+	pub fn completion_percentages(&self) -> [(f64, f64); 8] {
+		self.stats
+			.completion_percentages(&self.completion_stats)
+	}
+}
+
+/// Stats computed from a player's TP and PRO records. All tuples are `(TP, PRO)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileStats {
+	pub points: (u32, u32),
+	pub wrs: (u32, u32),
+
+	/// Index 0 is the total amount of completions, the other indices match their tier.
+	pub completions: [(u32, u32); 8],
+}
+
+impl ProfileStats {
+	/// Only records on maps in `map_pool` are counted, and only once per map.
+	pub fn compute(
+		tp: &[global_api::Record],
+		pro: &[global_api::Record],
+		map_pool: &[GlobalMap],
+	) -> Self {
+		let mut stats = Self::default();
+		let mut tp_maps = map_pool
+			.iter()
+			.map(|map| (map.id, map.tier as usize))
+			.collect::<HashMap<_, _>>();
+		let mut pro_maps = tp_maps.clone();
+
+		for record in tp {
+			if let Some(tier) = tp_maps.remove(&record.map_id) {
+				stats.points.0 += record.points;
+				stats.completions[0].0 += 1;
+				stats.completions[tier].0 += 1;
+
+				if record.points == 1000 {
+					stats.wrs.0 += 1;
+				}
+			}
+		}
+
+		for record in pro {
+			if let Some(tier) = pro_maps.remove(&record.map_id) {
+				stats.points.1 += record.points;
+				stats.completions[0].1 += 1;
+				stats.completions[tier].1 += 1;
+
+				if record.points == 1000 {
+					stats.wrs.1 += 1;
+				}
+			}
+		}
+
+		stats
+	}
+
+	pub const fn total_points(&self) -> u32 {
+		self.points.0 + self.points.1
+	}
+
+	pub fn completion_percentages(&self, completion_stats: &CompletionStats) -> [(f64, f64); 8] {
+		let percentage = |count: u32, max_count: u16| {
+			if count == 0 || max_count == 0 {
+				return 0f64;
+			}
+			(count as f64 / max_count as f64) * 100f64
+		};
+
+		let mut percentages = [(0f64, 0f64); 8];
+		for (i, (tp, pro)) in self.completions.iter().enumerate() {
+			percentages[i] = (
+				percentage(*tp, completion_stats.tp[i]),
+				percentage(*pro, completion_stats.pro[i]),
+			);
+		}
+
+		percentages
+	}
+}
+
+/// Fetches everything needed for a player's profile in a specific mode.
+#[tracing::instrument(skip(map_pool, gokz_client))]
+pub async fn execute(
+	player: PlayerIdentifier,
+	mode: Mode,
+	map_pool: &[GlobalMap],
+	gokz_client: &gokz_rs::Client,
+) -> Result<Profile> {
+	let (fancy_player, tp, pro, completion_stats) = tokio::join!(
+		schnose_api::get_player(player.clone(), gokz_client),
+		global_api::get_player_records(player.clone(), mode, true, 0, 9999, gokz_client),
+		global_api::get_player_records(player.clone(), mode, false, 0, 9999, gokz_client),
+		kzgo_api::get_completions(mode, gokz_client),
+	);
+
+	let tp = tp.unwrap_or_default();
+	let pro = pro.unwrap_or_default();
+
+	if tp.is_empty() && pro.is_empty() {
+		return Err(eyre!("No records found."));
+	}
+
+	let stats = ProfileStats::compute(&tp, &pro, map_pool);
+	let rank = Rank::from_points(stats.total_points(), mode);
+
+	Ok(Profile {
+		player: fancy_player?,
+		mode,
+		stats,
+		completion_stats: completion_stats?,
+		rank,
+	})
+}
+
+/// Only fetches a player's record counts. This is a lot cheaper than [`execute`].
+#[tracing::instrument(skip(gokz_client))]
+pub async fn summary(
+	player: PlayerIdentifier,
+	gokz_client: &gokz_rs::Client,
+) -> Result<FancyPlayer> {
+	Ok(schnose_api::get_player(player, gokz_client).await?)
+}
+
+#[cfg(test)]
+mod tests {
+	use {
+		super::*,
+		crate::commands::tests::{global_api_record, global_map},
+		gokz_rs::Tier,
+	};
+
+	#[test]
+	fn compute_stats() {
+		let lionharder = global_map();
+		let mut beginnerblock = global_map();
+		beginnerblock.id = 291;
+		beginnerblock.tier = Tier::VeryEasy;
+
+		let mut tp_beginnerblock = global_api_record(2, 5, 0);
+		tp_beginnerblock.map_id = 291;
+		tp_beginnerblock.points = 900;
+
+		let mut not_in_pool = global_api_record(3, 0, 0);
+		not_in_pool.map_id = 1;
+
+		let tp = [
+			global_api_record(1, 1, 0),
+			tp_beginnerblock,
+		];
+		let pro = [
+			global_api_record(4, 0, 0),
+			// duplicate records only count once
+			global_api_record(5, 0, 0),
+			not_in_pool,
+		];
+
+		let stats = ProfileStats::compute(&tp, &pro, &[lionharder, beginnerblock]);
+		assert_eq!(stats.points, (1900, 1000));
+		assert_eq!(stats.total_points(), 2900);
+		assert_eq!(stats.wrs, (1, 1));
+		assert_eq!(stats.completions[0], (2, 1));
+		assert_eq!(stats.completions[Tier::VeryEasy as usize], (1, 0));
+		assert_eq!(stats.completions[Tier::Extreme as usize], (1, 1));
+
+		let completion_stats = CompletionStats {
+			mode: Mode::SimpleKZ,
+			tp: [4, 2, 0, 0, 0, 0, 2, 0],
+			pro: [4, 2, 0, 0, 0, 0, 2, 0],
+		};
+		let percentages = stats.completion_percentages(&completion_stats);
+		assert_eq!(percentages[0], (50.0, 25.0));
+		assert_eq!(percentages[Tier::Extreme as usize], (50.0, 50.0));
+		assert_eq!(percentages[Tier::Hard as usize], (0.0, 0.0));
+	}
+}
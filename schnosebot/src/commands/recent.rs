@@ -0,0 +1,38 @@
+use {
+	super::{fetch_place, Record},
+	color_eyre::{eyre::eyre, Result},
+	gokz_rs::{schnose_api, PlayerIdentifier},
+};
+
+/// A player's `limit` most recent runs, newest first. Each run will include its place on the
+/// leaderboard.
+#[tracing::instrument(skip(gokz_client))]
+pub async fn execute(
+	player: PlayerIdentifier,
+	limit: u32,
+	gokz_client: &gokz_rs::Client,
+) -> Result<Vec<Record>> {
+	let records = schnose_api::get_recent(player, limit, gokz_client).await?;
+
+	if records.is_empty() {
+		return Err(eyre!("No records found."));
+	}
+
+	Ok(futures::future::join_all(
+		records
+			.into_iter()
+			.map(|record| fetch_place(record.into(), gokz_client)),
+	)
+	.await)
+}
+
+/// The most recent run submitted by anyone.
+#[tracing::instrument(skip(gokz_client))]
+pub async fn latest(gokz_client: &gokz_rs::Client) -> Result<Record> {
+	schnose_api::get_records(1, gokz_client)
+		.await?
+		.into_iter()
+		.next()
+		.map(Record::from)
+		.ok_or_else(|| eyre!("No records found."))
+}
@@ -0,0 +1,29 @@
+use {
+	super::{Record, RecordLookup},
+	crate::global_maps::GlobalMap,
+	gokz_rs::{global_api, Mode},
+};
+
+/// World records on a map. `course` 0 is the main course, anything above that is a bonus.
+#[tracing::instrument(skip(gokz_client))]
+pub async fn execute(
+	map: GlobalMap,
+	mode: Mode,
+	course: u8,
+	gokz_client: &gokz_rs::Client,
+) -> RecordLookup {
+	let (tp, pro) = tokio::join!(
+		global_api::get_wr(map.id.into(), mode, true, course, gokz_client),
+		global_api::get_wr(map.id.into(), mode, false, course, gokz_client),
+	);
+
+	let into_wr = |record: global_api::Record| Record { place: Some(1), ..record.into() };
+
+	RecordLookup {
+		map,
+		mode,
+		course,
+		tp: tp.ok().map(into_wr),
+		pro: pro.ok().map(into_wr),
+	}
+}
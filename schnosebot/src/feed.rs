@@ -0,0 +1,330 @@
+//! Polling for new global records so bots can announce them.
+//!
+//! [`RecordFeed::poll`] fetches the latest records from a [`RecordSource`], skips everything it
+//! has already seen and looks up the leaderboard position of the rest. Deciding who gets told
+//! about which record is up to the bots; [`is_pb`] helps with that.
+
+use {
+	crate::{commands::Record, global_maps::GlobalMap},
+	async_trait::async_trait,
+	color_eyre::Result,
+	gokz_rs::{global_api, schnose_api, Tier},
+	std::collections::{HashSet, VecDeque},
+	tracing::warn,
+};
+
+/// How often to poll for new records by default.
+pub const DEFAULT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How many records to fetch per poll.
+const BATCH_SIZE: u32 = 100;
+
+/// How many record IDs to remember. This only has to be larger than [`BATCH_SIZE`].
+const SEEN_CAPACITY: usize = 1000;
+
+/// Where [`RecordFeed`] gets its records from. This is a trait so the feed can be tested without
+/// talking to the APIs.
+#[async_trait]
+pub trait RecordSource: Send + Sync {
+	/// The `limit` most recent records, newest first.
+	async fn latest_records(&self, limit: u32) -> Result<Vec<schnose_api::Record>>;
+
+	/// Leaderboard position of a record.
+	async fn place(&self, record_id: u32) -> Result<u32>;
+
+	/// ID of the player's current PB on the record's map, mode, runtype and course.
+	async fn pb_id(&self, record: &Record) -> Result<u32>;
+}
+
+/// [`RecordSource`] backed by the SchnoseAPI and GlobalAPI.
+#[derive(Debug, Clone)]
+pub struct ApiSource {
+	gokz_client: gokz_rs::Client,
+}
+
+impl ApiSource {
+	pub fn new(gokz_client: gokz_rs::Client) -> Self {
+		Self { gokz_client }
+	}
+}
+
+#[async_trait]
+impl RecordSource for ApiSource {
+	async fn latest_records(&self, limit: u32) -> Result<Vec<schnose_api::Record>> {
+		Ok(schnose_api::get_records(limit, &self.gokz_client).await?)
+	}
+
+	async fn place(&self, record_id: u32) -> Result<u32> {
+		Ok(global_api::get_place(record_id, &self.gokz_client).await?)
+	}
+
+	async fn pb_id(&self, record: &Record) -> Result<u32> {
+		Ok(global_api::get_pb(
+			record.steam_id.into(),
+			record.map_name.clone().into(),
+			record.mode,
+			record.is_tp(),
+			record.course,
+			&self.gokz_client,
+		)
+		.await?
+		.id)
+	}
+}
+
+/// A record that hasn't been seen before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedRecord {
+	/// [`Record::place`] is set if it could be fetched.
+	pub record: Record,
+	pub tier: Tier,
+}
+
+impl FeedRecord {
+	pub const fn is_wr(&self) -> bool {
+		matches!(self.record.place, Some(1))
+	}
+}
+
+/// Whether `record` is its player's new PB, i.e. not slower than a time they already had.
+/// Lookup failures count as "no", since announcing a slower run as a PB would be wrong.
+pub async fn is_pb(source: &impl RecordSource, record: &FeedRecord) -> bool {
+	if record.is_wr() {
+		return true;
+	}
+
+	match source.pb_id(&record.record).await {
+		Ok(pb_id) => pb_id == record.record.id,
+		Err(why) => {
+			warn!("Failed to fetch PB for record #{}: {why:?}", record.record.id);
+			false
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct RecordFeed {
+	seen: HashSet<u32>,
+	/// Insertion order of `seen`, so we can forget old IDs.
+	seen_order: VecDeque<u32>,
+	primed: bool,
+}
+
+impl RecordFeed {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns all records on maps in `map_pool` that haven't been returned before, oldest first.
+	///
+	/// The first successful poll only remembers the current records, so restarting the bot
+	/// doesn't announce everything again.
+	pub async fn poll(
+		&mut self,
+		source: &impl RecordSource,
+		map_pool: &[GlobalMap],
+	) -> Result<Vec<FeedRecord>> {
+		let records = source
+			.latest_records(BATCH_SIZE)
+			.await?;
+
+		let mut new_records = Vec::new();
+		for record in records.into_iter().rev() {
+			if !self.remember(record.id) || !self.primed {
+				continue;
+			}
+
+			let Some(map) = map_pool
+				.iter()
+				.find(|map| map.name == record.map_name)
+			else {
+				continue;
+			};
+
+			let mut record = Record::from(record);
+			record.place = match source.place(record.id).await {
+				Ok(place) => Some(place),
+				Err(why) => {
+					warn!("Failed to fetch place for record #{}: {why:?}", record.id);
+					None
+				}
+			};
+
+			new_records.push(FeedRecord { record, tier: map.tier });
+		}
+
+		self.primed = true;
+
+		Ok(new_records)
+	}
+
+	/// Returns `false` if `record_id` has been seen before.
+	fn remember(&mut self, record_id: u32) -> bool {
+		if !self.seen.insert(record_id) {
+			return false;
+		}
+
+		self.seen_order.push_back(record_id);
+		if self.seen_order.len() > SEEN_CAPACITY {
+			if let Some(oldest) = self.seen_order.pop_front() {
+				self.seen.remove(&oldest);
+			}
+		}
+
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use {
+		super::*,
+		crate::global_maps::MapSnapshot,
+		color_eyre::eyre::eyre,
+		std::{collections::HashMap, sync::Mutex},
+	};
+
+	const RECORDS_1: &str = include_str!("../fixtures/feed/records_1.json");
+	const RECORDS_2: &str = include_str!("../fixtures/feed/records_2.json");
+	const RECORDS_3: &str = include_str!("../fixtures/feed/records_3.json");
+	const PLACES: &str = include_str!("../fixtures/feed/places.json");
+	const PBS: &str = include_str!("../fixtures/feed/pbs.json");
+	const MAP_SNAPSHOT: &str = include_str!("../fixtures/map_snapshot.json");
+
+	/// Replays recorded API responses, one per poll.
+	struct FixtureSource {
+		pages: Mutex<VecDeque<Vec<schnose_api::Record>>>,
+		places: HashMap<u32, u32>,
+		/// `"<steam_id> <map> <course> <mode> <runtype>"` -> PB ID
+		pbs: HashMap<String, u32>,
+	}
+
+	impl FixtureSource {
+		fn new(pages: &[&str]) -> Self {
+			Self {
+				pages: Mutex::new(
+					pages
+						.iter()
+						.map(|page| serde_json::from_str(page).unwrap())
+						.collect(),
+				),
+				places: serde_json::from_str(PLACES).unwrap(),
+				pbs: serde_json::from_str(PBS).unwrap(),
+			}
+		}
+	}
+
+	#[async_trait]
+	impl RecordSource for FixtureSource {
+		async fn latest_records(&self, _limit: u32) -> Result<Vec<schnose_api::Record>> {
+			self.pages
+				.lock()
+				.unwrap()
+				.pop_front()
+				.ok_or_else(|| eyre!("no more fixtures"))
+		}
+
+		async fn place(&self, record_id: u32) -> Result<u32> {
+			self.places
+				.get(&record_id)
+				.copied()
+				.ok_or_else(|| eyre!("no place for #{record_id}"))
+		}
+
+		async fn pb_id(&self, record: &Record) -> Result<u32> {
+			let key = format!(
+				"{} {} {} {} {}",
+				record.steam_id,
+				record.map_name,
+				record.course,
+				record.mode.short(),
+				record.runtype()
+			);
+
+			self.pbs
+				.get(&key)
+				.copied()
+				.ok_or_else(|| eyre!("no PB for `{key}`"))
+		}
+	}
+
+	#[tokio::test]
+	async fn poll_fixtures() {
+		let maps = MapSnapshot::from_json(MAP_SNAPSHOT)
+			.unwrap()
+			.maps;
+		let source = FixtureSource::new(&[RECORDS_1, RECORDS_2, RECORDS_2]);
+		let mut feed = RecordFeed::new();
+
+		// first poll only primes the feed
+		assert!(feed
+			.poll(&source, &maps)
+			.await
+			.unwrap()
+			.is_empty());
+
+		let records = feed.poll(&source, &maps).await.unwrap();
+		let summary = records
+			.iter()
+			.map(|record| (record.record.id, record.record.place, record.is_wr(), record.tier))
+			.collect::<Vec<_>>();
+
+		// oldest first, the non-global map is skipped
+		assert_eq!(
+			summary,
+			[
+				(19000004, Some(1), true, Tier::Extreme),
+				(19000005, Some(12), false, Tier::Medium)
+			]
+		);
+		assert_eq!(records[1].record.course, 1);
+
+		// nothing new
+		assert!(feed
+			.poll(&source, &maps)
+			.await
+			.unwrap()
+			.is_empty());
+
+		// errors don't change anything
+		assert!(feed.poll(&source, &maps).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn only_new_pbs() {
+		let maps = MapSnapshot::from_json(MAP_SNAPSHOT)
+			.unwrap()
+			.maps;
+		let source = FixtureSource::new(&[RECORDS_1, RECORDS_2, RECORDS_3]);
+		let mut feed = RecordFeed::new();
+
+		feed.poll(&source, &maps).await.unwrap();
+
+		let mut pbs = Vec::new();
+		for record in feed.poll(&source, &maps).await.unwrap() {
+			pbs.push((record.record.id, is_pb(&source, &record).await));
+		}
+
+		// WR and a new PB
+		assert_eq!(pbs, [(19000004, true), (19000005, true)]);
+
+		// a slower run by the same player on the same course
+		let records = feed.poll(&source, &maps).await.unwrap();
+		assert_eq!(records.len(), 1);
+		assert_eq!(records[0].record.id, 19000007);
+		assert!(!records[0].is_wr());
+		assert!(!is_pb(&source, &records[0]).await);
+	}
+
+	#[test]
+	fn forget_old_ids() {
+		let mut feed = RecordFeed::new();
+		for id in 0..=SEEN_CAPACITY as u32 {
+			assert!(feed.remember(id));
+		}
+
+		assert!(!feed.remember(SEEN_CAPACITY as u32));
+		assert!(feed.remember(0));
+		assert_eq!(feed.seen.len(), SEEN_CAPACITY);
+	}
+}
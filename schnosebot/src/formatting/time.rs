@@ -0,0 +1,336 @@
+//! Parsing and formatting of run times.
+//!
+//! All times are in seconds, which is what the APIs give us.
+
+use color_eyre::{
+	eyre::{bail as yeet, eyre},
+	Result,
+};
+
+/// The tickrate used by global servers.
+pub const TICKRATE: f64 = 128.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeStyle {
+	/// `01:23.456`, `01:02:03.456`
+	#[default]
+	Compact,
+
+	/// `1m 23.456s`, `1h 2m 3.456s`
+	Verbose,
+
+	/// `10682 ticks` (at [`TICKRATE`])
+	Ticks,
+}
+
+/// How to format a time. The default is [`TimeStyle::Compact`] with millisecond precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeFormat {
+	pub style: TimeStyle,
+	/// Number of decimal places, at most 3. Ignored for [`TimeStyle::Ticks`].
+	pub precision: u8,
+}
+
+impl Default for TimeFormat {
+	fn default() -> Self {
+		Self { style: TimeStyle::Compact, precision: 3 }
+	}
+}
+
+impl TimeFormat {
+	pub fn new(style: TimeStyle) -> Self {
+		Self { style, ..Default::default() }
+	}
+
+	pub fn precision(mut self, precision: u8) -> Self {
+		self.precision = precision.min(3);
+		self
+	}
+
+	/// Formats a run time, e.g. `01:23.456`.
+	pub fn format(&self, seconds: f64) -> String {
+		match self.style {
+			TimeStyle::Compact => {
+				let parts = self.split(seconds);
+				let fraction = parts.fraction(self.precision);
+				if parts.hours > 0 {
+					format!(
+						"{:02}:{:02}:{:02}{fraction}",
+						parts.hours, parts.minutes, parts.seconds
+					)
+				} else {
+					format!("{:02}:{:02}{fraction}", parts.minutes, parts.seconds)
+				}
+			}
+			TimeStyle::Verbose => self.format_verbose(seconds),
+			TimeStyle::Ticks => format_ticks(seconds),
+		}
+	}
+
+	/// Formats the difference between two times with an explicit sign, e.g. `+0.532` or
+	/// `-1:02.100`. Unlike [`Self::format`] this doesn't pad minutes, so small deltas stay small.
+	pub fn format_delta(&self, delta: f64) -> String {
+		let sign = if delta < 0.0 { '-' } else { '+' };
+		let delta = delta.abs();
+
+		let formatted = match self.style {
+			TimeStyle::Compact => {
+				let parts = self.split(delta);
+				let fraction = parts.fraction(self.precision);
+				if parts.hours > 0 {
+					format!("{}:{:02}:{:02}{fraction}", parts.hours, parts.minutes, parts.seconds)
+				} else if parts.minutes > 0 {
+					format!("{}:{:02}{fraction}", parts.minutes, parts.seconds)
+				} else {
+					format!("{}{fraction}", parts.seconds)
+				}
+			}
+			TimeStyle::Verbose => self.format_verbose(delta),
+			TimeStyle::Ticks => format_ticks(delta),
+		};
+
+		format!("{sign}{formatted}")
+	}
+
+	fn format_verbose(&self, seconds: f64) -> String {
+		let parts = self.split(seconds);
+		let fraction = parts.fraction(self.precision);
+		let seconds = format!("{}{fraction}s", parts.seconds);
+
+		match (parts.hours, parts.minutes) {
+			(0, 0) => seconds,
+			(0, minutes) => format!("{minutes}m {seconds}"),
+			(hours, minutes) => format!("{hours}h {minutes}m {seconds}"),
+		}
+	}
+
+	/// Rounds `seconds` to the configured precision before splitting it up, so `59.9996` becomes
+	/// `01:00.000` instead of `00:60.000`.
+	fn split(&self, seconds: f64) -> TimeParts {
+		let precision = self.precision.min(3) as u32;
+		let scale = 10u64.pow(precision);
+		let units = (seconds.max(0.0) * scale as f64).round() as u64;
+		let whole = units / scale;
+
+		TimeParts {
+			hours: whole / 3600,
+			minutes: (whole % 3600) / 60,
+			seconds: whole % 60,
+			fraction: units % scale,
+		}
+	}
+}
+
+struct TimeParts {
+	hours: u64,
+	minutes: u64,
+	seconds: u64,
+	fraction: u64,
+}
+
+impl TimeParts {
+	fn fraction(&self, precision: u8) -> String {
+		match precision.min(3) {
+			0 => String::new(),
+			precision => format!(".{:0width$}", self.fraction, width = precision as usize),
+		}
+	}
+}
+
+fn format_ticks(seconds: f64) -> String {
+	let ticks = to_ticks(seconds);
+	match ticks {
+		1 => String::from("1 tick"),
+		ticks => format!("{ticks} ticks"),
+	}
+}
+
+/// Converts seconds to ticks at [`TICKRATE`].
+pub fn to_ticks(seconds: f64) -> u64 {
+	(seconds.max(0.0) * TICKRATE).round() as u64
+}
+
+/// Parses a user-entered time into seconds. Supported formats:
+/// - `83.4`
+/// - `1:23.456`, `1:02:03.5`
+/// - `1h 2m`, `2m3.5s`, `500ms`
+pub fn parse_time(input: &str) -> Result<f64> {
+	let input = input.trim().to_lowercase();
+
+	if input.is_empty() {
+		yeet!("Please enter a time.");
+	}
+
+	let seconds = if input.contains(':') {
+		parse_colon_separated(&input)
+	} else if input.ends_with(|c: char| c.is_ascii_alphabetic()) {
+		parse_units(&input)
+	} else {
+		parse_number(&input)
+	}
+	.map_err(|why| eyre!("`{input}` is not a valid time. {why}"))?;
+
+	if !seconds.is_finite() {
+		yeet!("`{input}` is not a valid time.");
+	}
+
+	Ok(seconds)
+}
+
+fn parse_number(input: &str) -> Result<f64> {
+	if input.is_empty()
+		|| !input
+			.chars()
+			.all(|c| c.is_ascii_digit() || c == '.')
+	{
+		yeet!("Expected a number, got `{input}`.");
+	}
+
+	input
+		.parse::<f64>()
+		.map_err(|_| eyre!("Expected a number, got `{input}`."))
+}
+
+/// `h:m:s` or `m:s`, only the last part may have decimals.
+fn parse_colon_separated(input: &str) -> Result<f64> {
+	let parts = input.split(':').collect::<Vec<_>>();
+
+	if parts.len() > 3 {
+		yeet!("Expected at most hours, minutes and seconds.");
+	}
+
+	let (last, rest) = parts
+		.split_last()
+		.expect("`split` always yields at least one item");
+
+	let mut seconds = 0.0;
+	for (i, part) in rest.iter().enumerate() {
+		if part.contains('.') {
+			yeet!("Only seconds can have decimals.");
+		}
+
+		let value = parse_number(part)?;
+		if i > 0 && value >= 60.0 {
+			yeet!("`{part}` is too large.");
+		}
+
+		seconds = seconds * 60.0 + value;
+	}
+
+	let last = parse_number(last)?;
+	if last >= 60.0 {
+		yeet!("`{last}` is too large.");
+	}
+
+	Ok(seconds * 60.0 + last)
+}
+
+/// Any combination of `h`, `m`, `s` and `ms`, largest unit first.
+fn parse_units(input: &str) -> Result<f64> {
+	let mut seconds = 0.0;
+	let mut last_unit = f64::INFINITY;
+	let mut rest = input;
+
+	while !rest.trim_start().is_empty() {
+		rest = rest.trim_start();
+
+		let number_len = rest
+			.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+			.unwrap_or(rest.len());
+		let (number, tail) = rest.split_at(number_len);
+		let number = parse_number(number)?;
+
+		let tail = tail.trim_start();
+		let unit_len = tail
+			.find(|c: char| !c.is_ascii_alphabetic())
+			.unwrap_or(tail.len());
+		let (unit, tail) = tail.split_at(unit_len);
+
+		let unit = match unit {
+			"h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+			"m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+			"s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+			"ms" => 0.001,
+			"" => yeet!("Missing unit after `{number}`."),
+			unit => yeet!("Unknown unit `{unit}`."),
+		};
+
+		if unit >= last_unit {
+			yeet!("Units have to go from largest to smallest.");
+		}
+
+		last_unit = unit;
+		seconds += number * unit;
+		rest = tail;
+	}
+
+	Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn format_compact() {
+		let fmt = TimeFormat::default();
+		assert_eq!(fmt.format(83.456), "01:23.456");
+		assert_eq!(fmt.format(59.9996), "01:00.000");
+		assert_eq!(fmt.format(3723.5), "01:02:03.500");
+		// no more wrapping at 24 hours
+		assert_eq!(fmt.format(90000.0), "25:00:00.000");
+		assert_eq!(fmt.precision(1).format(83.456), "01:23.5");
+		assert_eq!(fmt.precision(0).format(83.456), "01:23");
+	}
+
+	#[test]
+	fn format_verbose_and_ticks() {
+		let fmt = TimeFormat::new(TimeStyle::Verbose);
+		assert_eq!(fmt.format(23.456), "23.456s");
+		assert_eq!(fmt.format(83.456), "1m 23.456s");
+		assert_eq!(fmt.format(3723.0), "1h 2m 3.000s");
+
+		let fmt = TimeFormat::new(TimeStyle::Ticks);
+		assert_eq!(fmt.format(83.456), "10682 ticks");
+		assert_eq!(fmt.format(1.0 / TICKRATE), "1 tick");
+	}
+
+	#[test]
+	fn format_deltas() {
+		let fmt = TimeFormat::default();
+		assert_eq!(fmt.format_delta(0.532), "+0.532");
+		assert_eq!(fmt.format_delta(-62.1), "-1:02.100");
+		assert_eq!(fmt.format_delta(0.0), "+0.000");
+		assert_eq!(fmt.format_delta(-3600.25), "-1:00:00.250");
+		assert_eq!(TimeFormat::new(TimeStyle::Verbose).format_delta(-62.1), "-1m 2.100s");
+		assert_eq!(TimeFormat::new(TimeStyle::Ticks).format_delta(0.5), "+64 ticks");
+	}
+
+	#[test]
+	fn parse_valid_times() {
+		assert_eq!(parse_time("83.4").unwrap(), 83.4);
+		assert_eq!(parse_time("1:23.456").unwrap(), 83.456);
+		assert_eq!(parse_time("1:02:03.5").unwrap(), 3723.5);
+		assert_eq!(parse_time("1h 2m").unwrap(), 3720.0);
+		assert_eq!(parse_time("2m3.5s").unwrap(), 123.5);
+		assert_eq!(parse_time(" 1 min 500ms ").unwrap(), 60.5);
+		assert_eq!(parse_time("25:00:00").unwrap(), 90000.0);
+	}
+
+	#[test]
+	fn parse_invalid_times() {
+		for input in [
+			"", "abc", "1:60", "1:2:3:4", "1.5:00", "1m 2h", "1m 1m", "5x", "5 m s", "-3", "1:",
+		] {
+			assert!(parse_time(input).is_err(), "`{input}` should not parse");
+		}
+	}
+
+	#[test]
+	fn roundtrip() {
+		let fmt = TimeFormat::default();
+		for time in [0.0, 0.008, 83.456, 3723.5, 90000.125] {
+			assert_eq!(parse_time(&fmt.format(time)).unwrap(), time);
+		}
+	}
+}
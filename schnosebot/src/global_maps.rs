@@ -1,16 +1,28 @@
 use {
-	chrono::NaiveDateTime,
-	color_eyre::Result,
-	fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+	crate::map_search::MapSearch,
+	chrono::{DateTime, NaiveDateTime, Utc},
+	color_eyre::{eyre::bail as yeet, Result},
 	gokz_rs::{
 		global_api,
 		schnose_api::{self, maps::Course},
 		MapIdentifier, Mode, SteamID, Tier,
 	},
+	serde::{Deserialize, Serialize},
+	std::{
+		collections::HashMap,
+		path::{Path, PathBuf},
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc, RwLock,
+		},
+		time::Duration,
+	},
+	tokio::{sync::broadcast, task::JoinHandle},
+	tracing::{error, info, warn},
 };
 
 /// Custom version of [`global_api::Map`] with some additional fields for convenience.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GlobalMap {
 	pub id: u16,
 	pub name: String,
@@ -29,7 +41,7 @@ pub struct GlobalMap {
 	pub thumbnail: String,
 }
 
-/// Gets called once at the start to fetch and process all maps.
+/// Fetches and processes all maps. Used for the initial fetch and every refresh of [`MapCache`].
 #[tracing::instrument]
 pub async fn init(gokz_client: &gokz_rs::Client, global_only: bool) -> Result<Vec<GlobalMap>> {
 	let mut maps = Vec::new();
@@ -85,34 +97,387 @@ pub async fn init(gokz_client: &gokz_rs::Client, global_only: bool) -> Result<Ve
 	Ok(maps)
 }
 
-const MIN_SCORE: i64 = 1;
+/// Default interval for [`MapCache::spawn_refresh_task`].
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 30);
+
+/// How long to wait before retrying a failed refresh while the cache is degraded. This doubles
+/// after every failure.
+const RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Refreshes that return less than this fraction of the current map pool are rejected.
+const MIN_POOL_RATIO: f64 = 0.5;
+
+/// Makes sure `new` is a plausible replacement for `old`. The APIs occasionally respond with
+/// empty or partial lists, which would otherwise wipe the map pool and its snapshot.
+fn check_pool(old: &[GlobalMap], new: &[GlobalMap]) -> Result<()> {
+	if new.is_empty() {
+		yeet!("Got an empty map pool.");
+	}
+
+	if (new.len() as f64) < old.len() as f64 * MIN_POOL_RATIO {
+		yeet!("Map pool shrank from {} to {} maps.", old.len(), new.len());
+	}
+
+	Ok(())
+}
+
+/// The difference between two versions of the map pool.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapPoolDiff {
+	/// Maps which are in the new pool but weren't in the old one.
+	pub added: Vec<GlobalMap>,
+
+	/// Maps which were in the old pool but aren't in the new one anymore.
+	pub removed: Vec<GlobalMap>,
+
+	/// Maps which exist in both pools but changed in some way (tier, filters, courses, ...).
+	/// The first element is the old version, the second element is the new one.
+	pub changed: Vec<(GlobalMap, GlobalMap)>,
+}
+
+impl MapPoolDiff {
+	/// Compares two map pools by map ID.
+	pub fn new(old: &[GlobalMap], new: &[GlobalMap]) -> Self {
+		let old_maps = old
+			.iter()
+			.map(|map| (map.id, map))
+			.collect::<HashMap<_, _>>();
+		let new_maps = new
+			.iter()
+			.map(|map| (map.id, map))
+			.collect::<HashMap<_, _>>();
+
+		let mut diff = Self::default();
+
+		for map in new {
+			match old_maps.get(&map.id) {
+				None => diff.added.push(map.to_owned()),
+				Some(&old_map) if old_map != map => {
+					diff.changed
+						.push((old_map.to_owned(), map.to_owned()));
+				}
+				Some(_) => {}
+			}
+		}
+
+		diff.removed = old
+			.iter()
+			.filter(|map| !new_maps.contains_key(&map.id))
+			.cloned()
+			.collect();
+
+		diff
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+	}
+}
+
+impl std::fmt::Display for MapPoolDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{} added, {} removed, {} changed",
+			self.added.len(),
+			self.removed.len(),
+			self.changed.len()
+		)
+	}
+}
+
+/// Current version of the [`MapSnapshot`] file format. Bump this whenever [`GlobalMap`] changes
+/// in a way that makes old snapshots unreadable.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// On-disk copy of the map pool. This is used as a fallback if the APIs are down when starting up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapSnapshot {
+	/// See [`SNAPSHOT_VERSION`].
+	pub version: u32,
+
+	/// When the maps in this snapshot were fetched.
+	pub created_on: DateTime<Utc>,
+
+	/// Whether this snapshot only contains global maps.
+	pub global_only: bool,
+
+	pub maps: Vec<GlobalMap>,
+}
+
+impl MapSnapshot {
+	pub fn new(maps: Vec<GlobalMap>, global_only: bool) -> Self {
+		Self {
+			version: SNAPSHOT_VERSION,
+			created_on: Utc::now(),
+			global_only,
+			maps,
+		}
+	}
+
+	/// Parses a snapshot from JSON, rejecting snapshots with a different [`SNAPSHOT_VERSION`].
+	pub fn from_json(json: &str) -> Result<Self> {
+		#[derive(Deserialize)]
+		struct Version {
+			version: u32,
+		}
+
+		let Version { version } = serde_json::from_str(json)?;
+		if version != SNAPSHOT_VERSION {
+			yeet!("Unsupported snapshot version `{version}` (expected `{SNAPSHOT_VERSION}`).");
+		}
+
+		Ok(serde_json::from_str(json)?)
+	}
+
+	#[tracing::instrument]
+	pub fn load(path: &Path) -> Result<Self> {
+		let json = std::fs::read_to_string(path)?;
+		Self::from_json(&json)
+	}
+
+	/// Writes the snapshot to `path`. The file is written to a temporary location first and then
+	/// moved, so a crash while writing can't corrupt an existing snapshot.
+	#[tracing::instrument(skip(self))]
+	pub fn save(&self, path: &Path) -> Result<()> {
+		let json = serde_json::to_string(self)?;
+		let tmp_path = path.with_extension("tmp");
+		std::fs::write(&tmp_path, json)?;
+		std::fs::rename(tmp_path, path)?;
+		Ok(())
+	}
+}
+
+/// Shared, reloadable cache of all maps. Cloning this is cheap and every clone will see the same
+/// map pool. When the cache gets refreshed the entire pool is swapped out at once, so readers
+/// never see a half-updated state.
+#[derive(Debug, Clone)]
+pub struct MapCache {
+	maps: Arc<RwLock<Arc<Vec<GlobalMap>>>>,
+	global_only: bool,
+	updates: broadcast::Sender<Arc<MapPoolDiff>>,
+	snapshot_path: Option<PathBuf>,
+	degraded: Arc<AtomicBool>,
+}
+
+impl MapCache {
+	/// Fetches the map pool once and wraps it in a new cache.
+	///
+	/// If `snapshot_path` is set, every successful fetch will be written to that file. If the
+	/// initial fetch fails, the cache will be filled from that file instead and start in degraded
+	/// mode (see [`Self::is_degraded`]) until the next successful refresh.
+	#[tracing::instrument]
+	pub async fn new(
+		gokz_client: &gokz_rs::Client,
+		global_only: bool,
+		snapshot_path: Option<PathBuf>,
+	) -> Result<Self> {
+		let maps = init(gokz_client, global_only)
+			.await
+			.and_then(|maps| check_pool(&[], &maps).map(|()| maps));
+
+		let (maps, degraded) = match maps {
+			Ok(maps) => (maps, false),
+			Err(why) => {
+				let Some(path) = &snapshot_path else {
+					return Err(why);
+				};
+
+				error!("Failed to fetch maps: {why:?}");
+				let snapshot = MapSnapshot::load(path)?;
+
+				if snapshot.global_only != global_only {
+					warn!(
+						"Snapshot at `{}` has `global_only = {}`.",
+						path.display(),
+						snapshot.global_only
+					);
+				}
+
+				warn!(
+					"Loaded {} maps from snapshot at `{}` (created on {}).",
+					snapshot.maps.len(),
+					path.display(),
+					snapshot.created_on
+				);
+
+				(snapshot.maps, true)
+			}
+		};
+
+		let mut cache = Self::from_maps(maps, global_only);
+		cache.snapshot_path = snapshot_path;
+		cache
+			.degraded
+			.store(degraded, Ordering::Relaxed);
+
+		if !degraded {
+			cache.save_snapshot();
+		}
+
+		Ok(cache)
+	}
+
+	/// Creates a new cache from an already existing map pool.
+	pub fn from_maps(maps: Vec<GlobalMap>, global_only: bool) -> Self {
+		let (updates, _) = broadcast::channel(16);
+		Self {
+			maps: Arc::new(RwLock::new(Arc::new(maps))),
+			global_only,
+			updates,
+			snapshot_path: None,
+			degraded: Arc::new(AtomicBool::new(false)),
+		}
+	}
+
+	/// Write every successful refresh to `snapshot_path`. [`Self::new`] already does this, this is
+	/// for caches created with [`Self::from_maps`].
+	pub fn with_snapshot_path(mut self, snapshot_path: PathBuf) -> Self {
+		self.snapshot_path = Some(snapshot_path);
+		self
+	}
+
+	/// Whether the current map pool might be out of date, because it was loaded from a snapshot
+	/// instead of the APIs or the last refresh returned an implausible map pool.
+	pub fn is_degraded(&self) -> bool {
+		self.degraded.load(Ordering::Relaxed)
+	}
+
+	/// Writes the current map pool to the snapshot file, if there is one.
+	fn save_snapshot(&self) {
+		let Some(path) = &self.snapshot_path else {
+			return;
+		};
+
+		let snapshot = MapSnapshot::new(self.maps().to_vec(), self.global_only);
+		if let Err(why) = snapshot.save(path) {
+			error!("Failed to write map snapshot to `{}`: {why:?}", path.display());
+		}
+	}
+
+	/// The current map pool.
+	pub fn maps(&self) -> Arc<Vec<GlobalMap>> {
+		Arc::clone(
+			&self
+				.maps
+				.read()
+				.expect("Map cache lock poisoned."),
+		)
+	}
+
+	/// The names of all maps in the current map pool.
+	pub fn map_names(&self) -> Vec<String> {
+		self.maps()
+			.iter()
+			.map(|map| map.name.clone())
+			.collect()
+	}
+
+	/// Get notified whenever the map pool changes. Refreshes that don't change anything will not
+	/// be sent.
+	pub fn subscribe(&self) -> broadcast::Receiver<Arc<MapPoolDiff>> {
+		self.updates.subscribe()
+	}
+
+	/// Swaps the current map pool with `maps` and notifies all subscribers if anything changed.
+	pub fn replace(&self, maps: Vec<GlobalMap>) -> MapPoolDiff {
+		let mut current = self
+			.maps
+			.write()
+			.expect("Map cache lock poisoned.");
+
+		let diff = MapPoolDiff::new(&current, &maps);
+		*current = Arc::new(maps);
+		drop(current);
+
+		if !diff.is_empty() {
+			// This only fails if there are no subscribers, which is fine.
+			_ = self
+				.updates
+				.send(Arc::new(diff.clone()));
+		}
+
+		diff
+	}
+
+	/// Re-fetches all maps and swaps them into the cache.
+	#[tracing::instrument(skip(self))]
+	pub async fn refresh(&self, gokz_client: &gokz_rs::Client) -> Result<MapPoolDiff> {
+		let maps = init(gokz_client, self.global_only).await?;
+		self.apply_refresh(maps)
+	}
+
+	/// Swaps in a freshly fetched map pool. If it's empty or shrank too much (see
+	/// [`MIN_POOL_RATIO`]), the old maps and snapshot are kept and the cache is marked as
+	/// degraded instead.
+	fn apply_refresh(&self, maps: Vec<GlobalMap>) -> Result<MapPoolDiff> {
+		if let Err(why) = check_pool(&self.maps(), &maps) {
+			self.degraded
+				.store(true, Ordering::Relaxed);
+			return Err(why);
+		}
+
+		let diff = self.replace(maps);
+
+		self.degraded
+			.store(false, Ordering::Relaxed);
+		self.save_snapshot();
+
+		Ok(diff)
+	}
+
+	/// Spawns a background task that calls [`Self::refresh`] every `interval`. Failed refreshes
+	/// are logged and the old map pool is kept. If the cache is [degraded](Self::is_degraded),
+	/// the task first retries right away, backing off up to `interval`, until a refresh succeeds.
+	pub fn spawn_refresh_task(
+		&self,
+		gokz_client: gokz_rs::Client,
+		interval: Duration,
+	) -> JoinHandle<()> {
+		let cache = self.clone();
+		tokio::spawn(async move {
+			let mut backoff = RETRY_BACKOFF;
+			while cache.is_degraded() {
+				match cache.refresh(&gokz_client).await {
+					Ok(diff) => info!("Recovered from degraded map cache. ({diff})"),
+					Err(why) => {
+						warn!("Failed to refresh degraded map cache: {why:?}");
+						tokio::time::sleep(backoff).await;
+						backoff = (backoff * 2).min(interval);
+					}
+				}
+			}
+
+			let mut interval = tokio::time::interval(interval);
+			// The first tick completes immediately and we just got a fresh map pool.
+			interval.tick().await;
+
+			loop {
+				interval.tick().await;
+				match cache.refresh(&gokz_client).await {
+					Ok(diff) => info!("Refreshed map cache. ({diff})"),
+					Err(why) => error!("Failed to refresh map cache: {why:?}"),
+				}
+			}
+		})
+	}
+}
 
+/// Returns the best match for `map_identifier`, even if there are several equally good ones.
+/// Use [`MapSearch`] if you care about ambiguity.
 pub fn fuzzy_find_map(
 	map_identifier: impl Into<MapIdentifier>,
 	map_pool: &[GlobalMap],
 ) -> Option<GlobalMap> {
-	let map_identifier = map_identifier.into();
-	match map_identifier {
-		MapIdentifier::ID(map_id) => {
-			map_pool
-				.iter()
-				.find_map(|map| if map.id == map_id { Some(map.to_owned()) } else { None })
-		}
-		MapIdentifier::Name(map_name) => {
-			let fzf = SkimMatcherV2::default();
-			let map_name = map_name.to_lowercase();
-			map_pool
-				.iter()
-				.filter_map(|map| {
-					let score = fzf.fuzzy_match(&map.name, &map_name)?;
-					if score >= MIN_SCORE || map_name.is_empty() {
-						return Some((score, map.to_owned()));
-					}
-					None
-				})
-				.max_by(|(a_score, _), (b_score, _)| a_score.cmp(b_score))
-				.map(|(_, map)| map)
-		}
+	match map_identifier.into() {
+		MapIdentifier::ID(map_id) => map_pool
+			.iter()
+			.find(|map| map.id == map_id)
+			.cloned(),
+		MapIdentifier::Name(map_name) => MapSearch::default()
+			.search(&map_name, map_pool)
+			.first()
+			.map(|candidate| candidate.map.to_owned()),
 	}
 }
 
@@ -122,3 +487,132 @@ pub fn fuzzy_find_map_name(
 ) -> Option<String> {
 	fuzzy_find_map(map_identifier, map_pool).map(|map| map.name)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+
+	const SNAPSHOT_FIXTURE: &str = include_str!("../fixtures/map_snapshot.json");
+
+	pub(crate) fn map(id: u16, name: &str, tier: Tier) -> GlobalMap {
+		GlobalMap {
+			id,
+			name: name.to_owned(),
+			tier,
+			courses: Vec::new(),
+			kzt: true,
+			skz: true,
+			vnl: false,
+			mapper_name: String::from("AlphaKeks"),
+			mapper_steam_id: None,
+			filesize: 0,
+			validated: true,
+			created_on: NaiveDateTime::default(),
+			updated_on: NaiveDateTime::default(),
+			url: format!("https://kzgo.eu/maps/{name}"),
+			thumbnail: String::new(),
+		}
+	}
+
+	#[test]
+	fn diff_map_pools() {
+		let old = vec![
+			map(1, "kz_lionharder", Tier::VeryHard),
+			map(2, "kz_beginnerblock_go", Tier::VeryEasy),
+			map(3, "kz_checkmate", Tier::Medium),
+		];
+		let new = vec![
+			map(1, "kz_lionharder", Tier::Extreme),
+			map(3, "kz_checkmate", Tier::Medium),
+			map(4, "kz_spacemario_h", Tier::Extreme),
+		];
+
+		let diff = MapPoolDiff::new(&old, &new);
+		assert_eq!(diff.added, vec![map(4, "kz_spacemario_h", Tier::Extreme)]);
+		assert_eq!(
+			diff.removed,
+			vec![map(
+				2,
+				"kz_beginnerblock_go",
+				Tier::VeryEasy
+			)]
+		);
+		assert_eq!(diff.changed.len(), 1);
+		assert_eq!(diff.changed[0].0.tier, Tier::VeryHard);
+		assert_eq!(diff.changed[0].1.tier, Tier::Extreme);
+
+		assert!(MapPoolDiff::new(&new, &new).is_empty());
+	}
+
+	#[test]
+	fn parse_snapshot_fixture() -> Result<()> {
+		let snapshot = MapSnapshot::from_json(SNAPSHOT_FIXTURE)?;
+		assert_eq!(snapshot.maps.len(), 3);
+
+		let lionharder = &snapshot.maps[0];
+		assert_eq!(lionharder.name, "kz_lionharder");
+		assert_eq!(lionharder.tier, Tier::Extreme);
+		assert_eq!(lionharder.courses.len(), 2);
+		assert!(lionharder.skz && !lionharder.vnl);
+		assert_eq!(lionharder.updated_on.to_string(), "2021-02-09 21:28:08");
+
+		let json = serde_json::to_string(&snapshot)?;
+		assert_eq!(MapSnapshot::from_json(&json)?, snapshot);
+
+		Ok(())
+	}
+
+	#[test]
+	fn reject_unknown_snapshot_version() {
+		let json = SNAPSHOT_FIXTURE.replacen(r#""version": 1"#, r#""version": 0"#, 1);
+		assert!(MapSnapshot::from_json(&json).is_err());
+	}
+
+	#[tokio::test]
+	async fn replace_notifies_subscribers() {
+		let cache = MapCache::from_maps(vec![map(1, "kz_lionharder", Tier::VeryHard)], true);
+		let mut updates = cache.subscribe();
+
+		let diff = cache.replace(vec![map(1, "kz_lionharder", Tier::VeryHard)]);
+		assert!(diff.is_empty());
+
+		let diff = cache.replace(vec![
+			map(1, "kz_lionharder", Tier::VeryHard),
+			map(2, "kz_checkmate", Tier::Medium),
+		]);
+		assert_eq!(diff.added.len(), 1);
+		assert_eq!(cache.map_names(), vec!["kz_lionharder", "kz_checkmate"]);
+
+		let received = updates.try_recv().unwrap();
+		assert_eq!(*received, diff);
+		assert!(updates.try_recv().is_err());
+	}
+
+	#[test]
+	fn reject_implausible_refreshes() {
+		let path = std::env::temp_dir().join(format!("map_snapshot_{}.json", std::process::id()));
+		let maps = vec![
+			map(1, "kz_lionharder", Tier::Extreme),
+			map(2, "kz_checkmate", Tier::Medium),
+			map(3, "kz_spacemario_h", Tier::Extreme),
+		];
+		let cache = MapCache::from_maps(maps.clone(), true).with_snapshot_path(path.clone());
+
+		assert!(cache.apply_refresh(Vec::new()).is_err());
+		assert!(cache
+			.apply_refresh(vec![map(1, "kz_lionharder", Tier::Extreme)])
+			.is_err());
+		assert!(cache.is_degraded());
+		assert_eq!(*cache.maps(), maps);
+		assert!(!path.exists());
+
+		let diff = cache
+			.apply_refresh(maps[..2].to_vec())
+			.unwrap();
+		assert_eq!(diff.removed.len(), 1);
+		assert!(!cache.is_degraded());
+		assert_eq!(MapSnapshot::load(&path).unwrap().maps, maps[..2]);
+
+		std::fs::remove_file(path).unwrap();
+	}
+}
@@ -1,2 +1,5 @@
+pub mod commands;
+pub mod feed;
 pub mod formatting;
 pub mod global_maps;
+pub mod map_search;
@@ -0,0 +1,293 @@
+//! Ranked map search over a map pool.
+//!
+//! Unlike [`crate::global_maps::fuzzy_find_map`], which always picks _something_, [`MapSearch`]
+//! returns scored candidates and tells you when a query is ambiguous. Matches are ranked like
+//! this:
+//!
+//! 1. user-defined aliases (e.g. `lh` -> `kz_lionharder`)
+//! 2. exact names (with or without a prefix like `kz_`)
+//! 3. names starting with the query
+//! 4. fuzzy matches
+
+use {
+	crate::global_maps::GlobalMap,
+	fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+	gokz_rs::MapIdentifier,
+	std::collections::HashMap,
+};
+
+/// Prefixes that get ignored when comparing map names, so `lionharder` finds `kz_lionharder`.
+pub const MAP_PREFIXES: [&str; 4] = ["kz_", "bkz_", "xc_", "kzpro_"];
+
+/// Fuzzy matches below this score are thrown away.
+pub const DEFAULT_MIN_SCORE: i64 = 50;
+
+/// If the second best fuzzy match scores at least this many percent of the best one, we can't
+/// confidently pick either.
+pub const DEFAULT_AMBIGUITY_THRESHOLD: i64 = 90;
+
+/// How a [`Candidate`] matched the query. Variants are ordered from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+	Fuzzy,
+	Prefix,
+	Exact,
+	Alias,
+}
+
+/// A single search result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate<'a> {
+	pub map: &'a GlobalMap,
+	pub kind: MatchKind,
+	/// Only comparable between candidates of the same [`MatchKind`].
+	pub score: i64,
+}
+
+/// Outcome of [`MapSearch::find`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchResult<'a> {
+	Found(&'a GlobalMap),
+	/// Several maps match equally well. These are sorted from best to worst.
+	Ambiguous(Vec<&'a GlobalMap>),
+	NotFound,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapSearch {
+	/// alias -> map name, both lowercase
+	aliases: HashMap<String, String>,
+	min_score: i64,
+	ambiguity_threshold: i64,
+}
+
+impl Default for MapSearch {
+	fn default() -> Self {
+		Self::new(HashMap::new())
+	}
+}
+
+impl MapSearch {
+	/// `aliases` maps short names to full map names, e.g. `"lh" => "kz_lionharder"`.
+	pub fn new(aliases: HashMap<String, String>) -> Self {
+		Self {
+			aliases: aliases
+				.into_iter()
+				.map(|(alias, map_name)| (normalize(&alias), map_name.to_lowercase()))
+				.collect(),
+			min_score: DEFAULT_MIN_SCORE,
+			ambiguity_threshold: DEFAULT_AMBIGUITY_THRESHOLD,
+		}
+	}
+
+	pub fn min_score(mut self, min_score: i64) -> Self {
+		self.min_score = min_score;
+		self
+	}
+
+	/// See [`DEFAULT_AMBIGUITY_THRESHOLD`].
+	pub fn ambiguity_threshold(mut self, percent: i64) -> Self {
+		self.ambiguity_threshold = percent.clamp(0, 100);
+		self
+	}
+
+	/// Returns all maps matching `query`, best match first.
+	pub fn search<'a>(&self, query: &str, map_pool: &'a [GlobalMap]) -> Vec<Candidate<'a>> {
+		let query = query.trim().to_lowercase();
+		let stripped_query = strip_prefix(&query);
+
+		if stripped_query.is_empty() {
+			return Vec::new();
+		}
+
+		let alias = self
+			.aliases
+			.get(&query)
+			.map(String::as_str);
+		let fzf = SkimMatcherV2::default();
+
+		let mut candidates = map_pool
+			.iter()
+			.filter_map(|map| {
+				let name = map.name.to_lowercase();
+				let stripped_name = strip_prefix(&name);
+
+				let (kind, score) = if alias == Some(name.as_str()) {
+					(MatchKind::Alias, 0)
+				} else if name == query {
+					// `kz_foo` should win over `bkz_foo` if the user typed `kz_foo`
+					(MatchKind::Exact, 1)
+				} else if stripped_name == stripped_query {
+					(MatchKind::Exact, 0)
+				} else if stripped_name.starts_with(stripped_query) {
+					// shorter names are closer to what the user typed
+					(MatchKind::Prefix, -(stripped_name.len() as i64))
+				} else {
+					let score = fzf.fuzzy_match(stripped_name, stripped_query)?;
+					if score < self.min_score {
+						return None;
+					}
+					(MatchKind::Fuzzy, score)
+				};
+
+				Some(Candidate { map, kind, score })
+			})
+			.collect::<Vec<_>>();
+
+		candidates.sort_by(|a, b| {
+			b.kind
+				.cmp(&a.kind)
+				.then(b.score.cmp(&a.score))
+				.then_with(|| a.map.name.cmp(&b.map.name))
+		});
+
+		candidates
+	}
+
+	/// Tries to find exactly one map for `map_identifier`. Map IDs are never ambiguous.
+	pub fn find<'a>(
+		&self,
+		map_identifier: impl Into<MapIdentifier>,
+		map_pool: &'a [GlobalMap],
+	) -> SearchResult<'a> {
+		let map_name = match map_identifier.into() {
+			MapIdentifier::ID(map_id) => {
+				return map_pool
+					.iter()
+					.find(|map| map.id == map_id)
+					.map_or(SearchResult::NotFound, SearchResult::Found);
+			}
+			MapIdentifier::Name(map_name) => map_name,
+		};
+
+		let candidates = self.search(&map_name, map_pool);
+		let Some(best) = candidates.first() else {
+			return SearchResult::NotFound;
+		};
+
+		let contenders = candidates
+			.iter()
+			.take_while(|candidate| candidate.kind == best.kind)
+			.filter(|candidate| match best.kind {
+				MatchKind::Alias | MatchKind::Exact => candidate.score == best.score,
+				MatchKind::Prefix => true,
+				MatchKind::Fuzzy => candidate.score * 100 >= best.score * self.ambiguity_threshold,
+			})
+			.map(|candidate| candidate.map)
+			.collect::<Vec<_>>();
+
+		match contenders.as_slice() {
+			[map] => SearchResult::Found(map),
+			_ => SearchResult::Ambiguous(contenders),
+		}
+	}
+}
+
+/// Removes the first matching entry of [`MAP_PREFIXES`].
+pub fn strip_prefix(map_name: &str) -> &str {
+	MAP_PREFIXES
+		.iter()
+		.find_map(|prefix| map_name.strip_prefix(prefix))
+		.unwrap_or(map_name)
+}
+
+fn normalize(input: &str) -> String {
+	input.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+	use {super::*, crate::global_maps::tests::map, gokz_rs::Tier};
+
+	fn map_pool() -> Vec<GlobalMap> {
+		vec![
+			map(1, "kz_lionharder", Tier::VeryHard),
+			map(2, "kz_lionheart", Tier::Hard),
+			map(3, "kz_beginnerblock_go", Tier::VeryEasy),
+			map(4, "kz_checkmate", Tier::Medium),
+			map(5, "bkz_checkmate", Tier::Easy),
+			map(6, "xc_powerblock_rc1", Tier::Hard),
+			map(7, "kzpro_concrete_c02", Tier::Medium),
+		]
+	}
+
+	fn found(result: SearchResult<'_>) -> Option<&str> {
+		match result {
+			SearchResult::Found(map) => Some(&map.name),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn strip_known_prefixes() {
+		assert_eq!(strip_prefix("kz_lionharder"), "lionharder");
+		assert_eq!(strip_prefix("bkz_checkmate"), "checkmate");
+		assert_eq!(strip_prefix("xc_powerblock_rc1"), "powerblock_rc1");
+		assert_eq!(strip_prefix("kzpro_concrete_c02"), "concrete_c02");
+		assert_eq!(strip_prefix("skz_foo"), "skz_foo");
+	}
+
+	#[test]
+	fn exact_and_prefix_matches() {
+		let maps = map_pool();
+		let search = MapSearch::default();
+
+		assert_eq!(found(search.find(String::from("kz_lionharder"), &maps)), Some("kz_lionharder"));
+		assert_eq!(found(search.find(String::from("LIONHARDER"), &maps)), Some("kz_lionharder"));
+		assert_eq!(
+			found(search.find(String::from("powerblock"), &maps)),
+			Some("xc_powerblock_rc1")
+		);
+		assert_eq!(found(search.find(String::from("concrete"), &maps)), Some("kzpro_concrete_c02"));
+		assert_eq!(found(search.find(String::from("kz_checkmate"), &maps)), Some("kz_checkmate"));
+		assert_eq!(found(search.find(String::from("bkz_checkmate"), &maps)), Some("bkz_checkmate"));
+		assert_eq!(found(search.find(3, &maps)), Some("kz_beginnerblock_go"));
+	}
+
+	#[test]
+	fn ambiguous_matches() {
+		let maps = map_pool();
+		let search = MapSearch::default();
+
+		let SearchResult::Ambiguous(candidates) = search.find(String::from("lion"), &maps) else {
+			panic!("`lion` should be ambiguous");
+		};
+		let names = candidates
+			.iter()
+			.map(|map| map.name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(names, ["kz_lionheart", "kz_lionharder"]);
+
+		let SearchResult::Ambiguous(candidates) = search.find(String::from("checkmate"), &maps)
+		else {
+			panic!("`checkmate` should be ambiguous");
+		};
+		assert_eq!(candidates.len(), 2);
+	}
+
+	#[test]
+	fn aliases() {
+		let maps = map_pool();
+		let search = MapSearch::new(HashMap::from_iter([
+			(String::from("LH"), String::from("kz_lionharder")),
+			(String::from("gone"), String::from("kz_not_global")),
+		]));
+
+		assert_eq!(found(search.find(String::from("lh"), &maps)), Some("kz_lionharder"));
+		assert_eq!(search.find(String::from("gone"), &maps), SearchResult::NotFound);
+	}
+
+	#[test]
+	fn fuzzy_matches() {
+		let maps = map_pool();
+		let search = MapSearch::default();
+
+		assert_eq!(
+			found(search.find(String::from("beginnerblck"), &maps)),
+			Some("kz_beginnerblock_go")
+		);
+		assert_eq!(search.find(String::from("zzz"), &maps), SearchResult::NotFound);
+		assert_eq!(search.find(String::from("kz_"), &maps), SearchResult::NotFound);
+		assert!(search.search("", &maps).is_empty());
+	}
+}
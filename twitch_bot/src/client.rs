@@ -4,63 +4,135 @@ use {
 		db::{StreamerInfo, StreamerInfoRow},
 		error::{DatabaseError, GenParseError},
 		funny_macro::parse_args,
+		rate_limit::{CommandLimits, MessageQueue, RateLimiter},
+		settings::{self, ChannelSettings, SettingsUpdate},
+		supervisor::{Credentials, Health},
 		Error, Result,
 	},
 	color_eyre::{eyre::eyre, Result as Eyre},
 	gokz_rs::{MapIdentifier, Mode, PlayerIdentifier},
-	schnosebot::global_maps::{self, GlobalMap},
+	schnosebot::{
+		global_maps::{GlobalMap, MapCache},
+		map_search::{MapSearch, SearchResult},
+	},
 	sqlx::{MySql, Pool, QueryBuilder},
-	std::{collections::HashSet, fmt::Display},
-	tracing::error,
+	std::{
+		collections::{HashMap, HashSet},
+		fmt::Display,
+		path::PathBuf,
+		sync::{Arc, Mutex},
+		time::{Duration, Instant},
+	},
+	tokio::sync::{broadcast::error::RecvError, RwLock},
+	tracing::{error, info, warn},
 	twitch_irc::{
-		irc,
-		login::StaticLoginCredentials,
 		message::PrivmsgMessage,
 		transport::tcp::{TCPTransport, TLS},
 		TwitchIRCClient,
 	},
 };
 
-pub type TwitchClient = TwitchIRCClient<TCPTransport<TLS>, StaticLoginCredentials>;
+pub type TwitchClient = TwitchIRCClient<TCPTransport<TLS>, Credentials>;
 
 #[derive(Debug)]
 pub struct GlobalState {
 	pub client: TwitchClient,
 	pub channels: HashSet<String>,
 	pub gokz_client: gokz_rs::Client,
-	pub maps: Vec<GlobalMap>,
+	pub maps: MapCache,
+	pub map_search: MapSearch,
 	pub conn_pool: Pool<MySql>,
+	/// Keyed by channel ID. Channels that never changed anything aren't in here.
+	pub settings: RwLock<HashMap<String, ChannelSettings>>,
+	/// All outgoing messages go through here.
+	pub queue: MessageQueue,
+	pub command_limiter: Mutex<RateLimiter>,
+	pub health: Health,
 }
 
 impl GlobalState {
+	#[allow(clippy::too_many_arguments)]
 	pub async fn new(
 		client: TwitchClient,
 		channels: Vec<String>,
 		gokz_client: gokz_rs::Client,
 		conn_pool: Pool<MySql>,
+		map_refresh_interval: Duration,
+		map_snapshot: Option<PathBuf>,
+		map_search: MapSearch,
+		health: Health,
 	) -> Self {
-		let maps = global_maps::init(&gokz_client, false)
+		let maps = MapCache::new(&gokz_client, false, map_snapshot)
 			.await
 			.expect("Failed to fetch global maps.");
 
+		if maps.is_degraded() {
+			warn!("Starting with a map pool loaded from disk.");
+		}
+
+		maps.spawn_refresh_task(gokz_client.clone(), map_refresh_interval);
+
+		let mut map_updates = maps.subscribe();
+		tokio::spawn(async move {
+			loop {
+				match map_updates.recv().await {
+					Ok(diff) => info!("Map pool changed: {diff}"),
+					Err(RecvError::Lagged(skipped)) => warn!("Missed {skipped} map pool updates."),
+					Err(RecvError::Closed) => break,
+				}
+			}
+		});
+
+		settings::create_tables(&conn_pool)
+			.await
+			.expect("Failed to create channel settings tables.");
+
+		let settings = settings::load_all(&conn_pool)
+			.await
+			.expect("Failed to load channel settings.");
+
+		let queue = MessageQueue::spawn(client.clone());
+
 		Self {
 			client,
 			channels: HashSet::from_iter(channels),
 			gokz_client,
 			maps,
+			map_search,
 			conn_pool,
+			settings: RwLock::new(settings),
+			queue,
+			command_limiter: Mutex::new(RateLimiter::new(CommandLimits::COMMANDS)),
+			health,
 		}
 	}
 
-	pub fn global_maps(&self) -> &Vec<GlobalMap> {
-		&self.maps
+	pub fn global_maps(&self) -> Arc<Vec<GlobalMap>> {
+		self.maps.maps()
 	}
 
 	pub fn get_map(&self, map_identifier: impl Into<MapIdentifier>) -> Result<GlobalMap> {
 		let map_identifier = map_identifier.into();
-		schnosebot::global_maps::fuzzy_find_map(map_identifier.clone(), self.global_maps()).ok_or(
-			gokz_rs::Error::InvalidMapIdentifier { value: map_identifier.to_string() }.into(),
-		)
+		let maps = self.global_maps();
+
+		// Answering for the wrong map is worse than not answering at all.
+		match self
+			.map_search
+			.find(map_identifier.clone(), &maps)
+		{
+			SearchResult::Found(map) => Ok(map.to_owned()),
+			SearchResult::Ambiguous(candidates) => Err(Error::AmbiguousMap {
+				input: map_identifier.to_string(),
+				candidates: candidates
+					.into_iter()
+					.map(|map| map.name.clone())
+					.collect(),
+			}),
+			SearchResult::NotFound => {
+				Err(gokz_rs::Error::InvalidMapIdentifier { value: map_identifier.to_string() }
+					.into())
+			}
+		}
 	}
 
 	pub async fn streamer_info(&self, channel_id: impl AsRef<str>) -> Result<StreamerInfo> {
@@ -81,6 +153,38 @@ impl GlobalState {
 			.try_into()
 	}
 
+	pub async fn channel_settings(&self, channel_id: &str) -> ChannelSettings {
+		self.settings
+			.read()
+			.await
+			.get(channel_id)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// `!schnose set ...`, only available to the streamer and their moderators.
+	async fn update_settings(&self, message: PrivmsgMessage, args: &str) -> Result<String> {
+		if !is_moderator(&message) {
+			return Err(Error::Custom(String::from(
+				"Only the streamer and moderators can change settings.",
+			)));
+		}
+
+		let update = SettingsUpdate::parse(args)?;
+
+		let mut all_settings = self.settings.write().await;
+		let mut settings = all_settings
+			.get(&message.channel_id)
+			.cloned()
+			.unwrap_or_default();
+
+		let reply = settings.apply(update);
+		settings::save(&self.conn_pool, &message.channel_id, &settings).await?;
+		all_settings.insert(message.channel_id, settings);
+
+		Ok(reply)
+	}
+
 	pub async fn send(
 		&self,
 		message: impl Display,
@@ -96,16 +200,9 @@ impl GlobalState {
 		let channel = self
 			.channels
 			.get(&ctx.channel_login)
-			.map(|channel| format!("#{channel}"))
 			.ok_or(eyre!("NO CHANNEL FOUND"))?;
 
-		if let Err(why) = self
-			.client
-			.send_message(irc!("PRIVMSG", channel, message.to_string()))
-			.await
-		{
-			error!("Failed to send message: {why:?}");
-		}
+		self.queue.push(channel, message);
 
 		Ok(())
 	}
@@ -117,25 +214,62 @@ impl GlobalState {
 			return Ok(());
 		}
 
-		let (reply, tag_user) = match Command::parse(self, message.clone()).await {
-			Ok(command) => {
-				let tag_user = match command {
-					Command::Apistatus => true,
-					Command::BPB { .. } => true,
-					Command::BWR { .. } => true,
-					Command::Map { .. } => true,
-					Command::WR { .. } => true,
-					Command::PB { .. } => true,
-					Command::Player { .. } => true,
-					Command::Recent { .. } => true,
-					Command::MostRecentRun => true,
-				};
-
-				match command.execute(self).await {
-					Ok(message) => (message, tag_user),
-					Err(why) => (why.to_string(), false),
-				}
+		let settings = self
+			.channel_settings(&message.channel_id)
+			.await;
+
+		if let Some(args) = settings.management_args(&message.message_text) {
+			let reply = match self
+				.update_settings(message.clone(), args)
+				.await
+			{
+				Ok(reply) => reply,
+				Err(why) => why.to_string(),
+			};
+
+			if let Err(why) = self
+				.send(reply, message, settings.tag_user)
+				.await
+			{
+				error!("Failed to send reply: {why:?}");
+			}
+
+			return Ok(());
+		}
+
+		if !settings.has_command(&message.message_text) {
+			return Ok(());
+		}
+
+		// Checked before parsing so replies to invalid arguments are rate limited as well.
+		let cooldown = self
+			.command_limiter
+			.lock()
+			.expect("Lock poisoned")
+			.check(
+				&message.channel_login,
+				&message.sender.login,
+				is_moderator(&message),
+				Instant::now(),
+			);
+
+		if let Err(cooldown) = cooldown {
+			info!(
+				"[{}] {} is on cooldown: {cooldown:?}",
+				message.channel_login, message.sender.name
+			);
+			if cooldown.notify {
+				self.send(cooldown, message, true)
+					.await?;
 			}
+			return Ok(());
+		}
+
+		let (reply, tag_user) = match Command::parse(self, message.clone(), &settings).await {
+			Ok(command) => match command.execute(self).await {
+				Ok(message) => (message, settings.tag_user),
+				Err(why) => (why.to_string(), false),
+			},
 			Err(why) => (
 				match why {
 					e @ Error::Unknown => return Err(e.into()),
@@ -153,8 +287,9 @@ impl GlobalState {
 					e @ Error::Database(_) => e.to_string(),
 					e @ Error::Twitch => e.to_string(),
 					e @ Error::StreamerNotPlaying => e.to_string(),
+					e @ Error::AmbiguousMap { .. } => e.to_string(),
 				},
-				true,
+				settings.tag_user,
 			),
 		};
 
@@ -168,6 +303,44 @@ impl GlobalState {
 		Ok(())
 	}
 
+	/// Called after every successful login. twitch_irc keeps track of joined channels itself, but
+	/// we tell it again about all of them in case it lost any while reconnecting.
+	pub fn rejoin_channels(&self) -> Result<()> {
+		self.health.logged_in();
+		self.client
+			.set_wanted_channels(self.channels.clone())?;
+
+		Ok(())
+	}
+
+	/// Rejoins any channels we should be in but aren't.
+	#[tracing::instrument(skip(self))]
+	pub async fn check_channels(&self) {
+		let mut joined = 0;
+
+		for channel in &self.channels {
+			if let (_, true) = self
+				.client
+				.get_channel_status(channel.to_owned())
+				.await
+			{
+				joined += 1;
+				continue;
+			}
+
+			warn!("Not in `{channel}`. Rejoining...");
+
+			if let Err(why) = self.client.join(channel.to_owned()) {
+				error!("Failed to join `{channel}`: {why:?}");
+			}
+		}
+
+		self.health.update(|health| {
+			health.channels_joined = joined;
+			health.channels_wanted = self.channels.len();
+		});
+	}
+
 	pub async fn join_channel(&mut self, ctx: PrivmsgMessage) -> Result<()> {
 		let channel_name = &ctx.sender.login;
 		let mut query =
@@ -211,6 +384,14 @@ impl GlobalState {
 	}
 }
 
+/// Streamers and their moderators.
+pub fn is_moderator(message: &PrivmsgMessage) -> bool {
+	message
+		.badges
+		.iter()
+		.any(|badge| matches!(badge.name.as_str(), "broadcaster" | "moderator"))
+}
+
 #[derive(Debug, Clone)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum Command {
@@ -245,28 +426,28 @@ pub enum Command {
 		player: PlayerIdentifier,
 	},
 	MostRecentRun,
+	/// A custom text command with its placeholders filled in already.
+	Custom {
+		response: String,
+	},
 }
 
 impl Command {
-	pub async fn parse(state: &GlobalState, message: PrivmsgMessage) -> Result<Self> {
-		if !message.message_text.starts_with('!') {
+	pub async fn parse(
+		state: &GlobalState,
+		message: PrivmsgMessage,
+		settings: &ChannelSettings,
+	) -> Result<Self> {
+		let Some((command_name, msg)) = settings.strip_prefix(&message.message_text) else {
 			return Err(Error::NotACommand);
-		}
-
-		let msg = message.message_text.trim().to_owned();
-
-		let (_prefix, args) = msg
-			.split_once('!')
-			.expect("We checked for '!' above.");
+		};
 
-		let mut args = args.split(' ').collect::<Vec<_>>();
+		let (command_name, msg) = (command_name.to_lowercase(), msg.to_owned());
 
-		let (command_name, msg) = (
-			args[0],
-			args.drain(1..)
-				.collect::<Vec<_>>()
-				.join(" "),
-		);
+		let builtin = settings::builtin_command(&command_name);
+		if builtin.is_some_and(|command| !settings.is_enabled(command)) {
+			return Err(Error::NotACommand);
+		}
 
 		let streamer_info = state
 			.streamer_info(message.channel_id)
@@ -276,8 +457,20 @@ impl Command {
 		let sender_name = message.sender.name;
 		let parser = Parser::new(streamer_info.as_ref(), channel_name, sender_name);
 
+		let Some(command_name) = builtin else {
+			return match settings
+				.custom_commands
+				.get(&command_name)
+			{
+				Some(template) => Ok(Self::Custom {
+					response: settings::render(template, streamer_info.as_ref().ok()),
+				}),
+				None => Err(Error::UnknownCommand(command_name)),
+			};
+		};
+
 		match command_name {
-			"api" | "apistatus" => Ok(Self::Apistatus),
+			"apistatus" => Ok(Self::Apistatus),
 			"bpb" => {
 				let (map, mode, course, player) =
 					parse_args!(msg, "opt" MapIdentifier, "opt" Mode, "opt" u8, PlayerIdentifier)?;
@@ -298,7 +491,7 @@ impl Command {
 
 				Ok(Self::BWR { map, mode, course })
 			}
-			"m" | "map" => {
+			"map" => {
 				let map = parse_args!(msg, "opt" MapIdentifier)?;
 				let map = parser.parse_map(map)?;
 				let map = state.get_map(map)?;
@@ -323,7 +516,7 @@ impl Command {
 
 				Ok(Self::PB { map, player, mode })
 			}
-			"p" | "player" | "profile" => {
+			"player" => {
 				let player = parse_args!(msg, "opt" PlayerIdentifier)?;
 				let player = parser.parse_player_identifier(player);
 
@@ -335,7 +528,7 @@ impl Command {
 
 				Ok(Self::Recent { player })
 			}
-			"mostrecentrun" | "mrr" => Ok(Self::MostRecentRun),
+			"mrr" => Ok(Self::MostRecentRun),
 			cmd => Err(Error::UnknownCommand(cmd.to_owned())),
 		}
 	}
@@ -355,6 +548,7 @@ impl Command {
 			Self::Player { player } => commands::player::execute(state, player).await,
 			Self::Recent { player } => commands::recent::execute(state, player).await,
 			Self::MostRecentRun => commands::mrr::execute(state).await,
+			Self::Custom { response } => Ok(response),
 		}
 	}
 }
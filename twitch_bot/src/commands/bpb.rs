@@ -1,8 +1,8 @@
 use {
+	super::format_pb,
 	crate::{client::GlobalState, Result},
-	gokz_rs::{global_api, Mode, PlayerIdentifier},
-	schnosebot::{formatting::fmt_time, global_maps::GlobalMap},
-	tokio::time::{sleep, Duration},
+	gokz_rs::{Mode, PlayerIdentifier},
+	schnosebot::{commands, global_maps::GlobalMap},
 };
 
 #[tracing::instrument(skip(state))]
@@ -13,39 +13,15 @@ pub async fn execute(
 	mode: Mode,
 	course: u8,
 ) -> Result<String> {
-	let tp =
-		global_api::get_pb(player.clone(), map.id.into(), mode, true, course, &state.gokz_client)
-			.await;
-	let pro =
-		global_api::get_pb(player.clone(), map.id.into(), mode, false, course, &state.gokz_client)
-			.await;
+	let pbs = commands::pb::execute(map, player.clone(), mode, course, &state.gokz_client).await;
 
-	let map = map.name;
+	let map = &pbs.map.name;
 	let mode = mode.short();
-	let mut player_name = player;
-
-	let tp = if let Ok(record) = tp {
-		player_name = record.player_name.into();
-		format!(
-			"{} ({})",
-			fmt_time(record.time),
-			match record.teleports {
-				1 => String::from("1 TP"),
-				n => format!("{n} TPs"),
-			}
-		)
-	} else {
-		String::from("no TP record")
-	};
-
-	let pro = if let Ok(record) = pro {
-		player_name = record.player_name.into();
-		fmt_time(record.time)
-	} else {
-		String::from("no PRO record")
-	};
-
-	sleep(Duration::from_millis(727)).await;
+	let player_name = pbs
+		.player_name()
+		.map_or_else(|| player.to_string(), String::from);
+	let tp = format_pb(&pbs.tp, "TP");
+	let pro = format_pb(&pbs.pro, "PRO");
 
 	Ok(format!("[{player_name} on {map} B{course} in {mode}] TP: {tp} / PRO: {pro}"))
 }
@@ -1,3 +1,5 @@
+use schnosebot::{commands::Record, formatting::fmt_time};
+
 pub mod apistatus;
 pub mod bpb;
 pub mod bwr;
@@ -7,3 +9,62 @@ pub mod pb;
 pub mod player;
 pub mod recent;
 pub mod wr;
+
+fn format_teleports(teleports: u32) -> String {
+	match teleports {
+		1 => String::from("1 TP"),
+		n => format!("{n} TPs"),
+	}
+}
+
+/// `time (n TPs) by player` or `no TP record`
+fn format_wr(record: &Option<Record>, runtype: &str) -> String {
+	match record {
+		Some(record) if record.is_tp() => format!(
+			"{} ({}) by {}",
+			fmt_time(record.time),
+			format_teleports(record.teleports),
+			record.player_name
+		),
+		Some(record) => format!("{} by {}", fmt_time(record.time), record.player_name),
+		None => format!("no {runtype} record"),
+	}
+}
+
+/// `time [#place] (n TPs)` or `no TP record`
+fn format_pb(record: &Option<Record>, runtype: &str) -> String {
+	let Some(record) = record else {
+		return format!("no {runtype} record");
+	};
+
+	let mut text = fmt_time(record.time);
+
+	if let Some(place) = record.place {
+		text.push_str(&format!(" [#{place}]"));
+	}
+
+	if record.is_tp() {
+		text.push_str(&format!(" ({})", format_teleports(record.teleports)));
+	}
+
+	text
+}
+
+/// `[player on map in mode runtype] time (n TPs) on date`
+fn format_recent(record: &Record) -> String {
+	let player_name = &record.player_name;
+	let map = &record.map_name;
+	let mode = record.mode.short();
+	let runtype = record.runtype();
+	let teleports = if record.is_tp() {
+		format!("({})", format_teleports(record.teleports))
+	} else {
+		String::new()
+	};
+	let time = fmt_time(record.time);
+	let date = record
+		.created_on
+		.format("%d-%m-%Y %H:%M:%S");
+
+	format!("[{player_name} on {map} in {mode} {runtype}] {time} {teleports} on {date}")
+}
@@ -3,8 +3,8 @@ use {
 	color_eyre::Result as Eyre,
 	gokz_rs::{Mode, SteamID, Tier},
 	serde::Deserialize,
-	sqlx::{FromRow, MySql, Pool, QueryBuilder},
-	tracing::{info, warn},
+	sqlx::{FromRow, MySql, Pool},
+	tracing::info,
 };
 
 #[derive(Debug, FromRow)]
@@ -53,66 +53,6 @@ pub async fn get_config(conn_pool: &Pool<MySql>, prod: bool) -> Eyre<Config, sql
 	})
 }
 
-pub async fn update_tokens(
-	mut config: Config,
-	prod: bool,
-	client: &gokz_rs::Client,
-	conn_pool: &Pool<MySql>,
-) -> Eyre<Config> {
-	let response = client
-		.get("https://id.twitch.tv/oauth2/validate")
-		.header("Authorization", format!("OAuth {}", config.access_token))
-		.send()
-		.await?;
-
-	match response.status().as_u16() {
-		200 => return Ok(config),
-		code => {
-			warn!("[{code}] Current `access_token` is not valid anymore. Refreshing...");
-
-			let new_credentials = client
-				.post("https://id.twitch.tv/oauth2/token")
-				.header("Content-Type", "application/x-www-form-urlencoded")
-				.query(&[
-					("client_id", config.client_id.as_str()),
-					("client_secret", config.client_secret.as_str()),
-					("grant_type", "refresh_token"),
-					("refresh_token", config.refresh_token.as_str()),
-				])
-				.send()
-				.await?
-				.json::<TwitchResponse>()
-				.await?;
-
-			let mut query = QueryBuilder::<MySql>::new("UPDATE configs SET access_token = ");
-
-			query
-				.push_bind(&new_credentials.access_token)
-				.push(" , refresh_token = ")
-				.push_bind(&new_credentials.refresh_token)
-				.push(" WHERE id = ")
-				.push_bind(if prod { 1 } else { 2 });
-
-			query.build().execute(conn_pool).await?;
-
-			config.access_token = new_credentials.access_token;
-			config.refresh_token = new_credentials.refresh_token;
-		}
-	}
-
-	Ok(config)
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct TwitchResponse {
-	access_token: String,
-	expires_in: i32,
-	refresh_token: String,
-	scope: Vec<String>,
-	token_type: String,
-}
-
 #[derive(Debug, Clone, FromRow)]
 pub struct StreamerInfoRow {
 	pub api_key: String,
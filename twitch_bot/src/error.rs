@@ -18,6 +18,7 @@ pub enum Error {
 	Database(DatabaseError),
 	Twitch,
 	StreamerNotPlaying,
+	AmbiguousMap { input: String, candidates: Vec<String> },
 }
 
 impl std::error::Error for Error {}
@@ -46,6 +47,18 @@ impl Display for Error {
 			Self::StreamerNotPlaying => {
 				f.write_str("The streamer is not currently playing. Please supply arguments.")
 			}
+			Self::AmbiguousMap { input, candidates } => {
+				let shown = candidates
+					.iter()
+					.take(5)
+					.map(String::as_str)
+					.collect::<Vec<_>>()
+					.join(", ");
+				let more = if candidates.len() > 5 { ", ..." } else { "" };
+				f.write_fmt(format_args!(
+					"`{input}` matches multiple maps ({shown}{more}). Please be more specific."
+				))
+			}
 		}
 	}
 }
@@ -6,18 +6,17 @@
 use {
 	clap::Parser,
 	client::GlobalState,
-	color_eyre::Result as Eyre,
+	color_eyre::{eyre::eyre, Result as Eyre},
+	rate_limit::{CommandLimits, RateLimiter},
+	schnosebot::{global_maps, map_search::MapSearch},
 	serde::Deserialize,
 	sqlx::mysql::MySqlPoolOptions,
-	std::path::PathBuf,
-	tokio::time::Instant,
-	tracing::{debug, info, warn, Level},
+	std::{collections::HashMap, path::PathBuf, time::Instant},
+	supervisor::{Credentials, DatabaseTokenStorage, Health, Supervisor, CHANNEL_CHECK_INTERVAL},
+	tokio::time::Duration,
+	tracing::{debug, error, info, warn, Level},
 	tracing_subscriber::fmt::format::FmtSpan,
-	twitch_irc::{
-		login::{CredentialsPair, StaticLoginCredentials},
-		message::ServerMessage,
-		ClientConfig, SecureTCPTransport, TwitchIRCClient,
-	},
+	twitch_irc::{message::ServerMessage, ClientConfig, SecureTCPTransport, TwitchIRCClient},
 };
 
 #[derive(Debug, Parser)]
@@ -35,6 +34,12 @@ struct Args {
 #[allow(unused)]
 struct Config {
 	mysql_url: String,
+	/// How often (in seconds) to re-fetch the map pool. This defaults to 30 minutes.
+	map_refresh_interval: Option<u64>,
+	/// File to store a copy of the map pool in, used as a fallback if the APIs are down.
+	map_snapshot: Option<PathBuf>,
+	/// Short names for maps, e.g. `lh = "kz_lionharder"`.
+	map_aliases: Option<HashMap<String, String>>,
 }
 
 mod client;
@@ -42,6 +47,9 @@ mod commands;
 mod db;
 mod error;
 mod funny_macro;
+mod rate_limit;
+mod settings;
+mod supervisor;
 
 pub use error::{Error, Result};
 
@@ -63,26 +71,54 @@ async fn main() -> Eyre<()> {
 	let config: Config = toml::from_str(&config_file)?;
 
 	let gokz_client = gokz_rs::Client::new();
+	let map_refresh_interval = config
+		.map_refresh_interval
+		.map_or(global_maps::DEFAULT_REFRESH_INTERVAL, Duration::from_secs);
+	let map_snapshot = config.map_snapshot;
+	let map_search = MapSearch::new(config.map_aliases.unwrap_or_default());
 
 	let conn_pool = MySqlPoolOptions::new()
 		.connect(&config.mysql_url)
 		.await?;
 
 	let config = db::get_config(&conn_pool, !args.debug).await?;
-	let config = db::update_tokens(config, !args.debug, &gokz_client, &conn_pool).await?;
 
-	let client_config = ClientConfig::new_simple(StaticLoginCredentials {
-		credentials: CredentialsPair {
-			login: String::from(BOT_NAME),
-			token: Some(config.access_token),
-		},
-	});
+	let storage = DatabaseTokenStorage::new(
+		conn_pool.clone(),
+		!args.debug,
+		config.access_token,
+		config.refresh_token,
+	);
+
+	let credentials = Credentials::init_with_username(
+		Some(String::from(BOT_NAME)),
+		config.client_id,
+		config.client_secret,
+		storage.clone(),
+	);
+
+	let health = Health::default();
+	let supervisor = Supervisor {
+		credentials: credentials.clone(),
+		storage,
+		http_client: gokz_client.clone(),
+		health: health.clone(),
+	};
+
+	// Don't try to log in with an expired token.
+	supervisor.check_token().await?;
+	supervisor.spawn();
+
+	let client_config = ClientConfig::new_simple(credentials);
 
 	let (mut stream, twitch_client) =
-		TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(client_config);
+		TwitchIRCClient::<SecureTCPTransport, Credentials>::new(client_config);
 
-	let mut global_state =
-		GlobalState::new(twitch_client, config.channel_names, gokz_client, conn_pool).await;
+	let mut global_state = GlobalState::new(
+		twitch_client, config.channel_names, gokz_client, conn_pool, map_refresh_interval,
+		map_snapshot, map_search, health,
+	)
+	.await;
 
 	for channel in &global_state.channels {
 		info!("Joining `{channel}`");
@@ -91,9 +127,24 @@ async fn main() -> Eyre<()> {
 			.join(channel.to_owned())?;
 	}
 
-	let mut last_message = Instant::now();
+	let mut join_leave_limiter = RateLimiter::new(CommandLimits::JOIN_LEAVE);
+	let mut channel_check = tokio::time::interval(CHANNEL_CHECK_INTERVAL);
+
+	loop {
+		let message = tokio::select! {
+			message = stream.recv() => message,
+			_ = channel_check.tick() => {
+				global_state.check_channels().await;
+				continue;
+			}
+		};
+
+		// twitch_irc reconnects on its own, so this only happens if the client is gone.
+		let Some(message) = message else {
+			error!("Twitch connection closed.");
+			return Err(eyre!("Twitch connection closed."));
+		};
 
-	while let Some(message) = stream.recv().await {
 		match message {
 			ServerMessage::Privmsg(mut message) => {
 				let old_message = message.message_text.clone();
@@ -110,35 +161,47 @@ async fn main() -> Eyre<()> {
 				);
 
 				if message.channel_login == "schnosebot" {
-					let elapsed = last_message.elapsed().as_secs();
-
-					match message.message_text.trim() {
-						"!join" | "!leave" if elapsed < 30 => {
-							let msg = format!(
-								"Currently on cooldown. Please wait another {} second(s).",
-								30 - elapsed
-							);
+					let command = message.message_text.trim().to_owned();
+					if !matches!(command.as_str(), "!join" | "!leave" | "!status") {
+						continue;
+					}
+
+					if let Err(cooldown) = join_leave_limiter.check(
+						&message.channel_login,
+						&message.sender.login,
+						false,
+						Instant::now(),
+					) {
+						if cooldown.notify {
 							global_state
-								.send(msg, message, false)
+								.send(cooldown, message, true)
 								.await?;
-							continue;
 						}
+						continue;
+					}
+
+					match command.as_str() {
 						"!join" => {
 							global_state
 								.join_channel(message)
-								.await?;
+								.await?
 						}
 						"!leave" => {
 							global_state
 								.leave_channel(message)
+								.await?
+						}
+						_ => {
+							let health = global_state.health.get();
+							global_state
+								.send(health, message, true)
 								.await?;
+							continue;
 						}
-						_ => {}
 					}
 
 					debug!("Current channels: {:#?}", global_state.channels);
 
-					last_message = Instant::now();
 					continue;
 				}
 
@@ -149,12 +212,30 @@ async fn main() -> Eyre<()> {
 					warn!("Command failed: {why:?}");
 				}
 			}
+			ServerMessage::UserState(user_state) => {
+				let is_moderator = user_state
+					.badges
+					.iter()
+					.any(|badge| matches!(badge.name.as_str(), "broadcaster" | "moderator"));
+
+				global_state
+					.queue
+					.set_moderator(&user_state.channel_login, is_moderator);
+			}
+			ServerMessage::GlobalUserState(_) => {
+				info!("Logged in to Twitch.");
+
+				if let Err(why) = global_state.rejoin_channels() {
+					error!("Failed to rejoin channels: {why:?}");
+				}
+			}
+			ServerMessage::Reconnect(_) => {
+				warn!("Twitch asked us to reconnect.");
+			}
 			message => {
 				warn!("got some message");
 				debug!("{message:?}");
 			}
 		}
 	}
-
-	Ok(())
 }
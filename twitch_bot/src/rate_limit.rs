@@ -0,0 +1,586 @@
+//! Throttling for incoming commands and outgoing messages.
+//!
+//! Twitch silently drops messages (and eventually mutes accounts) that go over its rate limits,
+//! so every message the bot sends goes through a [`MessageQueue`] that waits until the limits
+//! allow it. Incoming commands are limited per user and per channel by a [`RateLimiter`].
+
+use {
+	crate::client::TwitchClient,
+	std::{
+		collections::{HashMap, HashSet, VecDeque},
+		fmt::Display,
+		sync::{Arc, Mutex},
+		time::{Duration, Instant},
+	},
+	tokio::sync::mpsc::{self, error::TryRecvError},
+	tracing::{error, warn},
+	twitch_irc::irc,
+};
+
+/// `burst` tokens that refill completely over `per`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+	pub burst: u32,
+	pub per: Duration,
+}
+
+impl Rate {
+	pub const fn new(burst: u32, per: Duration) -> Self {
+		Self { burst, per }
+	}
+}
+
+/// Twitch's limit for accounts that are not a moderator in the channel.
+pub const TWITCH_USER: Rate = Rate::new(20, Duration::from_secs(30));
+
+/// Twitch's limit for moderators and broadcasters.
+pub const TWITCH_MODERATOR: Rate = Rate::new(100, Duration::from_secs(30));
+
+/// Non-moderators may only send 1 message per second in each channel.
+pub const TWITCH_CHANNEL: Rate = Rate::new(1, Duration::from_secs(1));
+
+/// How many messages can wait in the [`MessageQueue`] before new ones get dropped.
+const QUEUE_SIZE: usize = 100;
+
+/// Buckets are only forgotten if there are more than this many of them.
+const MAX_IDLE_BUCKETS: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+	rate: Rate,
+	tokens: f64,
+	last_refill: Instant,
+}
+
+impl TokenBucket {
+	/// Starts out full.
+	pub fn new(rate: Rate, now: Instant) -> Self {
+		Self {
+			rate,
+			tokens: f64::from(rate.burst),
+			last_refill: now,
+		}
+	}
+
+	fn refill(&mut self, now: Instant) {
+		let elapsed = now
+			.saturating_duration_since(self.last_refill)
+			.as_secs_f64();
+		let per_second = f64::from(self.rate.burst) / self.rate.per.as_secs_f64();
+
+		self.tokens = (self.tokens + elapsed * per_second).min(f64::from(self.rate.burst));
+		self.last_refill = now;
+	}
+
+	/// How long until a token is available. [`Duration::ZERO`] if there is one right now.
+	pub fn available_in(&mut self, now: Instant) -> Duration {
+		self.refill(now);
+
+		if self.tokens >= 1.0 {
+			return Duration::ZERO;
+		}
+
+		let per_second = f64::from(self.rate.burst) / self.rate.per.as_secs_f64();
+		Duration::from_secs_f64((1.0 - self.tokens) / per_second)
+	}
+
+	/// Call [`Self::available_in`] first.
+	pub fn take(&mut self) {
+		self.tokens = (self.tokens - 1.0).max(0.0);
+	}
+
+	/// Takes a token if one is available, otherwise returns how long to wait for one.
+	#[cfg(test)]
+	pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+		match self.available_in(now) {
+			Duration::ZERO => {
+				self.take();
+				Ok(())
+			}
+			wait => Err(wait),
+		}
+	}
+
+	fn is_full(&mut self, now: Instant) -> bool {
+		self.refill(now);
+		self.tokens >= f64::from(self.rate.burst)
+	}
+}
+
+/// Limits for incoming commands.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandLimits {
+	/// Per user per channel. Moderators are exempt.
+	pub user: Rate,
+	pub channel: Rate,
+}
+
+impl CommandLimits {
+	pub const COMMANDS: Self = Self {
+		user: Rate::new(3, Duration::from_secs(15)),
+		channel: Rate::new(10, Duration::from_secs(20)),
+	};
+
+	/// `!join` / `!leave` in the bot's own channel.
+	pub const JOIN_LEAVE: Self = Self {
+		user: Rate::new(1, Duration::from_secs(30)),
+		channel: Rate::new(5, Duration::from_secs(30)),
+	};
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CooldownScope {
+	User,
+	Channel,
+}
+
+/// A command was rejected because of a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cooldown {
+	pub scope: CooldownScope,
+	pub remaining: Duration,
+	/// Only the first rejection per cooldown should get a reply, otherwise spamming commands
+	/// would still make the bot spam chat.
+	pub notify: bool,
+}
+
+impl Display for Cooldown {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let seconds = self.remaining.as_secs_f64().ceil() as u64;
+		match self.scope {
+			CooldownScope::User => f.write_fmt(format_args!(
+				"You're using commands too quickly. Please wait {seconds} second(s)."
+			)),
+			CooldownScope::Channel => f.write_fmt(format_args!(
+				"Too many commands in this chat. Please wait {seconds} second(s)."
+			)),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Limited {
+	bucket: TokenBucket,
+	notified: bool,
+}
+
+impl Limited {
+	fn check(&mut self, scope: CooldownScope, now: Instant) -> Result<(), Cooldown> {
+		match self.bucket.available_in(now) {
+			Duration::ZERO => Ok(()),
+			remaining => {
+				let notify = !self.notified;
+				self.notified = true;
+				Err(Cooldown { scope, remaining, notify })
+			}
+		}
+	}
+
+	fn take(&mut self) {
+		self.bucket.take();
+		self.notified = false;
+	}
+}
+
+/// Per user and per channel limits for incoming commands.
+#[derive(Debug)]
+pub struct RateLimiter {
+	limits: CommandLimits,
+	users: HashMap<(String, String), Limited>,
+	channels: HashMap<String, Limited>,
+}
+
+impl RateLimiter {
+	pub fn new(limits: CommandLimits) -> Self {
+		Self {
+			limits,
+			users: HashMap::new(),
+			channels: HashMap::new(),
+		}
+	}
+
+	/// Counts a command by `user` in `channel` if neither limit is exhausted.
+	pub fn check(
+		&mut self,
+		channel: &str,
+		user: &str,
+		is_moderator: bool,
+		now: Instant,
+	) -> Result<(), Cooldown> {
+		let limits = self.limits;
+		let new = |rate| Limited {
+			bucket: TokenBucket::new(rate, now),
+			notified: false,
+		};
+
+		let channel_bucket = self
+			.channels
+			.entry(channel.to_owned())
+			.or_insert_with(|| new(limits.channel));
+		channel_bucket.check(CooldownScope::Channel, now)?;
+
+		if !is_moderator {
+			let user_bucket = self
+				.users
+				.entry((channel.to_owned(), user.to_owned()))
+				.or_insert_with(|| new(limits.user));
+			user_bucket.check(CooldownScope::User, now)?;
+			user_bucket.take();
+		}
+
+		if let Some(channel_bucket) = self.channels.get_mut(channel) {
+			channel_bucket.take();
+		}
+
+		if self.users.len() > MAX_IDLE_BUCKETS {
+			self.users
+				.retain(|_, limited| !limited.bucket.is_full(now));
+		}
+
+		Ok(())
+	}
+}
+
+/// Twitch's limits for messages sent by the bot.
+#[derive(Debug)]
+pub struct SendLimiter {
+	global: TokenBucket,
+	global_moderator: TokenBucket,
+	channels: HashMap<String, TokenBucket>,
+}
+
+impl SendLimiter {
+	pub fn new(now: Instant) -> Self {
+		Self {
+			global: TokenBucket::new(TWITCH_USER, now),
+			global_moderator: TokenBucket::new(TWITCH_MODERATOR, now),
+			channels: HashMap::new(),
+		}
+	}
+
+	/// How long to wait before sending a message in `channel`.
+	pub fn available_in(&mut self, channel: &str, is_moderator: bool, now: Instant) -> Duration {
+		let mut wait = self.global_moderator.available_in(now);
+
+		if !is_moderator {
+			wait = wait
+				.max(self.global.available_in(now))
+				.max(
+					self.channels
+						.entry(channel.to_owned())
+						.or_insert_with(|| TokenBucket::new(TWITCH_CHANNEL, now))
+						.available_in(now),
+				);
+		}
+
+		wait
+	}
+
+	/// Call [`Self::available_in`] first.
+	pub fn take(&mut self, channel: &str, is_moderator: bool) {
+		self.global_moderator.take();
+
+		if !is_moderator {
+			self.global.take();
+			if let Some(bucket) = self.channels.get_mut(channel) {
+				bucket.take();
+			}
+		}
+	}
+}
+
+/// Messages waiting to be sent, with a separate queue for every channel so a throttled channel
+/// doesn't hold up the others.
+#[derive(Debug, Default)]
+struct Outbox {
+	/// Every message is tagged with how many messages were pushed before it.
+	channels: HashMap<String, VecDeque<(u64, String)>>,
+	pushed: u64,
+	len: usize,
+}
+
+impl Outbox {
+	fn push(&mut self, channel: String, message: String) {
+		self.channels
+			.entry(channel)
+			.or_default()
+			.push_back((self.pushed, message));
+		self.pushed += 1;
+		self.len += 1;
+	}
+
+	/// Takes the oldest message whose channel `limiter` allows right now. Otherwise returns how
+	/// long until a message is allowed, or `None` if there are no messages.
+	fn pop(
+		&mut self,
+		limiter: &mut SendLimiter,
+		is_moderator: impl Fn(&str) -> bool,
+		now: Instant,
+	) -> Result<(String, String), Option<Duration>> {
+		let mut oldest = None::<(&String, u64)>;
+		let mut wait = None::<Duration>;
+
+		for (channel, messages) in &self.channels {
+			let Some((position, _)) = messages.front() else {
+				continue;
+			};
+
+			let available_in = limiter.available_in(channel, is_moderator(channel), now);
+			if !available_in.is_zero() {
+				wait = Some(wait.map_or(available_in, |wait| wait.min(available_in)));
+			} else if oldest.is_none_or(|(_, oldest)| *position < oldest) {
+				oldest = Some((channel, *position));
+			}
+		}
+
+		let Some((channel, _)) = oldest else {
+			return Err(wait);
+		};
+
+		let channel = channel.clone();
+		let messages = self
+			.channels
+			.get_mut(&channel)
+			.expect("We just found this channel.");
+		let (_, message) = messages
+			.pop_front()
+			.expect("We just looked at this message.");
+
+		if messages.is_empty() {
+			self.channels.remove(&channel);
+		}
+		self.len -= 1;
+
+		Ok((channel, message))
+	}
+}
+
+/// Queued outbound sender. Messages to the same channel are sent in order, as fast as
+/// [`SendLimiter`] allows. Only the global limits are shared between channels.
+#[derive(Debug, Clone)]
+pub struct MessageQueue {
+	sender: mpsc::Sender<(String, String)>,
+	/// Channels in which the bot is a moderator. Twitch tells us with a `USERSTATE` message.
+	moderator_channels: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MessageQueue {
+	pub fn spawn(client: TwitchClient) -> Self {
+		let (sender, mut receiver) = mpsc::channel::<(String, String)>(QUEUE_SIZE);
+		let moderator_channels = Arc::new(Mutex::new(HashSet::new()));
+
+		let queue = Self {
+			sender,
+			moderator_channels: Arc::clone(&moderator_channels),
+		};
+
+		tokio::spawn(async move {
+			let mut limiter = SendLimiter::new(Instant::now());
+			let mut outbox = Outbox::default();
+			let mut open = true;
+
+			let is_moderator = |channel: &str| {
+				moderator_channels
+					.lock()
+					.expect("Lock poisoned")
+					.contains(channel)
+			};
+
+			loop {
+				// Messages stay in the channel while the outbox is full, so `push` starts
+				// dropping them.
+				while open && outbox.len < QUEUE_SIZE {
+					match receiver.try_recv() {
+						Ok((channel, message)) => outbox.push(channel, message),
+						Err(TryRecvError::Empty) => break,
+						Err(TryRecvError::Disconnected) => open = false,
+					}
+				}
+
+				let wait = match outbox.pop(&mut limiter, is_moderator, Instant::now()) {
+					Ok((channel, message)) => {
+						limiter.take(&channel, is_moderator(&channel));
+
+						if let Err(why) = client
+							.send_message(irc!("PRIVMSG", format!("#{channel}"), message))
+							.await
+						{
+							error!("Failed to send message: {why:?}");
+						}
+
+						continue;
+					}
+					Err(None) if !open => break,
+					Err(wait) => wait,
+				};
+
+				// Wait until a message may be sent, but keep accepting new ones, since they might
+				// be for a channel that isn't throttled.
+				let sleep = tokio::time::sleep(wait.unwrap_or(Duration::MAX));
+				tokio::select! {
+					message = receiver.recv(), if open && outbox.len < QUEUE_SIZE => match message {
+						Some((channel, message)) => outbox.push(channel, message),
+						None => open = false,
+					},
+					() = sleep => {}
+				}
+			}
+		});
+
+		queue
+	}
+
+	pub fn set_moderator(&self, channel: &str, is_moderator: bool) {
+		let mut moderator_channels = self
+			.moderator_channels
+			.lock()
+			.expect("Lock poisoned");
+
+		if is_moderator {
+			moderator_channels.insert(channel.to_owned());
+		} else {
+			moderator_channels.remove(channel);
+		}
+	}
+
+	/// Queues a message for `channel` (without the `#`). Drops it if the queue is full.
+	pub fn push(&self, channel: impl Into<String>, message: impl Into<String>) {
+		if let Err(why) = self
+			.sender
+			.try_send((channel.into(), message.into()))
+		{
+			warn!("Dropping outgoing message: {why}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn token_bucket() {
+		let start = Instant::now();
+		let mut bucket = TokenBucket::new(Rate::new(2, Duration::from_secs(10)), start);
+
+		assert!(bucket.try_take(start).is_ok());
+		assert!(bucket.try_take(start).is_ok());
+		assert_eq!(bucket.try_take(start), Err(Duration::from_secs(5)));
+
+		// 1 token every 5 seconds
+		let later = start + Duration::from_secs(6);
+		assert!(bucket.try_take(later).is_ok());
+		assert_eq!(bucket.try_take(later), Err(Duration::from_secs(4)));
+
+		// never more than `burst`
+		let much_later = later + Duration::from_secs(600);
+		assert!(bucket.is_full(much_later));
+		assert!(bucket.try_take(much_later).is_ok());
+		assert!(bucket.try_take(much_later).is_ok());
+		assert!(bucket.try_take(much_later).is_err());
+	}
+
+	#[test]
+	fn command_cooldowns() {
+		let start = Instant::now();
+		let mut limiter = RateLimiter::new(CommandLimits {
+			user: Rate::new(1, Duration::from_secs(10)),
+			channel: Rate::new(3, Duration::from_secs(30)),
+		});
+
+		assert!(limiter
+			.check("alphakeks", "szwagi", false, start)
+			.is_ok());
+
+		let cooldown = limiter
+			.check("alphakeks", "szwagi", false, start)
+			.unwrap_err();
+		assert_eq!(cooldown.scope, CooldownScope::User);
+		assert!(cooldown.notify);
+		assert_eq!(
+			cooldown.to_string(),
+			"You're using commands too quickly. Please wait 10 second(s)."
+		);
+
+		// only reply once
+		assert!(
+			!limiter
+				.check("alphakeks", "szwagi", false, start)
+				.unwrap_err()
+				.notify
+		);
+
+		// other users and channels aren't affected, moderators don't have a user limit
+		assert!(limiter
+			.check("alphakeks", "fob", false, start)
+			.is_ok());
+		assert!(limiter
+			.check("szwagi", "szwagi", false, start)
+			.is_ok());
+		assert!(limiter
+			.check("alphakeks", "alphakeks", true, start)
+			.is_ok());
+		assert_eq!(
+			limiter
+				.check("alphakeks", "alphakeks", true, start)
+				.unwrap_err()
+				.scope,
+			CooldownScope::Channel
+		);
+
+		let later = start + Duration::from_secs(10);
+		assert!(limiter
+			.check("alphakeks", "szwagi", false, later)
+			.is_ok());
+	}
+
+	#[test]
+	fn twitch_limits() {
+		let start = Instant::now();
+		let mut limiter = SendLimiter::new(start);
+
+		// 1 message per second per channel for non-moderators
+		assert_eq!(limiter.available_in("alphakeks", false, start), Duration::ZERO);
+		limiter.take("alphakeks", false);
+		assert_eq!(limiter.available_in("alphakeks", false, start), Duration::from_secs(1));
+		assert_eq!(limiter.available_in("szwagi", false, start), Duration::ZERO);
+		assert_eq!(limiter.available_in("alphakeks", true, start), Duration::ZERO);
+
+		// 20 messages per 30 seconds for non-moderators
+		for i in 1..20 {
+			let channel = format!("channel_{i}");
+			assert_eq!(limiter.available_in(&channel, false, start), Duration::ZERO, "#{i}");
+			limiter.take(&channel, false);
+		}
+
+		assert!(limiter.available_in("szwagi", false, start) > Duration::ZERO);
+		assert_eq!(limiter.available_in("szwagi", true, start), Duration::ZERO);
+	}
+
+	#[test]
+	fn throttled_channels_dont_block_others() {
+		let start = Instant::now();
+		let mut limiter = SendLimiter::new(start);
+		let mut outbox = Outbox::default();
+		let mut pop = |outbox: &mut Outbox, now| {
+			let message = outbox.pop(&mut limiter, |_| false, now);
+			if let Ok((channel, _)) = &message {
+				limiter.take(channel, false);
+			}
+			message.map(|(_, message)| message)
+		};
+
+		outbox.push(String::from("alphakeks"), String::from("1"));
+		outbox.push(String::from("alphakeks"), String::from("2"));
+		outbox.push(String::from("szwagi"), String::from("3"));
+
+		assert_eq!(pop(&mut outbox, start), Ok(String::from("1")));
+		assert_eq!(pop(&mut outbox, start), Ok(String::from("3")));
+		assert_eq!(pop(&mut outbox, start), Err(Some(Duration::from_secs(1))));
+
+		let later = start + Duration::from_secs(1);
+		assert_eq!(pop(&mut outbox, later), Ok(String::from("2")));
+		assert_eq!(pop(&mut outbox, later), Err(None));
+		assert_eq!(outbox.len, 0);
+	}
+}
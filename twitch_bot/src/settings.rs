@@ -0,0 +1,485 @@
+use {
+	crate::{db::StreamerInfo, Error, Result},
+	sqlx::{FromRow, MySql, Pool, QueryBuilder},
+	std::collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// Table for per-channel settings.
+pub const SETTINGS_TABLE: &str = "twitch_bot_channel_settings";
+
+/// Table for custom text commands.
+pub const CUSTOM_COMMANDS_TABLE: &str = "twitch_bot_custom_commands";
+
+pub const DEFAULT_PREFIX: &str = "!";
+
+/// Name of the management command. This one always works with [`DEFAULT_PREFIX`] as well, so a
+/// broken prefix can always be fixed.
+pub const MANAGEMENT_COMMAND: &str = "schnose";
+
+/// All built-in commands and their aliases. The first alias is the command's canonical name.
+pub const BUILTIN_COMMANDS: &[&[&str]] = &[
+	&["apistatus", "api"],
+	&["bpb"],
+	&["bwr"],
+	&["map", "m"],
+	&["wr"],
+	&["pb"],
+	&["player", "p", "profile"],
+	&["recent"],
+	&["mrr", "mostrecentrun"],
+];
+
+/// Returns the canonical name of a built-in command.
+pub fn builtin_command(name: &str) -> Option<&'static str> {
+	BUILTIN_COMMANDS
+		.iter()
+		.find(|aliases| aliases.contains(&name))
+		.map(|aliases| aliases[0])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelSettings {
+	pub prefix: String,
+	/// Whether replies start with `@user`.
+	pub tag_user: bool,
+	/// Canonical names of built-in commands that are turned off.
+	pub disabled: HashSet<String>,
+	/// Name -> response template. See [`render`].
+	pub custom_commands: BTreeMap<String, String>,
+}
+
+impl Default for ChannelSettings {
+	fn default() -> Self {
+		Self {
+			prefix: String::from(DEFAULT_PREFIX),
+			tag_user: true,
+			disabled: HashSet::new(),
+			custom_commands: BTreeMap::new(),
+		}
+	}
+}
+
+impl ChannelSettings {
+	/// Splits a message into command name and arguments if it starts with the channel's prefix.
+	pub fn strip_prefix<'msg>(&self, message: &'msg str) -> Option<(&'msg str, &'msg str)> {
+		let message = message
+			.trim()
+			.strip_prefix(&self.prefix)?;
+		let (name, args) = message
+			.split_once(' ')
+			.unwrap_or((message, ""));
+
+		if name.is_empty() {
+			return None;
+		}
+
+		Some((name, args.trim()))
+	}
+
+	/// Arguments of `!schnose ...` if `message` is a management command.
+	pub fn management_args<'msg>(&self, message: &'msg str) -> Option<&'msg str> {
+		let (name, args) = self
+			.strip_prefix(message)
+			.or_else(|| Self::default().strip_prefix(message))?;
+
+		(name == MANAGEMENT_COMMAND).then_some(args)
+	}
+
+	pub fn is_enabled(&self, command: &str) -> bool {
+		!self.disabled.contains(command)
+	}
+
+	/// Whether `message` invokes a command the bot will reply to, i.e. an enabled builtin or a
+	/// custom command. Everything else is ignored without a reply.
+	pub fn has_command(&self, message: &str) -> bool {
+		let Some((name, _)) = self.strip_prefix(message) else {
+			return false;
+		};
+
+		let name = name.to_lowercase();
+		match builtin_command(&name) {
+			Some(command) => self.is_enabled(command),
+			None => self.custom_commands.contains_key(&name),
+		}
+	}
+
+	pub fn apply(&mut self, update: SettingsUpdate) -> String {
+		match update {
+			SettingsUpdate::Prefix(prefix) => {
+				let reply = format!("Prefix changed to `{prefix}`.");
+				self.prefix = prefix;
+				reply
+			}
+			SettingsUpdate::TagUser(tag_user) => {
+				self.tag_user = tag_user;
+				format!("Replies will {}tag users.", if tag_user { "" } else { "no longer " })
+			}
+			SettingsUpdate::Disable(command) => {
+				self.disabled.insert(command.to_owned());
+				format!("Disabled `{command}`.")
+			}
+			SettingsUpdate::Enable(command) => {
+				self.disabled.remove(command);
+				format!("Enabled `{command}`.")
+			}
+			SettingsUpdate::SetCommand { name, response } => {
+				let reply = format!("Saved `{}{name}`.", self.prefix);
+				self.custom_commands
+					.insert(name, response);
+				reply
+			}
+			SettingsUpdate::RemoveCommand { name } => {
+				if self
+					.custom_commands
+					.remove(&name)
+					.is_some()
+				{
+					format!("Removed `{}{name}`.", self.prefix)
+				} else {
+					format!("`{}{name}` does not exist.", self.prefix)
+				}
+			}
+		}
+	}
+}
+
+/// A change requested with `!schnose set ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsUpdate {
+	/// `!schnose set prefix ?`
+	Prefix(String),
+	/// `!schnose set tag on|off`
+	TagUser(bool),
+	/// `!schnose set disable wr`
+	Disable(&'static str),
+	/// `!schnose set enable wr`
+	Enable(&'static str),
+	/// `!schnose set command discord Join my discord! Currently playing {map}.`
+	SetCommand { name: String, response: String },
+	/// `!schnose set command discord`
+	RemoveCommand { name: String },
+}
+
+const USAGE: &str = "Usage: `schnose set prefix <prefix>` / `tag on|off` / `disable <command>` / \
+                     `enable <command>` / `command <name> [response]`";
+
+impl SettingsUpdate {
+	/// Parses everything after `!schnose`.
+	pub fn parse(args: &str) -> Result<Self> {
+		let usage = || Error::Custom(String::from(USAGE));
+
+		let args = args
+			.trim()
+			.strip_prefix("set ")
+			.ok_or_else(usage)?
+			.trim();
+		let (setting, value) = args
+			.split_once(' ')
+			.map(|(setting, value)| (setting, value.trim()))
+			.ok_or_else(usage)?;
+
+		match setting {
+			"prefix" => {
+				if value.contains(char::is_whitespace) || value.chars().count() > 3 {
+					return Err(Error::Custom(String::from(
+						"The prefix has to be 1-3 characters without spaces.",
+					)));
+				}
+
+				Ok(Self::Prefix(value.to_owned()))
+			}
+			"tag" => match value {
+				"on" | "true" | "yes" => Ok(Self::TagUser(true)),
+				"off" | "false" | "no" => Ok(Self::TagUser(false)),
+				_ => Err(Error::IncorrectArgs { expected: String::from("`on` or `off`") }),
+			},
+			"disable" | "enable" => {
+				let command = builtin_command(value).ok_or_else(|| {
+					Error::Custom(format!("`{value}` is not a built-in command."))
+				})?;
+
+				Ok(if setting == "disable" {
+					Self::Disable(command)
+				} else {
+					Self::Enable(command)
+				})
+			}
+			"command" => {
+				let (name, response) = value
+					.split_once(' ')
+					.map_or((value, ""), |(name, response)| (name, response.trim()));
+				let name = name.to_lowercase();
+
+				if name == MANAGEMENT_COMMAND || builtin_command(&name).is_some() {
+					return Err(Error::Custom(format!("`{name}` is a built-in command.")));
+				}
+
+				if response.is_empty() {
+					Ok(Self::RemoveCommand { name })
+				} else {
+					Ok(Self::SetCommand { name, response: response.to_owned() })
+				}
+			}
+			_ => Err(usage()),
+		}
+	}
+}
+
+/// Fills in `{map}`, `{tier}` and `{mode}` with what the streamer is currently playing.
+pub fn render(template: &str, streamer_info: Option<&StreamerInfo>) -> String {
+	let map = streamer_info.and_then(|info| info.map.as_ref());
+	let mode = streamer_info.and_then(|info| info.mode);
+
+	template
+		.replace("{map}", map.map_or("unknown", |map| map.name.as_str()))
+		.replace(
+			"{tier}",
+			&map.map_or_else(|| String::from("?"), |map| (map.tier as u8).to_string()),
+		)
+		.replace("{mode}", &mode.map_or_else(|| String::from("unknown"), |mode| mode.short()))
+}
+
+#[derive(Debug, FromRow)]
+struct SettingsRow {
+	channel_id: String,
+	prefix: String,
+	tag_user: bool,
+	disabled_commands: String,
+}
+
+#[derive(Debug, FromRow)]
+struct CustomCommandRow {
+	channel_id: String,
+	name: String,
+	response: String,
+}
+
+pub async fn create_tables(conn_pool: &Pool<MySql>) -> Result<()> {
+	sqlx::query(&format!(
+		r#"
+		CREATE TABLE IF NOT EXISTS {SETTINGS_TABLE} (
+		    channel_id        VARCHAR(255)  NOT NULL PRIMARY KEY,
+		    prefix            VARCHAR(16)   NOT NULL DEFAULT '!',
+		    tag_user          BOOLEAN       NOT NULL DEFAULT TRUE,
+		    disabled_commands VARCHAR(255)  NOT NULL DEFAULT ''
+		)
+		"#
+	))
+	.execute(conn_pool)
+	.await?;
+
+	sqlx::query(&format!(
+		r#"
+		CREATE TABLE IF NOT EXISTS {CUSTOM_COMMANDS_TABLE} (
+		    channel_id VARCHAR(255)  NOT NULL,
+		    name       VARCHAR(255)  NOT NULL,
+		    response   VARCHAR(500)  NOT NULL,
+		    PRIMARY KEY (channel_id, name)
+		)
+		"#
+	))
+	.execute(conn_pool)
+	.await?;
+
+	Ok(())
+}
+
+/// Settings of every channel that changed anything, keyed by channel ID.
+pub async fn load_all(conn_pool: &Pool<MySql>) -> Result<HashMap<String, ChannelSettings>> {
+	let rows: Vec<SettingsRow> = sqlx::query_as(&format!("SELECT * FROM {SETTINGS_TABLE}"))
+		.fetch_all(conn_pool)
+		.await?;
+
+	let mut settings = rows
+		.into_iter()
+		.map(|row| {
+			let disabled = row
+				.disabled_commands
+				.split(',')
+				.filter(|command| !command.is_empty())
+				.map(String::from)
+				.collect();
+
+			(
+				row.channel_id,
+				ChannelSettings {
+					prefix: row.prefix,
+					tag_user: row.tag_user,
+					disabled,
+					custom_commands: BTreeMap::new(),
+				},
+			)
+		})
+		.collect::<HashMap<_, _>>();
+
+	let commands: Vec<CustomCommandRow> =
+		sqlx::query_as(&format!("SELECT * FROM {CUSTOM_COMMANDS_TABLE}"))
+			.fetch_all(conn_pool)
+			.await?;
+
+	for command in commands {
+		settings
+			.entry(command.channel_id)
+			.or_default()
+			.custom_commands
+			.insert(command.name, command.response);
+	}
+
+	Ok(settings)
+}
+
+/// Replaces everything stored for `channel_id` with `settings`.
+pub async fn save(
+	conn_pool: &Pool<MySql>,
+	channel_id: &str,
+	settings: &ChannelSettings,
+) -> Result<()> {
+	let mut disabled = settings
+		.disabled
+		.iter()
+		.map(String::as_str)
+		.collect::<Vec<_>>();
+	disabled.sort_unstable();
+
+	let mut transaction = conn_pool.begin().await?;
+
+	let mut query = QueryBuilder::<MySql>::new(format!(
+		"INSERT INTO {SETTINGS_TABLE} (channel_id, prefix, tag_user, disabled_commands)"
+	));
+	query
+		.push_values([settings], |mut query, settings| {
+			query
+				.push_bind(channel_id)
+				.push_bind(&settings.prefix)
+				.push_bind(settings.tag_user)
+				.push_bind(disabled.join(","));
+		})
+		.push(
+			r#"
+			ON DUPLICATE KEY UPDATE
+			    prefix = VALUES(prefix),
+			    tag_user = VALUES(tag_user),
+			    disabled_commands = VALUES(disabled_commands)
+			"#,
+		);
+	query
+		.build()
+		.execute(&mut transaction)
+		.await?;
+
+	let mut query = QueryBuilder::<MySql>::new(format!(
+		"DELETE FROM {CUSTOM_COMMANDS_TABLE} WHERE channel_id = "
+	));
+	query.push_bind(channel_id);
+	query
+		.build()
+		.execute(&mut transaction)
+		.await?;
+
+	if !settings.custom_commands.is_empty() {
+		let mut query = QueryBuilder::<MySql>::new(format!(
+			"INSERT INTO {CUSTOM_COMMANDS_TABLE} (channel_id, name, response)"
+		));
+		query.push_values(&settings.custom_commands, |mut query, (name, response)| {
+			query
+				.push_bind(channel_id)
+				.push_bind(name)
+				.push_bind(response);
+		});
+		query
+			.build()
+			.execute(&mut transaction)
+			.await?;
+	}
+
+	transaction.commit().await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use {
+		super::*,
+		crate::db::MapInfo,
+		gokz_rs::{Mode, SteamID, Tier},
+	};
+
+	#[test]
+	fn prefixes() {
+		let mut settings = ChannelSettings::default();
+		assert_eq!(settings.strip_prefix("!wr lionharder skz"), Some(("wr", "lionharder skz")));
+		assert_eq!(settings.strip_prefix("! wr"), None);
+		assert_eq!(settings.strip_prefix("wr"), None);
+
+		settings.apply(SettingsUpdate::parse("set prefix ?").unwrap());
+		assert_eq!(settings.strip_prefix("!wr"), None);
+		assert_eq!(settings.strip_prefix("?wr"), Some(("wr", "")));
+
+		// the management command always works with `!`
+		assert_eq!(settings.management_args("!schnose set tag off"), Some("set tag off"));
+		assert_eq!(settings.management_args("?schnose set tag off"), Some("set tag off"));
+		assert_eq!(settings.management_args("?wr"), None);
+	}
+
+	#[test]
+	fn known_commands() {
+		let mut settings = ChannelSettings::default();
+		assert!(settings.has_command("!WR lionharder"));
+		assert!(settings.has_command("!m"));
+		assert!(!settings.has_command("!discord"));
+		assert!(!settings.has_command("wr"));
+
+		settings.apply(SettingsUpdate::parse("set disable map").unwrap());
+		settings.apply(SettingsUpdate::parse("set command discord join my discord!").unwrap());
+		assert!(!settings.has_command("!m"));
+		assert!(settings.has_command("!discord"));
+	}
+
+	#[test]
+	fn parse_updates() {
+		assert_eq!(SettingsUpdate::parse("set tag off").unwrap(), SettingsUpdate::TagUser(false));
+		assert_eq!(SettingsUpdate::parse("set disable m").unwrap(), SettingsUpdate::Disable("map"));
+		assert_eq!(
+			SettingsUpdate::parse("set command Discord join my discord!").unwrap(),
+			SettingsUpdate::SetCommand {
+				name: String::from("discord"),
+				response: String::from("join my discord!")
+			}
+		);
+		assert_eq!(
+			SettingsUpdate::parse("set command discord").unwrap(),
+			SettingsUpdate::RemoveCommand { name: String::from("discord") }
+		);
+
+		assert!(SettingsUpdate::parse("set prefix !!!!").is_err());
+		assert!(SettingsUpdate::parse("set disable lj").is_err());
+		assert!(SettingsUpdate::parse("set command wr hi").is_err());
+		assert!(SettingsUpdate::parse("set tag maybe").is_err());
+		assert!(SettingsUpdate::parse("set").is_err());
+		assert!(SettingsUpdate::parse("").is_err());
+	}
+
+	#[test]
+	fn render_placeholders() {
+		let template = "Currently playing {map} (T{tier}) in {mode}.";
+		assert_eq!(render(template, None), "Currently playing unknown (T?) in unknown.");
+
+		let streamer_info = StreamerInfo {
+			api_key: String::new(),
+			channel_id: 0,
+			channel_name: String::from("alphakeks"),
+			player_name: String::from("AlphaKeks"),
+			steam_id: SteamID::new("STEAM_1:1:161178172").unwrap(),
+			mode: Some(Mode::SimpleKZ),
+			map: Some(MapInfo {
+				name: String::from("kz_lionharder"),
+				tier: Tier::Death,
+			}),
+		};
+		assert_eq!(
+			render(template, Some(&streamer_info)),
+			"Currently playing kz_lionharder (T7) in SKZ."
+		);
+	}
+}
@@ -0,0 +1,307 @@
+//! Keeps the bot logged in. Twitch's access tokens expire after a few hours, so we validate the
+//! current one on a schedule and refresh it before that happens. New tokens are written to the
+//! `configs` table so a restart picks them up.
+
+use {
+	async_trait::async_trait,
+	chrono::{DateTime, Utc},
+	color_eyre::{eyre::eyre, Result as Eyre},
+	serde::Deserialize,
+	sqlx::{MySql, Pool, QueryBuilder},
+	std::{
+		convert::Infallible,
+		fmt::Display,
+		sync::{Arc, Mutex},
+		time::Duration,
+	},
+	tokio::task::JoinHandle,
+	tracing::{error, info, warn},
+	twitch_irc::login::{
+		LoginCredentials, RefreshingLoginCredentials, TokenStorage, UserAccessToken,
+	},
+};
+
+/// Twitch wants apps to validate their tokens at least once an hour.
+pub const VALIDATE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// How often to make sure we are in every channel we should be in.
+pub const CHANNEL_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Tokens that expire before the next check are refreshed right away.
+const REFRESH_MARGIN: Duration = Duration::from_secs(2 * VALIDATE_INTERVAL.as_secs());
+
+pub type Credentials = RefreshingLoginCredentials<DatabaseTokenStorage>;
+
+/// Keeps the current token in memory and writes new ones to the `configs` table.
+#[derive(Debug, Clone)]
+pub struct DatabaseTokenStorage {
+	conn_pool: Pool<MySql>,
+	config_id: u32,
+	token: Arc<tokio::sync::Mutex<UserAccessToken>>,
+}
+
+impl DatabaseTokenStorage {
+	pub fn new(
+		conn_pool: Pool<MySql>,
+		prod: bool,
+		access_token: String,
+		refresh_token: String,
+	) -> Self {
+		Self {
+			conn_pool,
+			config_id: if prod { 1 } else { 2 },
+			token: Arc::new(tokio::sync::Mutex::new(UserAccessToken {
+				access_token,
+				refresh_token,
+				// We don't know how old the token is until we validate it.
+				created_at: Utc::now(),
+				expires_at: None,
+			})),
+		}
+	}
+
+	async fn set_expiry(&self, expires_at: DateTime<Utc>) {
+		self.token.lock().await.expires_at = Some(expires_at);
+	}
+
+	/// Makes the next call to [`LoginCredentials::get_credentials`] refresh the token.
+	async fn expire(&self) {
+		let mut token = self.token.lock().await;
+		token.expires_at = Some(token.created_at);
+	}
+}
+
+#[async_trait]
+impl TokenStorage for DatabaseTokenStorage {
+	type LoadError = Infallible;
+	type UpdateError = sqlx::Error;
+
+	async fn load_token(&mut self) -> Result<UserAccessToken, Self::LoadError> {
+		Ok(self.token.lock().await.clone())
+	}
+
+	async fn update_token(&mut self, token: &UserAccessToken) -> Result<(), Self::UpdateError> {
+		let mut query = QueryBuilder::<MySql>::new("UPDATE configs SET access_token = ");
+
+		query
+			.push_bind(&token.access_token)
+			.push(" , refresh_token = ")
+			.push_bind(&token.refresh_token)
+			.push(" WHERE id = ")
+			.push_bind(self.config_id);
+
+		query
+			.build()
+			.execute(&self.conn_pool)
+			.await?;
+
+		*self.token.lock().await = token.clone();
+		info!("Stored new access token.");
+
+		Ok(())
+	}
+}
+
+/// How the connection to Twitch is doing. Cloning this is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct Health(Arc<Mutex<HealthInfo>>);
+
+#[derive(Debug, Clone, Default)]
+pub struct HealthInfo {
+	/// When we last logged in successfully.
+	pub connected_since: Option<DateTime<Utc>>,
+	/// Logins after the first one.
+	pub reconnects: u32,
+	pub token_expires_at: Option<DateTime<Utc>>,
+	pub last_refresh: Option<DateTime<Utc>>,
+	/// Set while refreshing the token is failing.
+	pub last_error: Option<String>,
+	pub channels_joined: usize,
+	pub channels_wanted: usize,
+}
+
+impl Health {
+	pub fn get(&self) -> HealthInfo {
+		self.0
+			.lock()
+			.expect("Lock poisoned")
+			.clone()
+	}
+
+	pub fn update(&self, f: impl FnOnce(&mut HealthInfo)) {
+		f(&mut self.0.lock().expect("Lock poisoned"));
+	}
+
+	/// Twitch sends a `GLOBALUSERSTATE` after every successful login.
+	pub fn logged_in(&self) {
+		self.update(|health| {
+			if health.connected_since.is_some() {
+				health.reconnects += 1;
+			}
+
+			health.connected_since = Some(Utc::now());
+		});
+	}
+}
+
+impl Display for HealthInfo {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let now = Utc::now();
+
+		match self.connected_since {
+			Some(since) => write!(f, "connected for {}", fmt_duration(now - since))?,
+			None => f.write_str("not connected yet")?,
+		}
+
+		write!(
+			f,
+			", {} reconnects, {}/{} channels joined",
+			self.reconnects, self.channels_joined, self.channels_wanted
+		)?;
+
+		if let Some(expires_at) = self.token_expires_at {
+			write!(f, ", token expires in {}", fmt_duration(expires_at - now))?;
+		}
+
+		if let Some(why) = &self.last_error {
+			write!(f, ", last error: {why}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// `1h 02m`
+fn fmt_duration(duration: chrono::Duration) -> String {
+	let minutes = duration.num_minutes().max(0);
+	format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+#[derive(Debug)]
+pub struct Supervisor {
+	pub credentials: Credentials,
+	pub storage: DatabaseTokenStorage,
+	pub http_client: gokz_rs::Client,
+	pub health: Health,
+}
+
+impl Supervisor {
+	/// Checks the token every [`VALIDATE_INTERVAL`] and logs how the connection is doing.
+	pub fn spawn(self) -> JoinHandle<()> {
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(VALIDATE_INTERVAL);
+
+			loop {
+				interval.tick().await;
+
+				if let Err(why) = self.check_token().await {
+					error!("Failed to refresh access token: {why:?}");
+					self.health
+						.update(|health| health.last_error = Some(why.to_string()));
+				}
+
+				info!("Connection health: {}", self.health.get());
+			}
+		})
+	}
+
+	/// Validates the current token and refreshes it if Twitch doesn't accept it anymore or it
+	/// would expire before the next check.
+	#[tracing::instrument(skip(self))]
+	pub async fn check_token(&self) -> Eyre<()> {
+		let access_token = self.access_token().await?;
+
+		if let Some(expires_in) = validate(&access_token, &self.http_client).await? {
+			if expires_in > REFRESH_MARGIN {
+				return self.token_valid_for(expires_in).await;
+			}
+		}
+
+		warn!("Current `access_token` is about to expire or not valid anymore. Refreshing...");
+
+		self.storage.expire().await;
+		let access_token = self.access_token().await?;
+
+		let expires_in = validate(&access_token, &self.http_client)
+			.await?
+			.ok_or(eyre!("Twitch rejected the refreshed access token."))?;
+
+		self.health
+			.update(|health| health.last_refresh = Some(Utc::now()));
+
+		self.token_valid_for(expires_in).await
+	}
+
+	/// Refreshes the token first if it's expired.
+	async fn access_token(&self) -> Eyre<String> {
+		self.credentials
+			.get_credentials()
+			.await?
+			.token
+			.ok_or(eyre!("No access token."))
+	}
+
+	async fn token_valid_for(&self, expires_in: Duration) -> Eyre<()> {
+		let expires_at = Utc::now() + chrono::Duration::from_std(expires_in)?;
+
+		self.storage
+			.set_expiry(expires_at)
+			.await;
+		self.health.update(|health| {
+			health.token_expires_at = Some(expires_at);
+			health.last_error = None;
+		});
+
+		Ok(())
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct Validation {
+	expires_in: u64,
+}
+
+/// How much longer the token is valid for, or `None` if Twitch doesn't accept it anymore.
+#[tracing::instrument(skip_all)]
+async fn validate(access_token: &str, client: &gokz_rs::Client) -> Eyre<Option<Duration>> {
+	let response = client
+		.get("https://id.twitch.tv/oauth2/validate")
+		.header("Authorization", format!("OAuth {access_token}"))
+		.send()
+		.await?;
+
+	if response.status().as_u16() == 401 {
+		return Ok(None);
+	}
+
+	let validation = response
+		.error_for_status()?
+		.json::<Validation>()
+		.await?;
+
+	Ok(Some(Duration::from_secs(validation.expires_in)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn health() {
+		let health = Health::default();
+		assert_eq!(health.get().reconnects, 0);
+
+		health.logged_in();
+		health.logged_in();
+		health.update(|health| {
+			health.channels_joined = 2;
+			health.channels_wanted = 3;
+		});
+
+		let info = health.get();
+		assert_eq!(info.reconnects, 1);
+		assert!(info
+			.to_string()
+			.starts_with("connected for 0h 00m, 1 reconnects, 2/3 channels joined"));
+	}
+}